@@ -0,0 +1,323 @@
+use crate::scene::{NodeHandle, Scene};
+use glam::{Quat, Vec3};
+
+/// How values between two keyframes are obtained.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Hold the value of the previous keyframe.
+    Step,
+    /// Linear interpolation, spherical linear for rotations.
+    Linear,
+    /// Hermite spline, every keyframe holds an in tangent, a value and an out tangent.
+    CubicSpline,
+}
+
+/// The node property an animation channel writes to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelProperty {
+    Translation,
+    Rotation,
+    Scale,
+    MorphWeights,
+}
+
+/// The keyframes for a single property of a single node.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    /// The node that is animated.
+    pub node: NodeHandle,
+
+    /// The property of the node that is animated.
+    pub property: ChannelProperty,
+
+    /// How to interpolate between the keyframes.
+    pub interpolation: Interpolation,
+
+    /// Keyframe times in seconds, strictly increasing.
+    pub times: Vec<f32>,
+
+    /// Flattened keyframe values, for cubic splines each keyframe holds the in tangent, value and out tangent.
+    pub values: Vec<f32>,
+
+    /// Number of floats per value; 3 for translation and scale, 4 for rotation, the target count for morph weights.
+    pub components: usize,
+}
+
+impl Channel {
+    /// The elements stored per keyframe.
+    fn elements_per_key(&self) -> usize {
+        match self.interpolation {
+            Interpolation::CubicSpline => 3,
+            _ => 1,
+        }
+    }
+
+    /// Check that the values hold exactly the number of elements the keyframes need.
+    pub fn is_valid(&self) -> bool {
+        self.components > 0
+            && !self.times.is_empty()
+            && self.values.len() == self.times.len() * self.elements_per_key() * self.components
+    }
+
+    /// Retrieve an element of a keyframe, for cubic splines element 0 is the in tangent and 2 the out tangent.
+    fn element(&self, key: usize, element: usize) -> &[f32] {
+        let start = (key * self.elements_per_key() + element) * self.components;
+        &self.values[start..start + self.components]
+    }
+
+    /// Retrieve the value of a keyframe.
+    fn value(&self, key: usize) -> &[f32] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.element(key, 1),
+            _ => self.element(key, 0),
+        }
+    }
+
+    /// The time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or(0.0)
+    }
+
+    /// Evaluate the channel at time `t`, times outside of the keyframes are clamped.
+    pub fn sample(&self, t: f32) -> Vec<f32> {
+        // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#appendix-c-interpolation
+        let last = self.times.len() - 1;
+        if t <= self.times[0] {
+            return self.value(0).to_vec();
+        }
+        if t >= self.times[last] {
+            return self.value(last).to_vec();
+        }
+        // The keyframe before t, t is guaranteed to be between this one and the next.
+        let k = self.times.partition_point(|&time| time <= t) - 1;
+        let dt = self.times[k + 1] - self.times[k];
+        let s = (t - self.times[k]) / dt;
+        let is_rotation = self.property == ChannelProperty::Rotation;
+        match self.interpolation {
+            Interpolation::Step => self.value(k).to_vec(),
+            Interpolation::Linear if is_rotation => {
+                let a = Quat::from_slice(self.value(k));
+                let b = Quat::from_slice(self.value(k + 1));
+                a.slerp(b, s).to_array().to_vec()
+            }
+            Interpolation::Linear => self
+                .value(k)
+                .iter()
+                .zip(self.value(k + 1))
+                .map(|(a, b)| a + (b - a) * s)
+                .collect(),
+            Interpolation::CubicSpline => {
+                let s2 = s * s;
+                let s3 = s2 * s;
+                let v0 = self.element(k, 1);
+                let b0 = self.element(k, 2);
+                let a1 = self.element(k + 1, 0);
+                let v1 = self.element(k + 1, 1);
+                let res: Vec<f32> = (0..self.components)
+                    .map(|i| {
+                        (2.0 * s3 - 3.0 * s2 + 1.0) * v0[i]
+                            + (s3 - 2.0 * s2 + s) * dt * b0[i]
+                            + (-2.0 * s3 + 3.0 * s2) * v1[i]
+                            + (s3 - s2) * dt * a1[i]
+                    })
+                    .collect();
+                if is_rotation {
+                    Quat::from_slice(&res).normalize().to_array().to_vec()
+                } else {
+                    res
+                }
+            }
+        }
+    }
+}
+
+/// A set of channels that are played together.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    /// Name of the animation, if it has one.
+    pub name: Option<String>,
+
+    /// The channels, at most one per property of a node.
+    pub channels: Vec<Channel>,
+}
+
+impl Animation {
+    /// The duration is the last keyframe of all channels.
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .map(|c| c.duration())
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Plays animations on the nodes of a scene.
+#[derive(Clone, Debug)]
+pub struct AnimationPlayer {
+    /// The available animations.
+    pub animations: Vec<Animation>,
+
+    /// The animation currently played.
+    pub active: Option<usize>,
+
+    /// The current time in the active animation, in seconds.
+    pub time: f32,
+
+    /// Multiplier for the time advance, negative values play backwards.
+    pub speed: f32,
+
+    /// Whether time advances.
+    pub playing: bool,
+
+    /// Whether to wrap around at the end of the animation, or to stop.
+    pub looping: bool,
+}
+
+impl AnimationPlayer {
+    /// Create a player, the first animation will be active.
+    pub fn new(animations: Vec<Animation>) -> Self {
+        Self {
+            active: if animations.is_empty() { None } else { Some(0) },
+            animations,
+            time: 0.0,
+            speed: 1.0,
+            playing: true,
+            looping: true,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    /// Jump to a time in the active animation.
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Select the animation to play by index and restart it, none stops animating the nodes.
+    pub fn set_animation(&mut self, index: Option<usize>) {
+        self.active = index.filter(|i| *i < self.animations.len());
+        self.time = 0.0;
+    }
+
+    /// Select the animation to play by name, returns false if there is no such animation.
+    pub fn set_animation_by_name(&mut self, name: &str) -> bool {
+        let index = self
+            .animations
+            .iter()
+            .position(|a| a.name.as_deref() == Some(name));
+        if index.is_some() {
+            self.set_animation(index);
+        }
+        index.is_some()
+    }
+
+    /// The duration of the active animation.
+    pub fn duration(&self) -> f32 {
+        self.active
+            .map(|i| self.animations[i].duration())
+            .unwrap_or(0.0)
+    }
+
+    /// Advance the time by `dt` seconds, scaled by the speed, if the player is playing.
+    pub fn advance(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+        let duration = self.duration();
+        self.time += dt * self.speed;
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else if self.time < 0.0 || self.time > duration {
+            self.time = self.time.clamp(0.0, duration);
+            self.playing = false;
+        }
+    }
+
+    /// Write the animated properties at the current time into the nodes, other properties are left as is.
+    pub fn evaluate(&self, scene: &mut Scene) {
+        let Some(animation) = self.active.map(|i| &self.animations[i]) else {
+            return;
+        };
+        for channel in animation.channels.iter() {
+            let value = channel.sample(self.time);
+            let node = scene.node_mut(channel.node);
+            match channel.property {
+                ChannelProperty::Translation => node.translation = Vec3::from_slice(&value),
+                ChannelProperty::Rotation => node.rotation = Quat::from_slice(&value),
+                ChannelProperty::Scale => node.scale = Vec3::from_slice(&value),
+                ChannelProperty::MorphWeights => node.weights = value,
+            }
+        }
+    }
+
+    /// Advance, evaluate and update the scene in one go, does nothing if there is no active animation.
+    pub fn update(&mut self, dt: f32, scene: &mut Scene) {
+        if self.active.is_none() {
+            return;
+        }
+        self.advance(dt);
+        self.evaluate(scene);
+        scene.update();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn channel(interpolation: Interpolation, values: Vec<f32>) -> Channel {
+        Channel {
+            node: NodeHandle(0),
+            property: ChannelProperty::Translation,
+            interpolation,
+            times: vec![0.0, 2.0],
+            values,
+            components: 1,
+        }
+    }
+
+    #[test]
+    fn test_channel_sample() {
+        let step = channel(Interpolation::Step, vec![1.0, 3.0]);
+        assert!(step.is_valid());
+        assert_eq!(step.sample(-1.0), vec![1.0]);
+        assert_eq!(step.sample(1.0), vec![1.0]);
+        assert_eq!(step.sample(5.0), vec![3.0]);
+
+        let linear = channel(Interpolation::Linear, vec![1.0, 3.0]);
+        assert_eq!(linear.sample(1.0), vec![2.0]);
+
+        // Zero tangents; the midpoint of the hermite spline is the average of the values.
+        let cubic = channel(
+            Interpolation::CubicSpline,
+            vec![0.0, 1.0, 0.0, 0.0, 3.0, 0.0],
+        );
+        assert!(cubic.is_valid());
+        assert_eq!(cubic.sample(1.0), vec![2.0]);
+        assert!(cubic.sample(0.5)[0] < 1.5);
+    }
+}
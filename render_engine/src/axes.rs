@@ -0,0 +1,286 @@
+// Conventions for the up axis and handedness, and conversion between them.
+//
+// glTF is Y-up and right-handed, Blender is Z-up and right-handed. The left-handed conventions here are the right-handed
+// ones with the X axis mirrored. Conversions are signed permutations of the axes, optionally with a uniform scale for
+// unit conversion, so transforms stay decomposable into scale, rotation and translation after converting.
+
+use crate::animation::{Channel, ChannelProperty};
+use crate::lights::Light;
+use crate::scene::{Node, Skin};
+use crate::vertex::mesh::CpuMesh;
+use crate::view::camera::{Camera, Projection};
+use glam::{Mat3, Mat4, Quat, Vec3, Vec4};
+
+/// The axis that points up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum UpAxis {
+    #[default]
+    Y,
+    Z,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Handedness {
+    #[default]
+    Right,
+    Left,
+}
+
+/// A coordinate convention, the default is the one from glTF.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct CoordinateSystem {
+    pub up: UpAxis,
+    pub handedness: Handedness,
+}
+
+impl CoordinateSystem {
+    /// Y-up, right-handed.
+    pub const GLTF: Self = Self::new(UpAxis::Y, Handedness::Right);
+    /// Z-up, right-handed.
+    pub const BLENDER: Self = Self::new(UpAxis::Z, Handedness::Right);
+
+    pub const fn new(up: UpAxis, handedness: Handedness) -> Self {
+        Self { up, handedness }
+    }
+
+    /// The up direction in this convention.
+    pub fn up(&self) -> Vec3 {
+        match self.up {
+            UpAxis::Y => Vec3::Y,
+            UpAxis::Z => Vec3::Z,
+        }
+    }
+
+    /// Maps glTF coordinates into this convention.
+    fn basis(&self) -> Mat3 {
+        let basis = match self.up {
+            UpAxis::Y => Mat3::IDENTITY,
+            // https://docs.blender.org/manual/en/latest/addons/import_export/scene_gltf2.html, -Z forward becomes +Y.
+            UpAxis::Z => Mat3::from_cols(Vec3::X, Vec3::Z, -Vec3::Y),
+        };
+        match self.handedness {
+            Handedness::Right => basis,
+            Handedness::Left => Mat3::from_diagonal(Vec3::new(-1.0, 1.0, 1.0)) * basis,
+        }
+    }
+}
+
+/// Converts positions, directions and transforms from one convention to another.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AxisConversion {
+    /// A signed permutation of the axes.
+    basis: Mat3,
+    /// Uniform scale applied to positions, like 0.001 for files in millimeters.
+    scale: f32,
+}
+
+impl Default for AxisConversion {
+    fn default() -> Self {
+        Self {
+            basis: Mat3::IDENTITY,
+            scale: 1.0,
+        }
+    }
+}
+
+impl AxisConversion {
+    pub fn new(from: CoordinateSystem, to: CoordinateSystem) -> Self {
+        Self {
+            basis: to.basis() * from.basis().transpose(),
+            scale: 1.0,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether the conversion changes handedness, which reverses the winding of triangles.
+    pub fn is_mirror(&self) -> bool {
+        self.basis.determinant() < 0.0
+    }
+
+    /// The conversion as a matrix, applied to positions.
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_mat3(self.basis * self.scale)
+    }
+
+    pub fn point(&self, point: Vec3) -> Vec3 {
+        self.basis * point * self.scale
+    }
+
+    /// Convert a direction, it is not scaled.
+    pub fn direction(&self, direction: Vec3) -> Vec3 {
+        self.basis * direction
+    }
+
+    /// Convert a rotation, this is the rotation around the converted axis.
+    pub fn rotation(&self, rotation: Quat) -> Quat {
+        // Mirroring the axis also reverses the rotation around it.
+        let sign = self.basis.determinant();
+        let axis = self.direction(rotation.xyz()) * sign;
+        Quat::from_xyzw(axis.x, axis.y, axis.z, rotation.w)
+    }
+
+    /// Convert a per axis scale, this only reorders the axes.
+    pub fn axis_scale(&self, scale: Vec3) -> Vec3 {
+        self.basis.abs() * scale
+    }
+
+    /// Convert a transform, such that it maps converted points to converted points.
+    pub fn transform(&self, transform: &Mat4) -> Mat4 {
+        self.matrix() * *transform * self.matrix().inverse()
+    }
+
+    /// Convert the vertices of a mesh, reversing the winding of triangles if the handedness changes.
+    pub fn convert_mesh(&self, mesh: &mut CpuMesh) {
+        if self.is_identity() {
+            return;
+        }
+        for p in mesh.position.iter_mut() {
+            *p = self.point(*p);
+        }
+        if let Some(normal) = mesh.normal.as_mut() {
+            for n in normal.iter_mut() {
+                *n = self.direction((*n).into()).into();
+            }
+        }
+        // The bitangent is the cross product of the normal and tangent, so it flips if the handedness changes.
+        let sign = self.basis.determinant();
+        if let Some(tangents) = mesh.tangents.as_mut() {
+            for t in tangents.iter_mut() {
+                *t = self.direction(t.truncate()).extend(t.w * sign);
+            }
+        }
+        for target in mesh.morph_targets.iter_mut() {
+            for p in target.position.iter_mut() {
+                *p = self.point(*p);
+            }
+            for v in target
+                .normal
+                .iter_mut()
+                .chain(target.tangent.iter_mut())
+                .flatten()
+            {
+                *v = self.direction(*v);
+            }
+        }
+        if self.is_mirror() && mesh.topology == wgpu::PrimitiveTopology::TriangleList {
+            for triangle in mesh.index.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+    }
+
+    /// Convert the local transform of a node, its parent must be converted as well.
+    pub fn convert_node(&self, node: &mut Node) {
+        node.translation = self.point(node.translation);
+        node.rotation = self.rotation(node.rotation);
+        node.scale = self.axis_scale(node.scale);
+    }
+
+    pub fn convert_skin(&self, skin: &mut Skin) {
+        for m in skin.inverse_bind_matrices.iter_mut() {
+            *m = self.transform(m);
+        }
+    }
+
+    /// Convert the keyframes of an animation channel, all properties convert linearly so this also holds for the
+    /// tangents of cubic splines.
+    pub fn convert_channel(&self, channel: &mut Channel) {
+        match channel.property {
+            ChannelProperty::Translation => {
+                for v in channel.values.chunks_exact_mut(3) {
+                    v.copy_from_slice(&self.point(Vec3::from_slice(v)).to_array());
+                }
+            }
+            ChannelProperty::Rotation => {
+                for v in channel.values.chunks_exact_mut(4) {
+                    let q = self.rotation(Quat::from_vec4(Vec4::from_slice(v)));
+                    v.copy_from_slice(&q.to_array());
+                }
+            }
+            ChannelProperty::Scale => {
+                for v in channel.values.chunks_exact_mut(3) {
+                    v.copy_from_slice(&self.axis_scale(Vec3::from_slice(v)).to_array());
+                }
+            }
+            ChannelProperty::MorphWeights => {}
+        }
+    }
+
+    pub fn convert_light(&self, light: &Light) -> Light {
+        Light {
+            position: self.point(light.position.into()).into(),
+            direction: self.direction(light.direction.into()).into(),
+            range: light.range * self.scale,
+            ..*light
+        }
+    }
+
+    pub fn convert_camera(&self, camera: &Camera) -> Camera {
+        Camera {
+            eye: self.point(camera.eye),
+            target: self.point(camera.target),
+            up: self.direction(camera.up),
+            znear: camera.znear * self.scale,
+            zfar: camera.zfar * self.scale,
+            projection: match camera.projection {
+                Projection::Orthographic { ymag } => Projection::Orthographic {
+                    ymag: ymag * self.scale,
+                },
+                projection => projection,
+            },
+            ..*camera
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use glam::{Vec3A, vec3};
+
+    #[test]
+    fn test_axis_conversion() {
+        let to_blender = AxisConversion::new(CoordinateSystem::GLTF, CoordinateSystem::BLENDER);
+        assert_eq!(to_blender.point(Vec3::Y), Vec3::Z);
+        assert_eq!(to_blender.point(-Vec3::Z), Vec3::Y);
+        assert!(!to_blender.is_mirror());
+        let back = AxisConversion::new(CoordinateSystem::BLENDER, CoordinateSystem::GLTF);
+        assert_eq!(
+            back.point(to_blender.point(vec3(1.0, 2.0, 3.0))),
+            vec3(1.0, 2.0, 3.0)
+        );
+
+        // Transforms compose the same way before and after converting.
+        let left = CoordinateSystem::new(UpAxis::Z, Handedness::Left);
+        let conversion = AxisConversion::new(CoordinateSystem::GLTF, left).with_scale(0.5);
+        assert!(conversion.is_mirror());
+        let mut node = Node {
+            translation: vec3(1.0, 2.0, 3.0),
+            rotation: Quat::from_axis_angle(vec3(1.0, 2.0, 0.5).normalize(), 0.7),
+            scale: vec3(1.0, 2.0, 3.0),
+            ..Default::default()
+        };
+        let p = vec3(0.3, -0.2, 0.9);
+        let expected = conversion.point(node.local_transform().transform_point3(p));
+        conversion.convert_node(&mut node);
+        let converted = node.local_transform().transform_point3(conversion.point(p));
+        assert!(converted.abs_diff_eq(expected, 1e-5));
+
+        // Triangles keep facing the same way, relative to their normals.
+        let mut mesh = CpuMesh::new(vec![Vec3::ZERO, Vec3::X, Vec3::Y], vec![0, 1, 2]);
+        mesh.calculate_normals();
+        conversion.convert_mesh(&mut mesh);
+        let normal = mesh.normal.as_ref().unwrap()[0];
+        mesh.calculate_normals();
+        assert!(normal.abs_diff_eq(mesh.normal.as_ref().unwrap()[0], 1e-6));
+        assert_eq!(normal, Vec3A::from(conversion.direction(Vec3::Z)));
+    }
+}
@@ -19,9 +19,13 @@ impl simple_start::Drawable for LocalState {
     fn initialise(&mut self, state: &mut State) -> Result<(), anyhow::Error> {
         state.camera.camera.eye = vec3(-2.657022, 0.9352254, 1.5044956);
 
-        let gltf_path = std::path::PathBuf::from("../../assets/DamagedHelmet.glb");
+        // A gltf or OBJ file can be passed as the first argument.
+        let gltf_path = std::env::args()
+            .nth(1)
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| std::path::PathBuf::from("../../assets/DamagedHelmet.glb"));
         let mesh_objects_textured =
-            simple_start::loader::load_gltf_objects(&state.context, &gltf_path)?;
+            simple_start::loader::load_objects(&state.context, &gltf_path)?.objects;
 
         pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
@@ -143,11 +147,11 @@ impl simple_start::Drawable for LocalState {
             };
             let mut render_pass = encoder.begin_render_pass(&render_pass_desc);
             // Setup
-            render_pass.set_pipeline(&material.render_pipeline);
             // println!("camera: { :?}", state.camera);
             state
                 .camera
                 .to_camera_uniform()
+                .with_viewport_size(width, height)
                 .add_commands(device, &mut render_pass);
             render_pass.set_bind_group(
                 simple_start::lights::CpuLights::LIGHT_SET,
@@ -156,9 +160,11 @@ impl simple_start::Drawable for LocalState {
             );
 
             // Object properties.
-            for obj in persistent.mesh_objects_textured.iter() {
-                obj.add_commands(&mut render_pass);
-            }
+            material.add_commands(
+                &mut render_pass,
+                state.camera.camera.eye,
+                &persistent.mesh_objects_textured,
+            );
         }
 
         state.context.queue.submit(Some(encoder.finish()));
@@ -21,11 +21,117 @@ use simple_start::{State, fragment::mesh_object_textured::MeshObjectTextured, vi
 
 */
 
+use simple_start::fragment::{PBRMaterial, PBRMaterialConfig};
 use simple_start::vertex::mesh_object::MeshObject;
+use simple_start::watch::FileWatcher;
 struct PersistentState {
-    mesh_objects_textured: Vec<MeshObjectTextured>,
+    /// The scene from the gltf file, which owns the objects.
+    scene: simple_start::scene::Scene,
+    /// Lights that came from the gltf file.
+    gltf_lights: Vec<simple_start::lights::Light>,
+    /// Plays the animations from the gltf file.
+    animation: simple_start::animation::AnimationPlayer,
+    /// Time of the previous frame, to advance the animation.
+    last_frame_time: f64,
     depth_format: wgpu::TextureFormat,
-    material: Option<simple_start::fragment::PBRMaterial>,
+    material: Option<PBRMaterial>,
+    /// The file while it streams in, until then we render what we have.
+    loading: Option<simple_start::loader::background::BackgroundLoad>,
+    /// Set through the RENDER_ENGINE_HOT_RELOAD environment variable.
+    hot_reload: Option<HotReload>,
+}
+
+/// Reloads the file and the shaders when they change on disk.
+struct HotReload {
+    path: std::path::PathBuf,
+    file: FileWatcher,
+    shaders: FileWatcher,
+    /// Whether the file was reloaded, the camera is then left where the user put it.
+    reloaded: bool,
+}
+impl HotReload {
+    fn new(path: &std::path::Path) -> Self {
+        let shader_paths: Vec<_> = MeshObject::wgsl_files()
+            .paths
+            .into_iter()
+            .chain(PBRMaterial::wgsl_files().paths)
+            .collect();
+        Self {
+            path: path.to_owned(),
+            file: FileWatcher::new([path]),
+            shaders: FileWatcher::new(shader_paths),
+            reloaded: false,
+        }
+    }
+}
+
+/// Build the material from the shaders in the source tree.
+fn load_material_from_disk(
+    context: &simple_start::context::Context,
+    config: &PBRMaterialConfig,
+) -> Result<PBRMaterial, simple_start::wgpu_util::ShaderError> {
+    let vertex_source =
+        MeshObject::shader_from_wgsl_files(&context.device, &MeshObject::wgsl_files())?;
+    PBRMaterial::from_wgsl_files(context, config, &vertex_source, &PBRMaterial::wgsl_files())
+}
+
+impl PersistentState {
+    fn set_contents(&mut self, state: &mut State, contents: simple_start::loader::GltfContents) {
+        let reloaded = self.hot_reload.as_ref().is_some_and(|h| h.reloaded);
+        if let Some(camera) = contents.cameras.first()
+            && !reloaded
+        {
+            state.set_camera(camera);
+        }
+        self.scene = contents.scene;
+        self.gltf_lights = contents.lights.lights;
+        self.animation = contents.animation;
+    }
+
+    /// Start loading the file again if it changed, we keep rendering the current contents until it is loaded.
+    fn poll_hot_reload(&mut self) {
+        let Some(hot_reload) = self.hot_reload.as_mut() else {
+            return;
+        };
+        if hot_reload.file.changed() {
+            info!("Reloading {:?}", hot_reload.path);
+            hot_reload.reloaded = true;
+            // This cancels the previous load if that is still running.
+            self.loading = Some(simple_start::loader::background::BackgroundLoad::new(
+                &hot_reload.path,
+                &Default::default(),
+            ));
+        }
+    }
+
+    /// Upload the next part of the file that is loading, and swap in its contents once it is done.
+    fn poll_loading(&mut self, state: &mut State) {
+        let Some(loading) = self.loading.as_mut() else {
+            return;
+        };
+        let title = match loading.poll(&state.context) {
+            None => {
+                let progress = loading.progress();
+                format!(
+                    "Loading {:?}, {:.0}%",
+                    loading.path(),
+                    progress.fraction() * 100.0
+                )
+            }
+            Some(result) => {
+                let path = loading.path().display().to_string();
+                self.loading = None;
+                match result {
+                    Ok(contents) => self.set_contents(state, contents),
+                    Err(e) => error!("Failed to load {path}: {e}"),
+                }
+                path
+            }
+        };
+        if let Some(window) = state.window.as_ref() {
+            window.set_title(&title);
+        }
+    }
 }
 struct LocalState {
     persistent: Option<PersistentState>,
@@ -52,8 +158,36 @@ impl simple_start::Drawable for LocalState {
 
         // let gltf_path = std::path::PathBuf::from("../../assets/mailbox_self/mailbox.glb"); // With a texture!
 
-        let mut mesh_objects_textured =
-            simple_start::loader::load_gltf_objects(&state.context, &gltf_path)?;
+        // A gltf or OBJ file passed as the first argument takes precedence.
+        let gltf_path = std::env::args()
+            .nth(1)
+            .map(std::path::PathBuf::from)
+            .unwrap_or(gltf_path);
+        pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float; // 1.
+        let mut persistent = PersistentState {
+            scene: simple_start::scene::Scene::new(vec![]),
+            gltf_lights: vec![],
+            animation: simple_start::animation::AnimationPlayer::new(vec![]),
+            last_frame_time: simple_start::get_current_time_f64(),
+            material: None,
+            depth_format: DEPTH_FORMAT,
+            loading: None,
+            hot_reload: None,
+        };
+        // With a window the file loads in the background while we keep rendering, a single frame to a file needs
+        // all of it up front.
+        if state.window.is_some() {
+            persistent.loading = Some(simple_start::loader::background::BackgroundLoad::new(
+                &gltf_path,
+                &Default::default(),
+            ));
+            if std::env::var_os("RENDER_ENGINE_HOT_RELOAD").is_some() {
+                persistent.hot_reload = Some(HotReload::new(&gltf_path));
+            }
+        } else {
+            let gltf_contents = simple_start::loader::load_contents(&state.context, &gltf_path)?;
+            persistent.set_contents(state, gltf_contents);
+        }
 
         /*
         let (document, buffers, images) = gltf::import(gltf_path)?;
@@ -97,10 +231,9 @@ impl simple_start::Drawable for LocalState {
         mesh_object.replace_gpu_data();
         */
 
-        pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float; // 1.
         // mesh_objects_textured.clear();
         if false {
-            mesh_objects_textured.push(MeshObjectTextured::new(
+            persistent.scene.objects.push(MeshObjectTextured::new(
                 state.context.clone(),
                 MeshObject::new(
                     state.context.clone(),
@@ -115,11 +248,7 @@ impl simple_start::Drawable for LocalState {
         // let mesh_object_textured =
         //     MeshObjectTextured::new_simple(state.context.clone(), mesh_object.clone(), &textures);
 
-        self.persistent = Some(PersistentState {
-            mesh_objects_textured,
-            material: None,
-            depth_format: DEPTH_FORMAT,
-        });
+        self.persistent = Some(persistent);
 
         Ok(())
     }
@@ -131,8 +260,15 @@ impl simple_start::Drawable for LocalState {
             return Err(wgpu::SurfaceError::Lost.into());
         }
 
-        let device = &state.context.device;
         let persistent = self.persistent.as_mut().unwrap();
+        persistent.poll_hot_reload();
+        persistent.poll_loading(state);
+        let device = &state.context.device;
+
+        let now = simple_start::get_current_time_f64();
+        let dt = (now - persistent.last_frame_time) as f32;
+        persistent.last_frame_time = now;
+        persistent.animation.update(dt, &mut persistent.scene);
 
         let l1_theta = simple_start::get_angle_f32(1.2);
         let l2_theta = -simple_start::get_angle_f32(0.7) + 3.14;
@@ -178,6 +314,7 @@ impl simple_start::Drawable for LocalState {
                 )
                 .with_intensity(1.0),
         ]);
+        let lights = lights.with_lights(&persistent.gltf_lights);
 
         let gpu_lights = lights.to_gpu();
 
@@ -215,14 +352,25 @@ impl simple_start::Drawable for LocalState {
         // pub const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb; // 1.
         // pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float; // 1.
 
+        let config = PBRMaterialConfig {
+            rgba_format: texture_format,
+            depth_format: persistent.depth_format,
+        };
+        // When hot reloading the shaders are read from disk, if they fail to build we keep what we had.
+        let reload_shaders = match persistent.hot_reload.as_mut() {
+            Some(hot_reload) => hot_reload.shaders.changed() || persistent.material.is_none(),
+            None => false,
+        };
+        if reload_shaders {
+            match load_material_from_disk(&state.context, &config) {
+                Ok(material) => persistent.material = Some(material),
+                Err(e) => error!("Failed to build the shaders: {e}"),
+            }
+        }
         let material = persistent.material.get_or_insert_with(|| {
-            let config = simple_start::fragment::PBRMaterialConfig {
-                rgba_format: texture_format,
-                depth_format: persistent.depth_format,
-            };
             info!("Setting up pipeline with {texture_format:?}");
 
-            simple_start::fragment::PBRMaterial::new(
+            PBRMaterial::new(
                 &state.context,
                 &config,
                 simple_start::vertex::mesh_object::MeshObject::retrieve_embedded_shader(
@@ -268,11 +416,11 @@ impl simple_start::Drawable for LocalState {
             };
             let mut render_pass = encoder.begin_render_pass(&render_pass_desc);
             // Setup
-            render_pass.set_pipeline(&material.render_pipeline);
             // println!("camera: { :?}", state.camera);
             state
                 .camera
                 .to_camera_uniform()
+                .with_viewport_size(width, height)
                 .add_commands(device, &mut render_pass);
             // .render_pass
             // .set_bind_group(0, &camera_bind_group, &[]);
@@ -284,9 +432,11 @@ impl simple_start::Drawable for LocalState {
 
             // Object properties.
             // persistent.mesh_object.add_commands(&mut render_pass);
-            for obj in persistent.mesh_objects_textured.iter() {
-                obj.add_commands(&mut render_pass);
-            }
+            material.add_commands(
+                &mut render_pass,
+                state.camera.camera.eye,
+                &persistent.scene.objects,
+            );
             // render_pass.set_bind_group(2, &persistent.gpu_mesh.bind_group, &[]);
             // render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
             // render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
@@ -27,10 +27,22 @@ fn get_necessary_features() -> wgpu::Features {
     | wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY // such that we can bind less than the declared count.
 }
 
+/// Features that are enabled when the adapter has them; compressed textures are uploaded as is if supported.
+fn get_optional_features() -> wgpu::Features {
+    wgpu::Features::TEXTURE_COMPRESSION_BC
+        | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+        | wgpu::Features::TEXTURE_COMPRESSION_ASTC
+}
+
 fn get_necessary_limits() -> wgpu::Limits {
     wgpu::Limits {
         max_binding_array_elements_per_shader_stage: 1024,
         max_binding_array_sampler_elements_per_shader_stage: 1024,
+        // The mesh object binds every vertex attribute and the skinning data as a storage buffer, thirteen in the
+        // vertex stage. Native adapters support that, WebGPU only guarantees eight and so does not run this renderer;
+        // neither does it support the texture binding arrays above.
+        #[cfg(not(target_arch = "wasm32"))]
+        max_storage_buffers_per_shader_stage: 16,
         ..Default::default()
     }
 }
@@ -53,7 +65,8 @@ impl Context {
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: get_necessary_features(),
+                required_features: get_necessary_features()
+                    | (adapter.features() & get_optional_features()),
                 experimental_features: unsafe { wgpu::ExperimentalFeatures::enabled() },
 
                 required_limits: get_necessary_limits(),
@@ -105,7 +118,8 @@ impl Context {
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: get_necessary_features(),
+                required_features: get_necessary_features()
+                    | (adapter.features() & get_optional_features()),
                 experimental_features: unsafe { wgpu::ExperimentalFeatures::enabled() },
                 // we're building for the web we'll have to disable some.
                 required_limits: get_necessary_limits(),
@@ -147,4 +161,9 @@ impl Context {
             target: Target::new_surface(context.clone(), surface, window, config),
         })
     }
+
+    /// Whether the device has the features to sample textures of this format.
+    pub fn supports_texture_format(&self, format: wgpu::TextureFormat) -> bool {
+        self.device.features().contains(format.required_features())
+    }
 }
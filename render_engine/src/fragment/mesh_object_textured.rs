@@ -1,6 +1,6 @@
 use crate::{
     context::Context,
-    texture::{CpuTextureInfo, GpuTextureInfo, SampledTexture},
+    texture::{AlphaMode, CpuTextureInfo, GpuTextureInfo, MaterialUniform, SampledTexture},
     vertex::mesh_object::MeshObject,
 };
 
@@ -48,6 +48,8 @@ impl MeshObjectTextured {
                     sampler,
                     texture,
                     texture_type: crate::texture::TextureType::BaseColor,
+                    tex_coord: 0,
+                    transform: Default::default(),
                 }
             })
             .collect();
@@ -71,14 +73,25 @@ impl MeshObjectTextured {
         context: Context,
         mesh_object: MeshObject,
         sampled_textures: &[SampledTexture],
+    ) -> Self {
+        Self::new_with_material(context, mesh_object, sampled_textures, Default::default())
+    }
+
+    /// Create the object with the material factors, the texture bind group is only built once.
+    pub fn new_with_material(
+        context: Context,
+        mesh_object: MeshObject,
+        sampled_textures: &[SampledTexture],
+        material: MaterialUniform,
     ) -> Self {
         let device = &context.device;
 
         let cpu_textures = CpuTextureInfo::new(
             device,
             &format!("{}", mesh_object.gpu_mesh.name),
-            &sampled_textures,
-        );
+            sampled_textures,
+        )
+        .with_material(material);
         let gpu_textures = cpu_textures.to_gpu();
 
         let mut res = Self {
@@ -87,9 +100,24 @@ impl MeshObjectTextured {
             cpu_textures,
             gpu_textures,
         };
-        res.replace_gpu_data();
+        res.mesh_object.replace_gpu_data();
         res
     }
+
+    /// Whether this object must be drawn with the alpha blending pipeline.
+    pub fn is_blended(&self) -> bool {
+        self.cpu_textures.material.alpha_mode == AlphaMode::Blend
+    }
+
+    /// The pipeline variant this object must be drawn with.
+    pub fn pipeline_variant(&self) -> super::PBRPipelineVariant {
+        super::PBRPipelineVariant {
+            alpha_blend: self.is_blended(),
+            double_sided: self.cpu_textures.material.double_sided != 0,
+            topology: self.mesh_object.gpu_mesh.topology,
+        }
+    }
+
     pub fn replace_gpu_data(&mut self) {
         self.mesh_object.replace_gpu_data();
         self.gpu_textures = self.cpu_textures.to_gpu();
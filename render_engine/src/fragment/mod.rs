@@ -111,7 +111,7 @@ impl Renderer {
     }
 }*/
 
-use crate::wgpu_util::StaticWgslStack;
+use crate::wgpu_util::{ShaderError, StaticWgslStack, WgslFileStack};
 
 pub const MESH_OBJECT_WGSL: StaticWgslStack = StaticWgslStack {
     name: "phong_thing",
@@ -129,9 +129,55 @@ pub struct PBRMaterialConfig {
     pub depth_format: wgpu::TextureFormat,
 }
 
+/// The properties of an object that require a different pipeline.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Default)]
+pub struct PBRPipelineVariant {
+    /// Alpha blended, this does not write depth.
+    pub alpha_blend: bool,
+    /// Double sided, so back faces are not culled.
+    pub double_sided: bool,
+    /// The primitive topology of the mesh.
+    pub topology: wgpu::PrimitiveTopology,
+}
+
+impl PBRPipelineVariant {
+    /// The topologies a mesh can have, strips, fans and loops are converted to lists when loading.
+    pub const TOPOLOGIES: [wgpu::PrimitiveTopology; 3] = [
+        wgpu::PrimitiveTopology::TriangleList,
+        wgpu::PrimitiveTopology::LineList,
+        wgpu::PrimitiveTopology::PointList,
+    ];
+
+    /// All the variants, in the order they should be drawn; opaque before blended.
+    pub const ALL: [PBRPipelineVariant; 4 * Self::TOPOLOGIES.len()] = {
+        let mut variants = [PBRPipelineVariant {
+            alpha_blend: false,
+            double_sided: false,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+        }; 4 * Self::TOPOLOGIES.len()];
+        let mut i = 0;
+        while i < variants.len() {
+            let topologies = Self::TOPOLOGIES.len();
+            variants[i] = PBRPipelineVariant {
+                alpha_blend: i / (2 * topologies) == 1,
+                double_sided: (i / topologies) % 2 == 1,
+                topology: Self::TOPOLOGIES[i % topologies],
+            };
+            i += 1;
+        }
+        variants
+    };
+
+    /// All the variants, see [`Self::ALL`].
+    pub fn all() -> &'static [PBRPipelineVariant] {
+        &Self::ALL
+    }
+}
+
 /// A phong-shading like material. Not quite... because I made a mess.
 pub struct PBRMaterial {
-    pub render_pipeline: wgpu::RenderPipeline,
+    /// The pipelines for each variant.
+    pub render_pipelines: std::collections::HashMap<PBRPipelineVariant, wgpu::RenderPipeline>,
 }
 
 impl PBRMaterial {
@@ -140,8 +186,107 @@ impl PBRMaterial {
         config: &PBRMaterialConfig,
         vertex_source: crate::vertex::VertexCreaterShader,
     ) -> Self {
-        let render_pipeline = Self::generate_pipeline(context, config, vertex_source);
-        PBRMaterial { render_pipeline }
+        let fragment_shader = Self::retrieve_embedded_shader(&context.device);
+        Self::with_fragment_shader(context, config, &vertex_source, &fragment_shader)
+    }
+
+    /// The sources of the embedded shader in the source tree, to edit the shader while the application runs.
+    pub fn wgsl_files() -> WgslFileStack {
+        WgslFileStack::new(
+            MESH_OBJECT_WGSL.name,
+            MESH_OBJECT_WGSL.entry,
+            &[
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader_common.wgsl"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/fragment/shader.wgsl"),
+            ],
+        )
+    }
+
+    /// Create the material with the fragment shader read from disk, errors in the shader are returned instead of
+    /// being fatal, such that the previous material can be kept.
+    pub fn from_wgsl_files(
+        context: &crate::Context,
+        config: &PBRMaterialConfig,
+        vertex_source: &crate::vertex::VertexCreaterShader,
+        files: &WgslFileStack,
+    ) -> Result<Self, ShaderError> {
+        let fragment_shader = files.create(&context.device)?;
+        crate::wgpu_util::capture_validation_errors(&context.device, || {
+            Self::with_fragment_shader(context, config, vertex_source, &fragment_shader)
+        })
+        .map_err(|source| ShaderError::Device {
+            name: files.name.clone(),
+            source,
+        })
+    }
+
+    fn with_fragment_shader(
+        context: &crate::Context,
+        config: &PBRMaterialConfig,
+        vertex_source: &crate::vertex::VertexCreaterShader,
+        fragment_shader: &wgpu::ShaderModule,
+    ) -> Self {
+        let render_pipelines = PBRPipelineVariant::all()
+            .iter()
+            .map(|variant| {
+                (
+                    *variant,
+                    Self::generate_pipeline(
+                        context,
+                        config,
+                        vertex_source,
+                        fragment_shader,
+                        variant,
+                    ),
+                )
+            })
+            .collect();
+        PBRMaterial { render_pipelines }
+    }
+
+    /// Retrieve the pipeline for a particular variant.
+    pub fn render_pipeline(&self, variant: &PBRPipelineVariant) -> &wgpu::RenderPipeline {
+        &self.render_pipelines[variant]
+    }
+
+    /// Draw the objects, the opaque and masked ones first, then the alpha blended ones from back to front as seen
+    /// from the camera position.
+    pub fn add_commands(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        camera_position: glam::Vec3,
+        objects: &[mesh_object_textured::MeshObjectTextured],
+    ) {
+        for variant in PBRPipelineVariant::all().iter().filter(|v| !v.alpha_blend) {
+            let mut pipeline_set = false;
+            for obj in objects
+                .iter()
+                .filter(|obj| obj.pipeline_variant() == *variant)
+            {
+                if !pipeline_set {
+                    render_pass.set_pipeline(self.render_pipeline(variant));
+                    pipeline_set = true;
+                }
+                obj.add_commands(render_pass);
+            }
+        }
+
+        // Blending is order dependent, so sort such that the furthest away object is drawn first.
+        let mut blended: Vec<_> = objects
+            .iter()
+            .filter(|obj| obj.is_blended())
+            .map(|obj| (obj.mesh_object.distance_to(camera_position), obj))
+            .collect();
+        blended.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut current_variant = None;
+        for (_distance, obj) in blended {
+            let variant = obj.pipeline_variant();
+            if current_variant != Some(variant) {
+                render_pass.set_pipeline(self.render_pipeline(&variant));
+                current_variant = Some(variant);
+            }
+            obj.add_commands(render_pass);
+        }
     }
 
     fn retrieve_embedded_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
@@ -151,10 +296,13 @@ impl PBRMaterial {
     fn generate_pipeline(
         context: &crate::Context,
         config: &PBRMaterialConfig,
-        vertex_source: crate::vertex::VertexCreaterShader,
+        vertex_source: &crate::vertex::VertexCreaterShader,
+        fragment_shader: &wgpu::ShaderModule,
+        variant: &PBRPipelineVariant,
     ) -> wgpu::RenderPipeline {
+        let alpha_blend = variant.alpha_blend;
+        let is_points = variant.topology == wgpu::PrimitiveTopology::PointList;
         let device = &context.device;
-        let fragment_shader = Self::retrieve_embedded_shader(device);
 
         let mesh_layout =
             device.create_bind_group_layout(&crate::vertex::mesh_object::MeshObject::MESH_LAYOUT);
@@ -185,24 +333,37 @@ impl PBRMaterial {
                 compilation_options: Default::default(),
             },
             fragment: Some(wgpu::FragmentState {
-                module: &fragment_shader,
+                module: fragment_shader,
                 entry_point: Some("main"),
                 targets: &[Some(wgpu::ColorTargetState {
                     format: config.rgba_format,
-                    blend: Some(wgpu::BlendState {
-                        alpha: wgpu::BlendComponent::REPLACE,
-                        color: wgpu::BlendComponent::REPLACE,
+                    blend: Some(if alpha_blend {
+                        wgpu::BlendState::ALPHA_BLENDING
+                    } else {
+                        wgpu::BlendState {
+                            alpha: wgpu::BlendComponent::REPLACE,
+                            color: wgpu::BlendComponent::REPLACE,
+                        }
                     }),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
                 compilation_options: Default::default(),
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
+                // Point meshes are uploaded as quads that the vertex shader sizes in screen space.
+                topology: if is_points {
+                    wgpu::PrimitiveTopology::TriangleList
+                } else {
+                    variant.topology
+                },
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // cull_mode: None,
+                // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_doublesided
+                cull_mode: if variant.double_sided || is_points {
+                    None
+                } else {
+                    Some(wgpu::Face::Back)
+                },
                 // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
                 polygon_mode: wgpu::PolygonMode::Fill,
                 // Requires Features::DEPTH_CLIP_CONTROL
@@ -212,7 +373,8 @@ impl PBRMaterial {
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: config.depth_format,
-                depth_write_enabled: true,
+                // Blended objects are sorted instead, they shouldn't occlude each other.
+                depth_write_enabled: !alpha_blend,
                 depth_compare: wgpu::CompareFunction::Less, // 1.
                 stencil: wgpu::StencilState::default(),     // 2.
                 bias: wgpu::DepthBiasState::default(),
@@ -196,6 +196,20 @@ var texture_sampler : binding_array<sampler>;
 @binding(TEXTURE_UNIFORM_META) @group(TEXTURE_UNIFORM_SET)
 var<storage, read> texture_uniform : array<TextureUniform>;
 
+// And the global factors of the material.
+@binding(TEXTURE_UNIFORM_MATERIAL) @group(TEXTURE_UNIFORM_SET)
+var<storage, read> material_uniform : array<MaterialUniform>;
+
+
+/// Select the uv map a texture is sampled with, https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_textureinfo_texcoord
+/// and apply the texture transform to it.
+fn select_uv(input: CommonVertexOutput, tex_coord: u32, transform: TextureTransform) -> vec2f {
+    var uv = input.uv_pos;
+    if (tex_coord == 1) {
+        uv = input.uv1_pos;
+    }
+    return TextureTransform_apply(transform, uv);
+}
 
 /// If this is true, the mesh is shaded with the shading normal values.
 const DEBUG_OUTPUT_NORMALS: bool = false;
@@ -304,34 +318,50 @@ fn SurfaceLightParameters_calculate(me: ptr<function, SurfaceLightParameters>) -
 }
 
 @fragment
-fn main(input : CommonVertexOutput) -> CommonFragmentOutput
+fn main(input : CommonVertexOutput, @builtin(front_facing) front_facing: bool) -> CommonFragmentOutput
 {
     // double_sided: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_doublesided
-    // MUST have normals swapped
+    // MUST have normals swapped, this is done below where the normal is determined.
 
     var output: CommonFragmentOutput;
     let texture_meta = texture_uniform[0];
+    let material = material_uniform[0];
 
-    let global_color = vec3<f32>(1.0, 1.0, 1.0); // we currently don't have this but it exists in the gltf.
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_basecolorfactor
+    let global_color = material.base_color_factor;
     let vertex_color = input.color;
 
-    var current_color = global_color * vertex_color;
+    // Alpha is carried along separately, it is only used by the mask and blend alpha modes.
+    var current_color = global_color.rgb * vertex_color.rgb;
+    var current_alpha = global_color.a * vertex_color.a;
 
     // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_basecolortexture
     // Base color texture is RGB, encoded with sRGB transfer function... What do we do with that?
     if ( texture_meta.base_color != 0){
-        current_color *= (textureSample(texture[texture_meta.base_color], texture_sampler[texture_meta.base_color], input.uv_pos)).xyz;
+        let base_color_sampled = textureSample(texture[texture_meta.base_color], texture_sampler[texture_meta.base_color], select_uv(input, texture_meta.base_color_tex_coord, texture_meta.base_color_transform));
+        current_color *= base_color_sampled.rgb;
+        current_alpha *= base_color_sampled.a;
     }
 
-    // Should read these two globals, defaults are https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#reference-material-pbrmetallicroughness
-    var metallic_factor = 0.0; // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_metallicfactor
-    var roughness_factor = 1.0; // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_roughnessfactor
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#alpha-coverage
+    // Opaque ignores alpha, mask is either fully opaque or discarded, blend keeps the alpha for the blend pipeline.
+    if (material.alpha_mode == ALPHA_MODE_MASK) {
+        if (current_alpha < material.alpha_cutoff) {
+            discard;
+        }
+        current_alpha = 1.0;
+    } else if (material.alpha_mode == ALPHA_MODE_OPAQUE) {
+        current_alpha = 1.0;
+    }
+
+    // The two globals, defaults are https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#reference-material-pbrmetallicroughness
+    var metallic_factor = material.metallic_factor; // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_metallicfactor
+    var roughness_factor = material.roughness_factor; // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_roughnessfactor
 
     // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_metallicroughnesstexture
     // metalness is from the b channel, rougnness from g, values are linear, red is ignored.
     if (texture_meta.metallic_roughness != 0){
-        metallic_factor = 1.0;
-        let metallic_sampled = (textureSample(texture[texture_meta.metallic_roughness], texture_sampler[texture_meta.metallic_roughness], input.uv_pos));
+        let metallic_sampled = (textureSample(texture[texture_meta.metallic_roughness], texture_sampler[texture_meta.metallic_roughness], select_uv(input, texture_meta.metallic_roughness_tex_coord, texture_meta.metallic_roughness_transform)));
         roughness_factor *= metallic_sampled.g;
         metallic_factor *= metallic_sampled.b;
     }
@@ -341,44 +371,54 @@ fn main(input : CommonVertexOutput) -> CommonFragmentOutput
     // lighting, lower values inidicate no direct lighting.
     var occlusion = 1.0;
     if (texture_meta.occlusion != 0){
-        let occlusion_sample = (textureSample(texture[texture_meta.occlusion], texture_sampler[texture_meta.occlusion], input.uv_pos)).xyz;
-        let occlusion_strength = 1.0;
+        let occlusion_sample = (textureSample(texture[texture_meta.occlusion], texture_sampler[texture_meta.occlusion], select_uv(input, texture_meta.occlusion_tex_coord, texture_meta.occlusion_transform))).xyz;
+        let occlusion_strength = material.occlusion_strength;
         occlusion = (1.0 + occlusion_strength * (occlusion_sample.r - 1.0));
     }
 
     // Global factor.
-    let emissive_factor = 1.0; // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_emissivefactor
+    let emissive_factor = material.emissive_factor; // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_emissivefactor
     // Emissive: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_emissivetexture
     // controls the color and intensity of the light being emitted by the material, rgb components encoded with
     // sRGB, fourth component Alpha must be ignored.
-    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_emissivefactor defaults to 0...
-    // let emissive_factor = vec3f(0.0, 0.0, 0.0); // Lets ignore that for now, this way we assume it is 1.0 if there is
-    // an emissive texture.
-    var emission = vec3f(0.0, 0.0, 0.0);
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_emissivefactor defaults to 0, so without a
+    // factor there is no emission, even if there is a texture.
+    var emission = emissive_factor;
     if (texture_meta.emissive != 0){
-        emission = (textureSample(texture[texture_meta.emissive], texture_sampler[texture_meta.emissive], input.uv_pos)).rgb;
+        emission *= (textureSample(texture[texture_meta.emissive], texture_sampler[texture_meta.emissive], select_uv(input, texture_meta.emissive_tex_coord, texture_meta.emissive_transform))).rgb;
     }
 
     // On normals: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_normaltexture
     // normal vectors use convention +x is right, +y is up, +z is towards the viewer, alpha must be ignored.
     // How do we reconcile this with the vertex normals?
     // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#reference-material-normaltextureinfo
-    let normal_scale = 1.0; // of course, also a global.
+    let normal_scale = material.normal_scale; // of course, also a global.
     // Lets start with the normal from the vertices.
     var normal : vec3f = normalize(input.normal);
+
+    // For double sided materials we may be looking at the back face, in which case the normal and the TBN are reversed.
+    // Single sided materials have their back faces culled, so this never triggers for them.
+    var tangent_w = input.tangent_w;
+    var bitangent_w = input.bitangent_w;
+    var normal_w = input.normal_w;
+    if (material.double_sided != 0 && !front_facing) {
+        normal = -normal;
+        tangent_w = -tangent_w;
+        bitangent_w = -bitangent_w;
+        normal_w = -normal_w;
+    }
     // And then in the schema for the texture info; https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#schema-reference-material-normaltextureinfo
     // scaledNormal =  normalize((<sampled normal texture value> * 2.0 - 1.0) * vec3(<normal scale>, <normal scale>, 1.0))
 
     if (texture_meta.normal != 0){
         // See the section around normal mapping in the big comment above why we are doing this here. It's the mikktspace conversion.
         // after https://github.com/KhronosGroup/glTF-Sample-Renderer/blob/e6b052db89fb2adbaf31da4565a08265c96c2b9f/source/Renderer/shaders/material_info.glsl#L172-L175
-        let normal_sampled = (textureSample(texture[texture_meta.normal], texture_sampler[texture_meta.normal], input.uv_pos)).rgb;
-        let global_normal_scale = 1.0;
-        let normal_scaled = (normal_sampled * 2.0 - vec3f(1.0)) * vec3f(global_normal_scale, global_normal_scale, 1.0);
+        let normal_sampled = (textureSample(texture[texture_meta.normal], texture_sampler[texture_meta.normal], select_uv(input, texture_meta.normal_tex_coord, texture_meta.normal_transform))).rgb;
+        let normal_scaled = (normal_sampled * 2.0 - vec3f(1.0)) * vec3f(normal_scale, normal_scale, 1.0);
         let normal_scaled_normalized = normalize(normal_scaled);
 
         // Finally, use the tangent, bitangent and normal that we created in the vertex schader:
-        normal = normalize(mat3x3f(input.tangent_w, input.bitangent_w, input.normal_w) * normal_scaled_normalized);
+        normal = normalize(mat3x3f(tangent_w, bitangent_w, normal_w) * normal_scaled_normalized);
     }
 
     if DEBUG_OUTPUT_NORMALS {
@@ -438,6 +478,6 @@ fn main(input : CommonVertexOutput) -> CommonFragmentOutput
    	// let corrected_color = color;
     // let corrected_color = srgb_to_linear(tonemap_khronos_pbr_neutral(linear_to_srgb(color)));
     let corrected_color = tonemap_khronos_pbr_neutral(color);
-    output.color = vec4<f32>(corrected_color, 1.0);
+    output.color = vec4<f32>(corrected_color, current_alpha);
     return output;
 }
@@ -47,14 +47,18 @@ pub mod context;
 pub mod target;
 
 // Render components.
+pub mod animation;
+pub mod axes;
 pub mod fragment;
 pub mod lights;
+pub mod scene;
 pub mod texture;
 pub mod vertex;
 pub mod view;
 
 // Helpers
 pub mod loader;
+pub mod watch;
 pub mod wgpu_util;
 
 use context::Context;
@@ -125,6 +129,13 @@ impl State {
         self.camera.update();
     }
 
+    /// Make the provided camera the active one, for example one loaded from a gltf file. The aspect ratio of the
+    /// current viewport is retained, since it is determined by the render target.
+    pub fn set_camera(&mut self, camera: &view::camera::Camera) {
+        let aspect = self.camera.camera.aspect;
+        self.camera.camera = view::camera::Camera { aspect, ..*camera };
+    }
+
     pub fn handle_key(&mut self, key: KeyCode, pressed: bool) -> bool {
         let amount = if pressed { 1.0 } else { 0.0 };
         match key {
@@ -13,6 +13,7 @@ pub enum LightType {
     Directional = 1, // Directional (rays parallel)
     Omni = 2,        // Spherical light (radiates outward in a circle)
     Ambient = 3,     // Just provides ambient illumination
+    Spot = 4,        // Cone shaped light from the position into the direction.
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, IntoBytes, Immutable, Default)]
@@ -23,8 +24,12 @@ pub struct Light {
     pub color: Vec3, // do lights have alpha?
     pub intensity: f32,
     pub light_type: LightType,
-    // something something falloff..?
-    pub _pad1: [u8; 12],
+    /// Distance at which the light intensity reaches zero, zero means infinite range.
+    pub range: f32,
+    /// Angle from the spot direction at which the falloff starts, in radians.
+    pub inner_cone_angle: f32,
+    /// Angle from the spot direction at which the falloff ends, in radians.
+    pub outer_cone_angle: f32,
 }
 
 impl Light {
@@ -46,6 +51,17 @@ impl Light {
             ..Default::default()
         }
     }
+    /// Add a white spot light, the cone angles default to the ones from KHR_lights_punctual.
+    pub fn spot() -> Self {
+        Light {
+            light_type: LightType::Spot,
+            color: vec3(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            inner_cone_angle: 0.0,
+            outer_cone_angle: std::f32::consts::FRAC_PI_4,
+            ..Default::default()
+        }
+    }
     /// Add an ambient light, this adds to the diffuse component regardless of the orientation.
     ///
     /// This is almost never what you want, it is not PBR correct and only applies to non-metallic rough surfaces.
@@ -75,6 +91,17 @@ impl Light {
         self.intensity = intensity;
         self
     }
+    /// Distance at which the light no longer has an effect, zero means infinite range.
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.range = range;
+        self
+    }
+    /// The inner and outer cone angles of a spot light, in radians.
+    pub fn with_cone_angles(mut self, inner_cone_angle: f32, outer_cone_angle: f32) -> Self {
+        self.inner_cone_angle = inner_cone_angle;
+        self.outer_cone_angle = outer_cone_angle;
+        self
+    }
 }
 
 // Things that involve rendering on the graphics card.
@@ -162,7 +189,16 @@ mod test {
     fn test_light_struct_align() {
         let module = naga::front::wgsl::parse_str(include_str!("shader_common.wgsl")).unwrap();
         crate::verify_wgsl_struct_sized!(
-            Light, module, position, direction, color, intensity, light_type
+            Light,
+            module,
+            position,
+            direction,
+            color,
+            intensity,
+            light_type,
+            range,
+            inner_cone_angle,
+            outer_cone_angle
         );
     }
 }
@@ -0,0 +1,317 @@
+// Loading files while the application keeps rendering.
+//
+// The document is imported, and its meshes and images are decoded, on worker threads; tangents are generated there as
+// part of loading the meshes. The gpu is only touched from BackgroundLoad::poll, which is called once per frame and
+// uploads a few of the prepared meshes and textures each time. Once everything is uploaded the scene is built from the
+// structure of the document, which is cheap since all the heavy lifting is done by then. With a cache directory in the
+// options, the workers read the structure and the prepared meshes and textures from there instead.
+//
+// OBJ, STL and PLY files are read and decoded on a worker thread as a whole, and uploaded by a single poll.
+
+use super::mesh_file::{MeshFileContents, read_mesh_file};
+use super::prepare::{Prepared, prepare_gltf};
+use super::structure::GltfStructure;
+use super::{GltfContents, LoadOptions, LoaderError, Uploaded};
+use crate::texture::mipmap::MipmapGenerator;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+/// Sent from the worker threads to the loader.
+enum Message {
+    /// The structure of the document, with the number of meshes and textures that will follow.
+    Structure {
+        structure: Box<GltfStructure>,
+        total: usize,
+    },
+    Prepared(Prepared),
+    /// An OBJ, STL or PLY file, which is uploaded at once.
+    MeshFile(Box<MeshFileContents>),
+    Failed(LoaderError),
+}
+
+/// How far a background load has progressed.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Meshes and textures decoded by the worker threads.
+    pub prepared: usize,
+    /// Meshes and textures uploaded to the gpu.
+    pub uploaded: usize,
+    /// The number of meshes and textures to load, none while the document is still being imported.
+    pub total: Option<usize>,
+}
+
+impl LoadProgress {
+    /// The uploaded fraction, between zero and one.
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            Some(0) => 1.0,
+            Some(total) => self.uploaded as f32 / total as f32,
+            None => 0.0,
+        }
+    }
+}
+
+/// A file that loads on worker threads, poll it every frame to upload its contents and obtain the result.
+///
+/// Dropping it cancels the load, the worker threads stop after the item they are working on. The contents of OBJ, STL
+/// and PLY files are uploaded by a single poll, those of gltf files are spread over several.
+pub struct BackgroundLoad {
+    path: PathBuf,
+    options: LoadOptions,
+    receiver: mpsc::Receiver<Message>,
+    structure: Option<GltfStructure>,
+    /// Prepared items that are waiting for their upload.
+    pending: VecDeque<Prepared>,
+    uploaded: Uploaded,
+    progress: LoadProgress,
+    uploads_per_poll: usize,
+    mipmap_generator: Option<MipmapGenerator>,
+    finished: bool,
+}
+
+impl BackgroundLoad {
+    /// Start loading the file, this returns immediately.
+    pub fn new(path: &Path, options: &LoadOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let (worker_path, worker_options) = (path.to_owned(), options.clone());
+        std::thread::spawn(
+            move || match read_mesh_file(&worker_path, &worker_options) {
+                Some(Ok(contents)) => {
+                    let _ = sender.send(Message::MeshFile(Box::new(contents)));
+                }
+                Some(Err(e)) => {
+                    let _ = sender.send(Message::Failed(e));
+                }
+                None => prepare(&worker_path, &worker_options, &sender),
+            },
+        );
+        Self {
+            path: path.to_owned(),
+            options: options.clone(),
+            receiver,
+            structure: None,
+            pending: Default::default(),
+            uploaded: Default::default(),
+            progress: Default::default(),
+            uploads_per_poll: 4,
+            mipmap_generator: None,
+            finished: false,
+        }
+    }
+
+    /// The number of meshes and textures uploaded by each poll, this bounds the time a poll takes.
+    pub fn with_uploads_per_poll(mut self, uploads_per_poll: usize) -> Self {
+        self.uploads_per_poll = uploads_per_poll.max(1);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress
+    }
+
+    /// Whether the result was returned by [`Self::poll`].
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Upload what the workers prepared since the last poll, this returns the contents once, when all of it is
+    /// uploaded, or the first error.
+    pub fn poll(&mut self, context: &crate::Context) -> Option<Result<GltfContents, LoaderError>> {
+        if self.finished {
+            return None;
+        }
+        let result = self.step(context).transpose();
+        self.finished = result.is_some();
+        result
+    }
+
+    fn step(&mut self, context: &crate::Context) -> Result<Option<GltfContents>, LoaderError> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(Message::Structure { structure, total }) => {
+                    self.structure = Some(*structure);
+                    self.progress.total = Some(total);
+                }
+                Ok(Message::MeshFile(contents)) => {
+                    self.progress = LoadProgress {
+                        prepared: 1,
+                        uploaded: 1,
+                        total: Some(1),
+                    };
+                    return Ok(Some(contents.upload(context)));
+                }
+                Ok(Message::Failed(e)) => return Err(e),
+                Ok(Message::Prepared(prepared)) => {
+                    self.progress.prepared += 1;
+                    self.pending.push_back(prepared);
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    if self.progress.total != Some(self.progress.prepared) {
+                        return Err(LoaderError::Invalid {
+                            path: self.path.clone(),
+                            reason: "the loader thread stopped unexpectedly".to_owned(),
+                        });
+                    }
+                    break;
+                }
+            }
+        }
+        let Some(structure) = self.structure.as_ref() else {
+            return Ok(None);
+        };
+
+        let mipmap_generator = self
+            .mipmap_generator
+            .get_or_insert_with(|| MipmapGenerator::new(&context.device));
+        for _ in 0..self.uploads_per_poll {
+            let Some(prepared) = self.pending.pop_front() else {
+                break;
+            };
+            self.uploaded
+                .upload(context, &structure.textures, prepared, mipmap_generator)?;
+            self.progress.uploaded += 1;
+        }
+
+        if self.progress.total != Some(self.progress.uploaded) {
+            return Ok(None);
+        }
+        super::build_gltf_contents(
+            context,
+            structure,
+            &self.options,
+            std::mem::take(&mut self.uploaded),
+        )
+        .map(Some)
+    }
+}
+
+/// Import the document, or read it from the cache, and prepare everything the scene uses, spread over the available
+/// cores.
+fn prepare(path: &Path, options: &LoadOptions, sender: &mpsc::Sender<Message>) {
+    let prepared = prepare_gltf(
+        path,
+        options,
+        |structure| {
+            let structure_sent = sender.send(Message::Structure {
+                structure: Box::new(structure.clone()),
+                total: structure.jobs().len(),
+            });
+            structure_sent.is_ok()
+        },
+        |result| {
+            let failed = result.is_err();
+            let message = match result {
+                Ok(prepared) => Message::Prepared(prepared),
+                Err(e) => Message::Failed(e),
+            };
+            // The load was dropped if nobody is listening anymore.
+            sender.send(message).is_ok() && !failed
+        },
+    );
+    if let Err(e) = prepared {
+        let _ = sender.send(Message::Failed(e));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loader::export::test::textured_triangle;
+    use crate::loader::{DecodedImage, SampledTextureKey};
+    use crate::texture::TextureType;
+    use glam::{Mat4, Vec3};
+
+    #[test]
+    fn test_prepare_in_background() {
+        let (mut exporter, mesh, mesh_index) = textured_triangle();
+        // Both nodes share the mesh, so it is only prepared once.
+        exporter.add_node(None, &Mat4::from_translation(Vec3::X), Some(mesh_index));
+        let path = std::env::temp_dir().join(format!(
+            "test_prepare_in_background_{}.glb",
+            std::process::id()
+        ));
+        exporter.save(&path).unwrap();
+
+        let load = BackgroundLoad::new(&path, &LoadOptions::default());
+        // The sender is dropped when the workers are done.
+        let messages: Vec<_> = load.receiver.iter().collect();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], Message::Structure { total: 2, .. }));
+        let meshes: Vec<_> = messages
+            .iter()
+            .filter_map(|p| match p {
+                Message::Prepared(Prepared::Mesh(_, mesh)) => Some(mesh),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].index, mesh.index);
+        assert!(meshes[0].tangents.is_some());
+        assert!(messages.iter().any(|p| matches!(
+            p,
+            Message::Prepared(Prepared::Texture(
+                SampledTextureKey {
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    ..
+                },
+                DecodedImage::Rgba8(_)
+            ))
+        )));
+
+        let missing = BackgroundLoad::new(Path::new("/nonexistent.glb"), &Default::default());
+        assert!(matches!(
+            missing.receiver.recv(),
+            Ok(Message::Failed(LoaderError::Import { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_mesh_file_in_background() {
+        let dir = std::env::temp_dir().join(format!(
+            "test_mesh_file_in_background_{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        image.save(dir.join("red.png")).unwrap();
+        std::fs::write(dir.join("red.mtl"), "newmtl red\nmap_Kd red.png\n").unwrap();
+        let path = dir.join("triangles.obj");
+        std::fs::write(
+            &path,
+            "mtllib red.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             usemtl red\ng a\nf 1 2 3\ng b\nf 3 2 1\n",
+        )
+        .unwrap();
+
+        // The file is read and its image decoded by the worker, the image is shared by both groups.
+        let load = BackgroundLoad::new(&path, &LoadOptions::default());
+        let message = load.receiver.recv();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let Ok(Message::MeshFile(contents)) = message else {
+            panic!("expected the contents of the file");
+        };
+        assert_eq!(contents.name.as_deref(), Some("triangles"));
+        assert_eq!(contents.nodes.len(), 2);
+        assert_eq!(contents.images.len(), 1);
+        assert_eq!(contents.images[0].image.dimensions(), (2, 2));
+        assert!(
+            contents
+                .objects
+                .iter()
+                .all(|o| o.textures == vec![(0, TextureType::BaseColor)])
+        );
+
+        let missing = BackgroundLoad::new(&dir.join("missing.stl"), &Default::default());
+        assert!(matches!(
+            missing.receiver.recv(),
+            Ok(Message::Failed(LoaderError::Io { .. }))
+        ));
+    }
+}
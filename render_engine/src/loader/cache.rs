@@ -0,0 +1,1089 @@
+// An on-disk cache of the structure and the prepared meshes and textures of gltf files, loading from it skips
+// importing the document, reading the accessors, generating tangents and decoding and downsampling the images.
+//
+// There is one entry per source file, named after a hash of its path. The header holds a key that hashes the contents
+// of the file and the load options, the payload starts with the uris of the external resources of the file with a hash
+// of their contents. An entry with another key, format version or resource contents is stale. A checksum over the
+// payload detects corrupt entries. Both are rebuilt when loading. All numbers are little endian.
+//
+//   magic, version: u32, key: u64, checksum: u64, payload
+//   payload: resources, structure, count: u32, then per item a tag: u8 and a mesh or a texture
+//   resources: count: u32, then per resource its uri and the hash of its contents
+
+use super::prepare::Prepared;
+use super::structure::{
+    GltfStructure, MaterialTexture, NodeStructure, PrimitiveStructure, TextureStructure,
+};
+use super::{DecodedImage, LoadOptions, PrimitiveKey, SampledTextureKey};
+use crate::animation::{self, ChannelProperty, Interpolation};
+use crate::lights::{Light, LightType};
+use crate::scene::{self, NodeHandle};
+use crate::texture::ktx2::{Ktx2Image, vk_format_to_wgpu, wgpu_to_vk_format};
+use crate::texture::{AlphaMode, MaterialUniform, TextureTransform, TextureType};
+use crate::vertex::mesh::{CpuMesh, MorphTarget};
+use crate::view::camera::{Camera, Projection};
+use glam::{Mat4, Quat, UVec4, Vec2, Vec3, Vec3A, Vec4};
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const MAGIC: &[u8; 8] = b"SSCACHE\0";
+
+/// Bumped whenever the layout changes, or the way meshes and textures are prepared.
+pub const CACHE_VERSION: u32 = 3;
+
+const HEADER_SIZE: usize = MAGIC.len() + 4 + 8 + 8;
+
+/// The topologies by their code in the file.
+const TOPOLOGIES: [wgpu::PrimitiveTopology; 5] = [
+    wgpu::PrimitiveTopology::PointList,
+    wgpu::PrimitiveTopology::LineList,
+    wgpu::PrimitiveTopology::LineStrip,
+    wgpu::PrimitiveTopology::TriangleList,
+    wgpu::PrimitiveTopology::TriangleStrip,
+];
+
+// The enums by their code in the file.
+const ADDRESS_MODES: [wgpu::AddressMode; 3] = [
+    wgpu::AddressMode::ClampToEdge,
+    wgpu::AddressMode::Repeat,
+    wgpu::AddressMode::MirrorRepeat,
+];
+const FILTER_MODES: [wgpu::FilterMode; 2] = [wgpu::FilterMode::Nearest, wgpu::FilterMode::Linear];
+const TEXTURE_TYPES: [TextureType; 6] = [
+    TextureType::None,
+    TextureType::BaseColor,
+    TextureType::MetallicRoughness,
+    TextureType::Occlusion,
+    TextureType::Normal,
+    TextureType::Emissive,
+];
+const ALPHA_MODES: [AlphaMode; 3] = [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend];
+const LIGHT_TYPES: [LightType; 5] = [
+    LightType::Off,
+    LightType::Directional,
+    LightType::Omni,
+    LightType::Ambient,
+    LightType::Spot,
+];
+const CHANNEL_PROPERTIES: [ChannelProperty; 4] = [
+    ChannelProperty::Translation,
+    ChannelProperty::Rotation,
+    ChannelProperty::Scale,
+    ChannelProperty::MorphWeights,
+];
+const INTERPOLATIONS: [Interpolation; 3] = [
+    Interpolation::Step,
+    Interpolation::Linear,
+    Interpolation::CubicSpline,
+];
+
+const TAG_MESH: u8 = 0;
+const TAG_TEXTURE: u8 = 1;
+const TAG_RGBA8: u8 = 0;
+const TAG_LEVELS: u8 = 1;
+
+#[derive(Error, Debug)]
+pub(super) enum CacheError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("the entry is for another version of the file or the loader")]
+    Stale,
+    #[error("the entry is corrupt: {0}")]
+    Corrupt(String),
+}
+
+/// An external resource of a file, with a hash of its contents.
+pub(super) type Resource = (String, u64);
+
+/// The entry of a file in the cache directory.
+pub(super) struct Cache {
+    path: PathBuf,
+    key: u64,
+    /// The directory the uris of the resources are relative to.
+    base: Option<PathBuf>,
+}
+
+impl Cache {
+    /// This reads the file to compute the key, but doesn't parse it.
+    pub fn new(
+        dir: &Path,
+        gltf_path: &Path,
+        options: &LoadOptions,
+    ) -> Result<Self, std::io::Error> {
+        // The algorithm of the default hasher may change between releases of rust, that only invalidates the cache.
+        let mut hasher = std::hash::DefaultHasher::new();
+        hasher.write(&std::fs::read(gltf_path)?);
+        options.scene.hash(&mut hasher);
+        options.coordinate_system.hash(&mut hasher);
+        options.world.hash(&mut hasher);
+        options.unit_scale.to_bits().hash(&mut hasher);
+
+        let mut path_hasher = std::hash::DefaultHasher::new();
+        std::fs::canonicalize(gltf_path)
+            .unwrap_or_else(|_| gltf_path.to_owned())
+            .hash(&mut path_hasher);
+        let stem = gltf_path
+            .file_stem()
+            .and_then(|z| z.to_str())
+            .unwrap_or("entry");
+        Ok(Self {
+            path: dir.join(format!("{stem}-{:016x}.cache", path_hasher.finish())),
+            key: hasher.finish(),
+            base: gltf_path.parent().map(|p| p.to_owned()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hash the current contents of a resource, missing files fail to load regardless.
+    fn resource_hash(&self, uri: &str) -> u64 {
+        let path = match self.base.as_ref() {
+            Some(base) => base.join(uri),
+            None => uri.into(),
+        };
+        let mut hasher = std::hash::DefaultHasher::new();
+        std::fs::read(path).ok().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The external buffers and images of the document, with the hashes of their current contents.
+    pub fn resources(&self, document: &gltf::Document) -> Vec<Resource> {
+        let buffer_uris = document.buffers().filter_map(|b| match b.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+        let image_uris = document.images().filter_map(|i| match i.source() {
+            gltf::image::Source::Uri { uri, .. } => Some(uri),
+            gltf::image::Source::View { .. } => None,
+        });
+        buffer_uris
+            .chain(image_uris)
+            // Data uris are part of the file itself.
+            .filter(|uri| !uri.starts_with("data:"))
+            .map(|uri| (uri.to_owned(), self.resource_hash(uri)))
+            .collect()
+    }
+
+    /// Read the entry, it must hold exactly the meshes and textures its structure uses.
+    pub fn read(&self) -> Result<(GltfStructure, Vec<Prepared>), CacheError> {
+        let bytes = std::fs::read(&self.path)?;
+        let (resources, structure, prepared) = decode(&bytes, self.key)?;
+        if resources
+            .iter()
+            .any(|(uri, hash)| self.resource_hash(uri) != *hash)
+        {
+            return Err(CacheError::Stale);
+        }
+        if !structure.is_consistent() {
+            return Err(CacheError::Corrupt(
+                "the structure refers to missing items".to_owned(),
+            ));
+        }
+        let jobs = structure.jobs();
+        let expected: HashSet<_> = jobs.iter().copied().collect();
+        let found: HashSet<_> = prepared.iter().map(|p| p.job()).collect();
+        if prepared.len() != jobs.len() || found != expected {
+            return Err(CacheError::Corrupt(
+                "the entry holds other meshes or textures".to_owned(),
+            ));
+        }
+        Ok((structure, prepared))
+    }
+
+    /// Write the entry through a temporary file, such that readers never see a partial entry.
+    pub fn write(
+        &self,
+        resources: &[Resource],
+        structure: &GltfStructure,
+        prepared: &[Prepared],
+    ) -> Result<(), CacheError> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temporary = self.path.with_extension("cache.tmp");
+        std::fs::write(&temporary, encode(resources, structure, prepared, self.key))?;
+        std::fs::rename(&temporary, &self.path)?;
+        Ok(())
+    }
+}
+
+fn checksum(payload: &[u8]) -> u64 {
+    let mut hasher = std::hash::DefaultHasher::new();
+    hasher.write(payload);
+    hasher.finish()
+}
+
+fn encode(
+    resources: &[Resource],
+    structure: &GltfStructure,
+    prepared: &[Prepared],
+    key: u64,
+) -> Vec<u8> {
+    let mut payload = Writer::default();
+    payload.u32(resources.len() as u32);
+    for (uri, hash) in resources.iter() {
+        payload.string(uri);
+        payload.u64(*hash);
+    }
+    payload.structure(structure);
+    payload.u32(prepared.len() as u32);
+    for item in prepared.iter() {
+        match item {
+            Prepared::Mesh(key, mesh) => {
+                payload.u8(TAG_MESH);
+                payload.u32(key.mesh_index as u32);
+                payload.u32(key.primitive_index as u32);
+                payload.mesh(mesh);
+            }
+            Prepared::Texture(key, image) => {
+                payload.u8(TAG_TEXTURE);
+                payload.u32(key.texture_index as u32);
+                payload.format(key.format);
+                payload.image(image);
+            }
+        }
+    }
+    let mut writer = Writer(MAGIC.to_vec());
+    writer.u32(CACHE_VERSION);
+    writer.u64(key);
+    writer.u64(checksum(&payload.0));
+    writer.0.extend_from_slice(&payload.0);
+    writer.0
+}
+
+/// The contents of an entry: the resources, the structure and the prepared meshes and textures.
+type Entry = (Vec<Resource>, GltfStructure, Vec<Prepared>);
+
+fn decode(bytes: &[u8], key: u64) -> Result<Entry, CacheError> {
+    if bytes.len() < HEADER_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(CacheError::Corrupt("missing header".to_owned()));
+    }
+    let mut header = Reader(&bytes[MAGIC.len()..HEADER_SIZE]);
+    if header.u32()? != CACHE_VERSION || header.u64()? != key {
+        return Err(CacheError::Stale);
+    }
+    let payload = &bytes[HEADER_SIZE..];
+    if header.u64()? != checksum(payload) {
+        return Err(CacheError::Corrupt("checksum mismatch".to_owned()));
+    }
+    let mut reader = Reader(payload);
+    let mut resources = vec![];
+    for _ in 0..reader.u32()? {
+        resources.push((reader.string()?, reader.u64()?));
+    }
+    let structure = reader.structure()?;
+    let count = reader.u32()?;
+    let mut prepared = vec![];
+    for _ in 0..count {
+        prepared.push(match reader.u8()? {
+            TAG_MESH => {
+                let key = PrimitiveKey {
+                    mesh_index: reader.u32()? as usize,
+                    primitive_index: reader.u32()? as usize,
+                };
+                Prepared::Mesh(key, Box::new(reader.mesh()?))
+            }
+            TAG_TEXTURE => {
+                let key = SampledTextureKey {
+                    texture_index: reader.u32()? as usize,
+                    format: reader.format()?,
+                };
+                Prepared::Texture(key, reader.image()?)
+            }
+            tag => return Err(CacheError::Corrupt(format!("unknown tag {tag}"))),
+        });
+    }
+    if !reader.0.is_empty() {
+        return Err(CacheError::Corrupt("trailing bytes".to_owned()));
+    }
+    Ok((resources, structure, prepared))
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        self.u8(value.is_some() as u8);
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+    /// A count followed by the components.
+    fn vectors<const N: usize>(&mut self, values: impl ExactSizeIterator<Item = [u32; N]>) {
+        self.u64(values.len() as u64);
+        for value in values {
+            for component in value {
+                self.u32(component);
+            }
+        }
+    }
+    fn floats<const N: usize>(&mut self, values: impl ExactSizeIterator<Item = [f32; N]>) {
+        self.vectors(values.map(|v| v.map(f32::to_bits)));
+    }
+    fn format(&mut self, format: wgpu::TextureFormat) {
+        // The formats come from ktx2 files or are rgba8, which all have a vulkan format.
+        self.u32(wgpu_to_vk_format(format).expect("format has a vulkan equivalent"));
+    }
+    fn string(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+    /// A fixed number of floats, without a count.
+    fn f32s<const N: usize>(&mut self, values: [f32; N]) {
+        for value in values {
+            self.u32(value.to_bits());
+        }
+    }
+    fn code<T: PartialEq>(&mut self, table: &[T], value: &T) {
+        let code = table.iter().position(|t| t == value);
+        self.u8(code.expect("value is in the table") as u8);
+    }
+    fn indices(&mut self, indices: &[usize]) {
+        self.vectors(indices.iter().map(|i| [*i as u32]));
+    }
+
+    fn structure(&mut self, structure: &GltfStructure) {
+        self.u32(structure.scene_names.len() as u32);
+        for name in structure.scene_names.iter() {
+            self.option(name.as_ref(), |w, name| w.string(name));
+        }
+        self.u32(structure.scene as u32);
+        self.indices(&structure.roots);
+        self.u32(structure.nodes.len() as u32);
+        for (node, hierarchy) in structure.nodes.iter().zip(structure.hierarchy.iter()) {
+            self.node(node);
+            self.option(hierarchy.mesh, |w, mesh| w.u32(mesh as u32));
+            self.indices(&hierarchy.children);
+        }
+        self.u32(structure.meshes.len() as u32);
+        for primitives in structure.meshes.iter() {
+            self.u32(primitives.len() as u32);
+            for primitive in primitives.iter() {
+                self.material(&primitive.material);
+                self.u32(primitive.textures.len() as u32);
+                for texture in primitive.textures.iter() {
+                    self.material_texture(texture);
+                }
+                self.u8(primitive.has_morph_targets as u8);
+            }
+        }
+        self.u32(structure.textures.len() as u32);
+        for texture in structure.textures.iter() {
+            self.option(texture.name.as_ref(), |w, name| w.string(name));
+            self.code(&ADDRESS_MODES, &texture.address_mode_u);
+            self.code(&ADDRESS_MODES, &texture.address_mode_v);
+            self.code(&FILTER_MODES, &texture.mag_filter);
+            self.code(&FILTER_MODES, &texture.min_filter);
+            self.option(texture.mipmap_filter, |w, filter| {
+                w.code(&FILTER_MODES, &filter)
+            });
+        }
+        self.u32(structure.skins.len() as u32);
+        for skin in structure.skins.iter() {
+            self.vectors(skin.joints.iter().map(|j| [j.0 as u32]));
+            self.floats(skin.inverse_bind_matrices.iter().map(|m| m.to_cols_array()));
+        }
+        self.u32(structure.animations.len() as u32);
+        for animation in structure.animations.iter() {
+            self.animation(animation);
+        }
+        self.u32(structure.lights.len() as u32);
+        for light in structure.lights.iter() {
+            self.light(light);
+        }
+        self.u32(structure.cameras.len() as u32);
+        for camera in structure.cameras.iter() {
+            self.camera(camera);
+        }
+    }
+
+    fn node(&mut self, node: &scene::Node) {
+        self.option(node.name.as_ref(), |w, name| w.string(name));
+        self.option(node.parent, |w, parent| w.u32(parent.0 as u32));
+        self.f32s(node.translation.to_array());
+        self.f32s(node.rotation.to_array());
+        self.f32s(node.scale.to_array());
+        self.floats(node.weights.iter().map(|w| [*w]));
+        self.option(node.extras.as_ref(), |w, extras| w.string(extras));
+        self.option(node.skin, |w, skin| w.u32(skin as u32));
+    }
+
+    fn material(&mut self, material: &MaterialUniform) {
+        self.f32s(material.base_color_factor.to_array());
+        self.f32s(material.emissive_factor.to_array());
+        self.f32s([
+            material.metallic_factor,
+            material.roughness_factor,
+            material.normal_scale,
+            material.occlusion_strength,
+            material.alpha_cutoff,
+        ]);
+        self.code(&ALPHA_MODES, &material.alpha_mode);
+        self.u32(material.double_sided);
+    }
+
+    fn material_texture(&mut self, texture: &MaterialTexture) {
+        self.u32(texture.key.texture_index as u32);
+        self.format(texture.key.format);
+        self.code(&TEXTURE_TYPES, &texture.texture_type);
+        self.u32(texture.tex_coord);
+        self.f32s(texture.transform.offset.to_array());
+        self.f32s(texture.transform.scale.to_array());
+        self.f32s([texture.transform.rotation]);
+    }
+
+    fn animation(&mut self, animation: &animation::Animation) {
+        self.option(animation.name.as_ref(), |w, name| w.string(name));
+        self.u32(animation.channels.len() as u32);
+        for channel in animation.channels.iter() {
+            self.u32(channel.node.0 as u32);
+            self.code(&CHANNEL_PROPERTIES, &channel.property);
+            self.code(&INTERPOLATIONS, &channel.interpolation);
+            self.floats(channel.times.iter().map(|t| [*t]));
+            self.floats(channel.values.iter().map(|v| [*v]));
+            self.u32(channel.components as u32);
+        }
+    }
+
+    fn light(&mut self, light: &Light) {
+        self.f32s(light.position.to_array());
+        self.f32s(light.direction.to_array());
+        self.f32s(light.color.to_array());
+        self.f32s([light.intensity]);
+        self.code(&LIGHT_TYPES, &light.light_type);
+        self.f32s([light.range, light.inner_cone_angle, light.outer_cone_angle]);
+    }
+
+    fn camera(&mut self, camera: &Camera) {
+        self.f32s(camera.eye.to_array());
+        self.f32s(camera.target.to_array());
+        self.f32s(camera.up.to_array());
+        self.f32s([camera.aspect, camera.fovy, camera.znear, camera.zfar]);
+        let ymag = match camera.projection {
+            Projection::Perspective => None,
+            Projection::Orthographic { ymag } => Some(ymag),
+        };
+        self.option(ymag, |w, ymag| w.f32s([ymag]));
+    }
+
+    fn mesh(&mut self, mesh: &CpuMesh) {
+        let topology = TOPOLOGIES.iter().position(|t| *t == mesh.topology);
+        self.u32(topology.expect("topology is supported") as u32);
+        self.option(mesh.name.as_ref(), |w, name| w.bytes(name.as_bytes()));
+        self.floats(mesh.position.iter().map(|v| v.to_array()));
+        self.vectors(mesh.index.iter().map(|i| [*i]));
+        self.option(mesh.color.as_ref(), |w, v| {
+            w.floats(v.iter().map(|v| v.to_array()))
+        });
+        self.option(mesh.normal.as_ref(), |w, v| {
+            w.floats(v.iter().map(|v| v.to_array()))
+        });
+        self.option(mesh.uv.as_ref(), |w, v| {
+            w.floats(v.iter().map(|v| v.to_array()))
+        });
+        self.option(mesh.uv1.as_ref(), |w, v| {
+            w.floats(v.iter().map(|v| v.to_array()))
+        });
+        self.option(mesh.tangents.as_ref(), |w, v| {
+            w.floats(v.iter().map(|v| v.to_array()))
+        });
+        self.option(mesh.joints.as_ref(), |w, v| {
+            w.vectors(v.iter().map(|v| v.to_array()))
+        });
+        self.option(mesh.weights.as_ref(), |w, v| {
+            w.floats(v.iter().map(|v| v.to_array()))
+        });
+        self.u32(mesh.morph_targets.len() as u32);
+        for target in mesh.morph_targets.iter() {
+            self.floats(target.position.iter().map(|v| v.to_array()));
+            self.option(target.normal.as_ref(), |w, v| {
+                w.floats(v.iter().map(|v| v.to_array()))
+            });
+            self.option(target.tangent.as_ref(), |w, v| {
+                w.floats(v.iter().map(|v| v.to_array()))
+            });
+        }
+    }
+
+    fn image(&mut self, image: &DecodedImage) {
+        match image {
+            DecodedImage::Rgba8(image) => {
+                self.u8(TAG_RGBA8);
+                self.rgba8(image);
+            }
+            DecodedImage::Ktx2 { image, fallback } => {
+                self.u8(TAG_LEVELS);
+                self.format(image.format);
+                self.u32(image.width);
+                self.u32(image.height);
+                self.u32(image.levels.len() as u32);
+                for level in image.levels.iter() {
+                    self.bytes(level);
+                }
+                self.option(fallback.as_ref(), |w, fallback| w.rgba8(fallback));
+            }
+        }
+    }
+
+    fn rgba8(&mut self, image: &image::RgbaImage) {
+        self.u32(image.width());
+        self.u32(image.height());
+        self.bytes(image.as_raw());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], CacheError> {
+        if length > self.0.len() {
+            return Err(CacheError::Corrupt(
+                "unexpected end of the entry".to_owned(),
+            ));
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, CacheError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, CacheError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, CacheError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    fn length(&mut self, element_size: usize) -> Result<usize, CacheError> {
+        let length = self.u64()? as usize;
+        // Don't allocate for lengths that can't fit in what is left.
+        match length.checked_mul(element_size) {
+            Some(size) if size <= self.0.len() => Ok(length),
+            _ => Err(CacheError::Corrupt(format!("invalid length {length}"))),
+        }
+    }
+    fn bytes(&mut self) -> Result<Vec<u8>, CacheError> {
+        let length = self.length(1)?;
+        Ok(self.take(length)?.to_vec())
+    }
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, CacheError>,
+    ) -> Result<Option<T>, CacheError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            flag => Err(CacheError::Corrupt(format!("invalid option {flag}"))),
+        }
+    }
+    fn vectors<const N: usize>(&mut self) -> Result<Vec<[u32; N]>, CacheError> {
+        let length = self.length(4 * N)?;
+        Ok(self
+            .take(length * 4 * N)?
+            .chunks_exact(4 * N)
+            .map(|v| {
+                std::array::from_fn(|i| u32::from_le_bytes(v[4 * i..][..4].try_into().unwrap()))
+            })
+            .collect())
+    }
+    fn floats<const N: usize, T>(
+        &mut self,
+        to: impl Fn([f32; N]) -> T,
+    ) -> Result<Vec<T>, CacheError> {
+        Ok(self
+            .vectors::<N>()?
+            .into_iter()
+            .map(|v| to(v.map(f32::from_bits)))
+            .collect())
+    }
+    fn format(&mut self) -> Result<wgpu::TextureFormat, CacheError> {
+        let vk_format = self.u32()?;
+        vk_format_to_wgpu(vk_format)
+            .ok_or_else(|| CacheError::Corrupt(format!("unknown format {vk_format}")))
+    }
+    fn string(&mut self) -> Result<String, CacheError> {
+        String::from_utf8(self.bytes()?)
+            .map_err(|_| CacheError::Corrupt("invalid utf-8 string".to_owned()))
+    }
+    fn f32s<const N: usize>(&mut self) -> Result<[f32; N], CacheError> {
+        let mut values = [0.0; N];
+        for value in values.iter_mut() {
+            *value = f32::from_bits(self.u32()?);
+        }
+        Ok(values)
+    }
+    fn code<T: Copy>(&mut self, table: &[T]) -> Result<T, CacheError> {
+        let code = self.u8()?;
+        table
+            .get(code as usize)
+            .copied()
+            .ok_or_else(|| CacheError::Corrupt(format!("unknown code {code}")))
+    }
+    fn index(&mut self) -> Result<usize, CacheError> {
+        Ok(self.u32()? as usize)
+    }
+    fn indices(&mut self) -> Result<Vec<usize>, CacheError> {
+        Ok(self
+            .vectors::<1>()?
+            .into_iter()
+            .map(|[i]| i as usize)
+            .collect())
+    }
+
+    fn structure(&mut self) -> Result<GltfStructure, CacheError> {
+        let scene_names = (0..self.u32()?)
+            .map(|_| self.option(|r| r.string()))
+            .collect::<Result<_, _>>()?;
+        let scene = self.index()?;
+        let roots = self.indices()?;
+        let mut nodes = vec![];
+        let mut hierarchy = vec![];
+        for _ in 0..self.u32()? {
+            nodes.push(self.node()?);
+            hierarchy.push(NodeStructure {
+                mesh: self.option(|r| r.index())?,
+                children: self.indices()?,
+            });
+        }
+        let mut meshes = vec![];
+        for _ in 0..self.u32()? {
+            let mut primitives = vec![];
+            for _ in 0..self.u32()? {
+                let material = self.material()?;
+                let textures = (0..self.u32()?)
+                    .map(|_| self.material_texture())
+                    .collect::<Result<_, _>>()?;
+                primitives.push(PrimitiveStructure {
+                    material,
+                    textures,
+                    has_morph_targets: self.u8()? != 0,
+                });
+            }
+            meshes.push(primitives);
+        }
+        let mut textures = vec![];
+        for _ in 0..self.u32()? {
+            textures.push(TextureStructure {
+                name: self.option(|r| r.string())?,
+                address_mode_u: self.code(&ADDRESS_MODES)?,
+                address_mode_v: self.code(&ADDRESS_MODES)?,
+                mag_filter: self.code(&FILTER_MODES)?,
+                min_filter: self.code(&FILTER_MODES)?,
+                mipmap_filter: self.option(|r| r.code(&FILTER_MODES))?,
+            });
+        }
+        let mut skins = vec![];
+        for _ in 0..self.u32()? {
+            skins.push(scene::Skin {
+                joints: self.indices()?.into_iter().map(NodeHandle).collect(),
+                inverse_bind_matrices: self.floats(|m: [f32; 16]| Mat4::from_cols_array(&m))?,
+            });
+        }
+        let animations = (0..self.u32()?)
+            .map(|_| self.animation())
+            .collect::<Result<_, _>>()?;
+        let lights = (0..self.u32()?)
+            .map(|_| self.light())
+            .collect::<Result<_, _>>()?;
+        let cameras = (0..self.u32()?)
+            .map(|_| self.camera())
+            .collect::<Result<_, _>>()?;
+        Ok(GltfStructure {
+            scene_names,
+            scene,
+            roots,
+            nodes,
+            hierarchy,
+            meshes,
+            textures,
+            skins,
+            animations,
+            lights,
+            cameras,
+        })
+    }
+
+    fn node(&mut self) -> Result<scene::Node, CacheError> {
+        Ok(scene::Node {
+            name: self.option(|r| r.string())?,
+            parent: self.option(|r| r.index())?.map(NodeHandle),
+            translation: Vec3::from_array(self.f32s()?),
+            rotation: Quat::from_array(self.f32s()?),
+            scale: Vec3::from_array(self.f32s()?),
+            weights: self.floats(|[w]| w)?,
+            extras: self.option(|r| r.string())?,
+            skin: self.option(|r| r.index())?,
+            ..Default::default()
+        })
+    }
+
+    fn material(&mut self) -> Result<MaterialUniform, CacheError> {
+        let base_color_factor = Vec4::from_array(self.f32s()?);
+        let emissive_factor = Vec3::from_array(self.f32s()?);
+        let [
+            metallic_factor,
+            roughness_factor,
+            normal_scale,
+            occlusion_strength,
+            alpha_cutoff,
+        ] = self.f32s()?;
+        Ok(MaterialUniform {
+            base_color_factor,
+            emissive_factor,
+            metallic_factor,
+            roughness_factor,
+            normal_scale,
+            occlusion_strength,
+            alpha_cutoff,
+            alpha_mode: self.code(&ALPHA_MODES)?,
+            double_sided: self.u32()?,
+            ..Default::default()
+        })
+    }
+
+    fn material_texture(&mut self) -> Result<MaterialTexture, CacheError> {
+        let key = SampledTextureKey {
+            texture_index: self.index()?,
+            format: self.format()?,
+        };
+        Ok(MaterialTexture {
+            key,
+            texture_type: self.code(&TEXTURE_TYPES)?,
+            tex_coord: self.u32()?,
+            transform: TextureTransform {
+                offset: Vec2::from_array(self.f32s()?),
+                scale: Vec2::from_array(self.f32s()?),
+                rotation: self.f32s::<1>()?[0],
+                ..Default::default()
+            },
+        })
+    }
+
+    fn animation(&mut self) -> Result<animation::Animation, CacheError> {
+        let name = self.option(|r| r.string())?;
+        let mut channels = vec![];
+        for _ in 0..self.u32()? {
+            channels.push(animation::Channel {
+                node: NodeHandle(self.index()?),
+                property: self.code(&CHANNEL_PROPERTIES)?,
+                interpolation: self.code(&INTERPOLATIONS)?,
+                times: self.floats(|[t]| t)?,
+                values: self.floats(|[v]| v)?,
+                components: self.index()?,
+            });
+        }
+        Ok(animation::Animation { name, channels })
+    }
+
+    fn light(&mut self) -> Result<Light, CacheError> {
+        let position = Vec3A::from_array(self.f32s()?);
+        let direction = Vec3A::from_array(self.f32s()?);
+        let color = Vec3::from_array(self.f32s()?);
+        let [intensity] = self.f32s()?;
+        let light_type = self.code(&LIGHT_TYPES)?;
+        let [range, inner_cone_angle, outer_cone_angle] = self.f32s()?;
+        Ok(Light {
+            position,
+            direction,
+            color,
+            intensity,
+            light_type,
+            range,
+            inner_cone_angle,
+            outer_cone_angle,
+        })
+    }
+
+    fn camera(&mut self) -> Result<Camera, CacheError> {
+        let eye = Vec3::from_array(self.f32s()?);
+        let target = Vec3::from_array(self.f32s()?);
+        let up = Vec3::from_array(self.f32s()?);
+        let [aspect, fovy, znear, zfar] = self.f32s()?;
+        let projection = match self.option(|r| r.f32s::<1>())? {
+            None => Projection::Perspective,
+            Some([ymag]) => Projection::Orthographic { ymag },
+        };
+        Ok(Camera {
+            eye,
+            target,
+            up,
+            aspect,
+            fovy,
+            znear,
+            zfar,
+            projection,
+        })
+    }
+
+    fn mesh(&mut self) -> Result<CpuMesh, CacheError> {
+        let topology = self.u32()?;
+        let topology = *TOPOLOGIES
+            .get(topology as usize)
+            .ok_or_else(|| CacheError::Corrupt(format!("unknown topology {topology}")))?;
+        let name = self
+            .option(|r| r.bytes())?
+            .map(|name| String::from_utf8_lossy(&name).into_owned());
+        let position = self.floats(Vec3::from_array)?;
+        let index = self.vectors::<1>()?.into_iter().map(|[i]| i).collect();
+        let mut mesh = CpuMesh::new(position, index).with_topology(topology);
+        mesh.name = name;
+        mesh.color = self.option(|r| r.floats(Vec4::from_array))?;
+        mesh.normal = self.option(|r| r.floats(Vec3A::from_array))?;
+        mesh.uv = self.option(|r| r.floats(Vec2::from_array))?;
+        mesh.uv1 = self.option(|r| r.floats(Vec2::from_array))?;
+        mesh.tangents = self.option(|r| r.floats(Vec4::from_array))?;
+        mesh.joints = self.option(|r| {
+            Ok(r.vectors::<4>()?
+                .into_iter()
+                .map(UVec4::from_array)
+                .collect())
+        })?;
+        mesh.weights = self.option(|r| r.floats(Vec4::from_array))?;
+        for _ in 0..self.u32()? {
+            mesh.morph_targets.push(MorphTarget {
+                position: self.floats(Vec3::from_array)?,
+                normal: self.option(|r| r.floats(Vec3::from_array))?,
+                tangent: self.option(|r| r.floats(Vec3::from_array))?,
+            });
+        }
+        Ok(mesh)
+    }
+
+    fn image(&mut self) -> Result<DecodedImage, CacheError> {
+        match self.u8()? {
+            TAG_RGBA8 => self.rgba8().map(DecodedImage::Rgba8),
+            TAG_LEVELS => {
+                let format = self.format()?;
+                let (width, height) = (self.u32()?, self.u32()?);
+                let levels = (0..self.u32()?)
+                    .map(|_| self.bytes())
+                    .collect::<Result<_, _>>()?;
+                Ok(DecodedImage::Ktx2 {
+                    image: Ktx2Image {
+                        format,
+                        width,
+                        height,
+                        levels,
+                    },
+                    fallback: self.option(|r| r.rgba8())?,
+                })
+            }
+            tag => Err(CacheError::Corrupt(format!("unknown image tag {tag}"))),
+        }
+    }
+
+    fn rgba8(&mut self) -> Result<image::RgbaImage, CacheError> {
+        let (width, height) = (self.u32()?, self.u32()?);
+        image::RgbaImage::from_raw(width, height, self.bytes()?)
+            .ok_or_else(|| CacheError::Corrupt("image size mismatch".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loader::prepare::Job;
+
+    /// A structure that uses a single mesh with one primitive and texture, with a bit of everything else.
+    fn structure(mesh_key: PrimitiveKey, texture_key: SampledTextureKey) -> GltfStructure {
+        let root = scene::Node {
+            name: Some("root".to_owned()),
+            translation: Vec3::X,
+            weights: vec![0.5],
+            extras: Some("{}".to_owned()),
+            skin: Some(0),
+            ..Default::default()
+        };
+        let child = scene::Node {
+            parent: Some(NodeHandle(0)),
+            ..Default::default()
+        };
+        let mut meshes = vec![vec![]; mesh_key.mesh_index + 1];
+        meshes[mesh_key.mesh_index].push(PrimitiveStructure {
+            material: MaterialUniform {
+                alpha_mode: AlphaMode::Mask,
+                ..Default::default()
+            },
+            textures: vec![MaterialTexture {
+                key: texture_key,
+                texture_type: TextureType::Normal,
+                tex_coord: 1,
+                transform: TextureTransform {
+                    rotation: 1.0,
+                    ..Default::default()
+                },
+            }],
+            has_morph_targets: true,
+        });
+        let texture = TextureStructure {
+            name: None,
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::MirrorRepeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: Some(wgpu::FilterMode::Linear),
+        };
+        GltfStructure {
+            scene_names: vec![None, Some("scene".to_owned())],
+            scene: 1,
+            roots: vec![0],
+            nodes: vec![root, child],
+            hierarchy: vec![
+                NodeStructure {
+                    mesh: None,
+                    children: vec![1],
+                },
+                NodeStructure {
+                    mesh: Some(mesh_key.mesh_index),
+                    children: vec![],
+                },
+            ],
+            meshes,
+            textures: vec![texture; texture_key.texture_index + 1],
+            skins: vec![scene::Skin {
+                joints: vec![NodeHandle(1)],
+                inverse_bind_matrices: vec![Mat4::from_translation(Vec3::Y)],
+            }],
+            animations: vec![animation::Animation {
+                name: Some("wave".to_owned()),
+                channels: vec![animation::Channel {
+                    node: NodeHandle(1),
+                    property: ChannelProperty::Rotation,
+                    interpolation: Interpolation::Linear,
+                    times: vec![0.0, 1.0],
+                    values: vec![0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0],
+                    components: 4,
+                }],
+            }],
+            lights: vec![Light::spot().with_range(2.0)],
+            cameras: vec![Camera {
+                projection: Projection::Orthographic { ymag: 2.0 },
+                ..Camera::new(1, 1)
+            }],
+        }
+    }
+
+    #[test]
+    fn test_cache_entry() {
+        let mut mesh = CpuMesh::new(vec![Vec3::ZERO, Vec3::X, Vec3::Y], vec![0, 1, 2]);
+        mesh.name = Some("triangle".to_owned());
+        mesh.uv = Some(vec![Vec2::ZERO, Vec2::X, Vec2::Y]);
+        mesh.calculate_normals();
+        mesh.calculate_tangents();
+        mesh.joints = Some(vec![UVec4::new(0, 1, 2, 3); 3]);
+        mesh.morph_targets.push(MorphTarget {
+            position: vec![Vec3::Z; 3],
+            normal: None,
+            tangent: Some(vec![Vec3::X; 3]),
+        });
+        let mesh_key = PrimitiveKey {
+            mesh_index: 1,
+            primitive_index: 0,
+        };
+        let texture_key = SampledTextureKey {
+            texture_index: 3,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        };
+        let image = Ktx2Image {
+            format: texture_key.format,
+            width: 2,
+            height: 1,
+            levels: vec![vec![1; 8], vec![2; 4]],
+        };
+        let prepared = vec![
+            Prepared::Mesh(mesh_key, Box::new(mesh.clone())),
+            Prepared::Texture(
+                texture_key,
+                DecodedImage::Ktx2 {
+                    image,
+                    fallback: Some(image::RgbaImage::from_pixel(
+                        1,
+                        1,
+                        image::Rgba([1, 2, 3, 4]),
+                    )),
+                },
+            ),
+        ];
+        let structure = structure(mesh_key, texture_key);
+        assert!(structure.is_consistent());
+        assert_eq!(
+            structure.jobs(),
+            vec![Job::Mesh(mesh_key), Job::Texture(texture_key)]
+        );
+
+        let dir = std::env::temp_dir().join(format!("test_cache_entry_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let resource = dir.join("resource.bin");
+        std::fs::write(&resource, [1, 2, 3]).unwrap();
+        let cache = Cache {
+            path: dir.join("entry.cache"),
+            key: 42,
+            base: Some(dir.clone()),
+        };
+        let resources = vec![(
+            "resource.bin".to_owned(),
+            cache.resource_hash("resource.bin"),
+        )];
+        cache.write(&resources, &structure, &prepared).unwrap();
+        let (read_structure, read) = cache.read().unwrap();
+        // The floats are stored by their bits, so everything prints the same.
+        assert_eq!(format!("{read_structure:?}"), format!("{structure:?}"));
+        let Prepared::Mesh(key, read_mesh) = &read[0] else {
+            panic!("expected a mesh");
+        };
+        assert_eq!(*key, mesh_key);
+        assert_eq!(read_mesh.name, mesh.name);
+        assert_eq!(read_mesh.position, mesh.position);
+        assert_eq!(read_mesh.index, mesh.index);
+        assert_eq!(read_mesh.normal, mesh.normal);
+        assert_eq!(read_mesh.uv, mesh.uv);
+        assert_eq!(read_mesh.tangents, mesh.tangents);
+        assert_eq!(read_mesh.joints, mesh.joints);
+        assert_eq!(read_mesh.color, None);
+        assert_eq!(read_mesh.morph_targets[0].tangent, Some(vec![Vec3::X; 3]));
+        let Prepared::Texture(key, DecodedImage::Ktx2 { image, fallback }) = &read[1] else {
+            panic!("expected a texture");
+        };
+        assert_eq!(*key, texture_key);
+        assert_eq!(image.levels, vec![vec![1; 8], vec![2; 4]]);
+        assert_eq!(fallback.as_ref().unwrap().as_raw(), &vec![1, 2, 3, 4]);
+
+        // Entries for other contents, with changed resources, for other jobs, or with damaged bytes are rejected.
+        let stale = Cache {
+            path: cache.path.clone(),
+            key: 43,
+            base: cache.base.clone(),
+        };
+        assert!(matches!(stale.read(), Err(CacheError::Stale)));
+        std::fs::write(&resource, [1, 2, 4]).unwrap();
+        assert!(matches!(cache.read(), Err(CacheError::Stale)));
+        std::fs::write(&resource, [1, 2, 3]).unwrap();
+        cache.write(&resources, &structure, &prepared[..1]).unwrap();
+        assert!(matches!(cache.read(), Err(CacheError::Corrupt(_))));
+        cache.write(&resources, &structure, &prepared).unwrap();
+        let mut bytes = std::fs::read(&cache.path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&cache.path, &bytes).unwrap();
+        assert!(matches!(cache.read(), Err(CacheError::Corrupt(_))));
+        std::fs::write(&cache.path, &bytes[..HEADER_SIZE - 1]).unwrap();
+        assert!(matches!(cache.read(), Err(CacheError::Corrupt(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
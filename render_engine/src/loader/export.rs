@@ -0,0 +1,663 @@
+// Writes meshes, materials and the node hierarchy to binary gltf files, https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#binary-gltf-layout
+//
+// Everything is stored in the single binary chunk, textures are embedded as png images.
+
+use crate::scene::Scene;
+use crate::texture::{AlphaMode, MaterialUniform, SampledTexture, TextureTransform, TextureType};
+use crate::vertex::mesh::CpuMesh;
+use glam::{Mat4, Vec3};
+use gltf::json;
+use json::validation::{Checked::Valid, USize64};
+use log::*;
+use std::collections::HashMap;
+use thiserror::Error;
+use zerocopy::{Immutable, IntoBytes};
+
+/// Errors while exporting.
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("failed to write {path:?}: {source}")]
+    Io {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to encode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("failed to serialize the document: {0}")]
+    Json(#[from] json::Error),
+    #[error("failed to write the binary document: {0}")]
+    Glb(#[from] gltf::Error),
+    #[error("textures of format {0:?} can not be exported")]
+    TextureFormat(wgpu::TextureFormat),
+}
+
+/// A texture used by a material, referring to a texture added with [`GlbExporter::add_image`].
+#[derive(Copy, Clone, Debug)]
+pub struct MaterialTexture {
+    /// The index returned by [`GlbExporter::add_image`].
+    pub texture: usize,
+    pub texture_type: TextureType,
+    /// The uv map this texture is sampled with.
+    pub tex_coord: u32,
+    pub transform: TextureTransform,
+}
+
+/// Builds a binary gltf document, the indices returned by the `add_*` methods refer to the added items.
+#[derive(Clone, Debug)]
+pub struct GlbExporter {
+    root: json::Root,
+    /// The contents of the single buffer, each buffer view starts at a multiple of four bytes.
+    bin: Vec<u8>,
+}
+
+impl GlbExporter {
+    pub fn new() -> Self {
+        let mut root = json::Root {
+            asset: json::Asset {
+                generator: Some(env!("CARGO_PKG_NAME").to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        root.scene = Some(root.push(json::Scene {
+            extensions: None,
+            extras: Default::default(),
+            name: None,
+            nodes: vec![],
+        }));
+        Self { root, bin: vec![] }
+    }
+
+    fn push_view(
+        &mut self,
+        bytes: &[u8],
+        target: Option<json::buffer::Target>,
+    ) -> json::Index<json::buffer::View> {
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        let offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+        self.root.push(json::buffer::View {
+            buffer: json::Index::new(0),
+            byte_length: USize64::from(bytes.len()),
+            byte_offset: Some(USize64::from(offset)),
+            byte_stride: None,
+            name: None,
+            target: target.map(Valid),
+            extensions: None,
+            extras: Default::default(),
+        })
+    }
+
+    fn push_accessor<T: IntoBytes + Immutable>(
+        &mut self,
+        values: &[T],
+        type_: json::accessor::Type,
+        component_type: json::accessor::ComponentType,
+        target: json::buffer::Target,
+    ) -> json::Index<json::Accessor> {
+        let view = self.push_view(values.as_bytes(), Some(target));
+        self.root.push(json::Accessor {
+            buffer_view: Some(view),
+            byte_offset: None,
+            count: USize64::from(values.len()),
+            component_type: Valid(json::accessor::GenericComponentType(component_type)),
+            extensions: None,
+            extras: Default::default(),
+            type_: Valid(type_),
+            min: None,
+            max: None,
+            name: None,
+            normalized: false,
+            sparse: None,
+        })
+    }
+
+    fn push_vec3(&mut self, values: &[Vec3], bounds: bool) -> json::Index<json::Accessor> {
+        use json::accessor::{ComponentType, Type};
+        let index = self.push_accessor(
+            values,
+            Type::Vec3,
+            ComponentType::F32,
+            json::buffer::Target::ArrayBuffer,
+        );
+        // Positions must have their bounds.
+        if bounds && !values.is_empty() {
+            let min = values.iter().fold(Vec3::MAX, |a, b| a.min(*b));
+            let max = values.iter().fold(Vec3::MIN, |a, b| a.max(*b));
+            let accessor = &mut self.root.accessors[index.value()];
+            accessor.min = Some(json::Value::from(min.to_array().to_vec()));
+            accessor.max = Some(json::Value::from(max.to_array().to_vec()));
+        }
+        index
+    }
+
+    /// Embed an image as png with a repeating, linearly filtered sampler, returns the texture index.
+    pub fn add_image(&mut self, image: &image::RgbaImage) -> Result<usize, ExportError> {
+        let mut png = std::io::Cursor::new(vec![]);
+        image.write_to(&mut png, image::ImageFormat::Png)?;
+        let view = self.push_view(png.get_ref(), None);
+        let source = self.root.push(json::Image {
+            buffer_view: Some(view),
+            mime_type: Some(json::image::MimeType("image/png".to_owned())),
+            name: None,
+            uri: None,
+            extensions: None,
+            extras: Default::default(),
+        });
+        let sampler = self.root.push(json::texture::Sampler {
+            mag_filter: Some(Valid(json::texture::MagFilter::Linear)),
+            min_filter: Some(Valid(json::texture::MinFilter::LinearMipmapLinear)),
+            ..Default::default()
+        });
+        let texture = self.root.push(json::Texture {
+            name: None,
+            sampler: Some(sampler),
+            source,
+            extensions: None,
+            extras: Default::default(),
+        });
+        Ok(texture.value())
+    }
+
+    /// Add a material with its textures, textures of the same type after the first are ignored. Returns the material
+    /// index.
+    pub fn add_material(
+        &mut self,
+        material: &MaterialUniform,
+        textures: &[MaterialTexture],
+    ) -> usize {
+        let find = |texture_type| textures.iter().find(|t| t.texture_type == texture_type);
+        let mut uses_transform = false;
+        let mut info = |texture: &MaterialTexture| {
+            let transform = texture.transform;
+            let extensions = (transform != TextureTransform::default()).then(|| {
+                uses_transform = true;
+                json::extensions::texture::Info {
+                    texture_transform: Some(json::extensions::texture::TextureTransform {
+                        offset: json::extensions::texture::TextureTransformOffset(
+                            transform.offset.to_array(),
+                        ),
+                        rotation: json::extensions::texture::TextureTransformRotation(
+                            transform.rotation,
+                        ),
+                        scale: json::extensions::texture::TextureTransformScale(
+                            transform.scale.to_array(),
+                        ),
+                        tex_coord: None,
+                        extras: Default::default(),
+                    }),
+                    others: Default::default(),
+                }
+            });
+            json::texture::Info {
+                index: json::Index::new(texture.texture as u32),
+                tex_coord: texture.tex_coord,
+                extensions,
+                extras: Default::default(),
+            }
+        };
+        let base_color_texture = find(TextureType::BaseColor).map(&mut info);
+        let metallic_roughness_texture = find(TextureType::MetallicRoughness).map(&mut info);
+        let emissive_texture = find(TextureType::Emissive).map(&mut info);
+        // The normal and occlusion textures have their own info, with the same fields and the scale or strength.
+        let normal_texture =
+            find(TextureType::Normal)
+                .map(&mut info)
+                .map(|info| json::material::NormalTexture {
+                    index: info.index,
+                    scale: material.normal_scale,
+                    tex_coord: info.tex_coord,
+                    extensions: info.extensions.map(|e| {
+                        let mut extension = json::extensions::material::NormalTexture::default();
+                        extension.others.extend(transform_extension(e));
+                        extension
+                    }),
+                    extras: Default::default(),
+                });
+        let occlusion_texture = find(TextureType::Occlusion).map(&mut info).map(|info| {
+            json::material::OcclusionTexture {
+                index: info.index,
+                strength: json::material::StrengthFactor(material.occlusion_strength),
+                tex_coord: info.tex_coord,
+                extensions: info.extensions.map(|e| {
+                    let mut extension = json::extensions::material::OcclusionTexture::default();
+                    extension.others.extend(transform_extension(e));
+                    extension
+                }),
+                extras: Default::default(),
+            }
+        });
+        if uses_transform
+            && !self
+                .root
+                .extensions_used
+                .iter()
+                .any(|e| e == "KHR_texture_transform")
+        {
+            self.root
+                .extensions_used
+                .push("KHR_texture_transform".to_owned());
+        }
+
+        let index = self.root.push(json::Material {
+            alpha_cutoff: (material.alpha_mode == AlphaMode::Mask)
+                .then_some(json::material::AlphaCutoff(material.alpha_cutoff)),
+            alpha_mode: Valid(match material.alpha_mode {
+                AlphaMode::Opaque => json::material::AlphaMode::Opaque,
+                AlphaMode::Mask => json::material::AlphaMode::Mask,
+                AlphaMode::Blend => json::material::AlphaMode::Blend,
+            }),
+            double_sided: material.double_sided != 0,
+            pbr_metallic_roughness: json::material::PbrMetallicRoughness {
+                base_color_factor: json::material::PbrBaseColorFactor(
+                    material.base_color_factor.to_array(),
+                ),
+                base_color_texture,
+                metallic_factor: json::material::StrengthFactor(material.metallic_factor),
+                roughness_factor: json::material::StrengthFactor(material.roughness_factor),
+                metallic_roughness_texture,
+                ..Default::default()
+            },
+            normal_texture,
+            occlusion_texture,
+            emissive_texture,
+            emissive_factor: json::material::EmissiveFactor(material.emissive_factor.to_array()),
+            ..Default::default()
+        });
+        index.value()
+    }
+
+    /// Add a mesh with a primitive for each cpu mesh and the index of its material, returns the mesh index.
+    pub fn add_mesh(
+        &mut self,
+        name: Option<&str>,
+        primitives: &[(&CpuMesh, Option<usize>)],
+    ) -> usize {
+        use json::accessor::{ComponentType, Type};
+        use json::mesh::Semantic;
+        let array = json::buffer::Target::ArrayBuffer;
+        let primitives = primitives
+            .iter()
+            .map(|(mesh, material)| {
+                let mut attributes = std::collections::BTreeMap::new();
+                attributes.insert(
+                    Valid(Semantic::Positions),
+                    self.push_vec3(&mesh.position, true),
+                );
+                if let Some(normal) = mesh.normal.as_ref() {
+                    let normal: Vec<Vec3> = normal.iter().map(|n| Vec3::from(*n)).collect();
+                    attributes.insert(Valid(Semantic::Normals), self.push_vec3(&normal, false));
+                }
+                if let Some(tangents) = mesh.tangents.as_ref() {
+                    let accessor =
+                        self.push_accessor(tangents, Type::Vec4, ComponentType::F32, array);
+                    attributes.insert(Valid(Semantic::Tangents), accessor);
+                }
+                if let Some(color) = mesh.color.as_ref() {
+                    let accessor = self.push_accessor(color, Type::Vec4, ComponentType::F32, array);
+                    attributes.insert(Valid(Semantic::Colors(0)), accessor);
+                }
+                for (set, uv) in [&mesh.uv, &mesh.uv1].into_iter().enumerate() {
+                    if let Some(uv) = uv.as_ref() {
+                        let accessor =
+                            self.push_accessor(uv, Type::Vec2, ComponentType::F32, array);
+                        attributes.insert(Valid(Semantic::TexCoords(set as u32)), accessor);
+                    }
+                }
+                if let (Some(joints), Some(weights)) = (mesh.joints.as_ref(), mesh.weights.as_ref())
+                {
+                    // Joint indices must be unsigned bytes or shorts.
+                    let joints: Vec<[u16; 4]> = joints
+                        .iter()
+                        .map(|j| j.to_array().map(|j| j as u16))
+                        .collect();
+                    let accessor =
+                        self.push_accessor(&joints, Type::Vec4, ComponentType::U16, array);
+                    attributes.insert(Valid(Semantic::Joints(0)), accessor);
+                    let accessor =
+                        self.push_accessor(weights, Type::Vec4, ComponentType::F32, array);
+                    attributes.insert(Valid(Semantic::Weights(0)), accessor);
+                }
+                let targets: Vec<json::mesh::MorphTarget> = mesh
+                    .morph_targets
+                    .iter()
+                    .map(|target| json::mesh::MorphTarget {
+                        positions: Some(self.push_vec3(&target.position, true)),
+                        normals: target.normal.as_ref().map(|v| self.push_vec3(v, false)),
+                        tangents: target.tangent.as_ref().map(|v| self.push_vec3(v, false)),
+                    })
+                    .collect();
+                let indices = self.push_accessor(
+                    &mesh.index,
+                    Type::Scalar,
+                    ComponentType::U32,
+                    json::buffer::Target::ElementArrayBuffer,
+                );
+                json::mesh::Primitive {
+                    attributes,
+                    extensions: None,
+                    extras: Default::default(),
+                    indices: Some(indices),
+                    material: material.map(|m| json::Index::new(m as u32)),
+                    mode: Valid(match mesh.topology {
+                        wgpu::PrimitiveTopology::PointList => json::mesh::Mode::Points,
+                        wgpu::PrimitiveTopology::LineList => json::mesh::Mode::Lines,
+                        wgpu::PrimitiveTopology::LineStrip => json::mesh::Mode::LineStrip,
+                        wgpu::PrimitiveTopology::TriangleList => json::mesh::Mode::Triangles,
+                        wgpu::PrimitiveTopology::TriangleStrip => json::mesh::Mode::TriangleStrip,
+                    }),
+                    targets: (!targets.is_empty()).then_some(targets),
+                }
+            })
+            .collect();
+        let index = self.root.push(json::Mesh {
+            extensions: None,
+            extras: Default::default(),
+            name: name.map(|z| z.to_owned()),
+            primitives,
+            weights: None,
+        });
+        index.value()
+    }
+
+    /// Add a root node, the transform must be decomposable into scale, rotation and translation. Returns the node
+    /// index.
+    pub fn add_node(&mut self, name: Option<&str>, transform: &Mat4, mesh: Option<usize>) -> usize {
+        let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+        let index = self.root.push(json::Node {
+            mesh: mesh.map(|m| json::Index::new(m as u32)),
+            name: name.map(|z| z.to_owned()),
+            rotation: Some(json::scene::UnitQuaternion(rotation.to_array())),
+            scale: Some(scale.to_array()),
+            translation: Some(translation.to_array()),
+            ..Default::default()
+        });
+        self.root.scenes[0].nodes.push(index);
+        index.value()
+    }
+
+    /// Make a root node the child of another node.
+    pub fn add_child(&mut self, parent: usize, child: usize) {
+        let child = json::Index::new(child as u32);
+        self.root.scenes[0].nodes.retain(|n| *n != child);
+        self.root.nodes[parent]
+            .children
+            .get_or_insert_default()
+            .push(child);
+    }
+
+    /// Add the nodes and objects of a scene, reading the meshes and textures back from the gpu.
+    ///
+    /// Textures that are not rgba8 are skipped with a warning, skins are not exported.
+    pub fn add_scene(
+        &mut self,
+        context: &crate::Context,
+        scene: &Scene,
+    ) -> Result<(), ExportError> {
+        // The exported index of each texture that was read back, none if it could not be exported.
+        let mut textures: Vec<(wgpu::Texture, Option<usize>)> = vec![];
+        let mut materials: HashMap<usize, usize> = HashMap::new();
+        let mut meshes: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut nodes = Vec::with_capacity(scene.nodes.len());
+        for node in scene.nodes.iter() {
+            // All objects placed by a node become the primitives of one mesh, nodes placing the same objects share it.
+            let objects: Vec<usize> = node.objects.iter().map(|o| o.object).collect();
+            let mesh = if objects.is_empty() {
+                None
+            } else if let Some(mesh) = meshes.get(&objects) {
+                Some(*mesh)
+            } else {
+                let mut primitives = vec![];
+                for object_index in objects.iter() {
+                    let object = &scene.objects[*object_index];
+                    let material = match materials.get(object_index) {
+                        Some(material) => *material,
+                        None => {
+                            let mut material_textures = vec![];
+                            for texture in object.cpu_textures.textures.iter() {
+                                if texture.texture_type == TextureType::None {
+                                    continue;
+                                }
+                                let index =
+                                    match textures.iter().find(|(t, _)| *t == texture.texture) {
+                                        Some((_, index)) => *index,
+                                        None => {
+                                            let index = match read_texture(context, texture) {
+                                                Ok(image) => Some(self.add_image(&image)?),
+                                                Err(e) => {
+                                                    warn!("{e}");
+                                                    None
+                                                }
+                                            };
+                                            textures.push((texture.texture.clone(), index));
+                                            index
+                                        }
+                                    };
+                                material_textures.extend(index.map(|index| MaterialTexture {
+                                    texture: index,
+                                    texture_type: texture.texture_type,
+                                    tex_coord: texture.tex_coord,
+                                    transform: texture.transform,
+                                }));
+                            }
+                            let material = self
+                                .add_material(&object.cpu_textures.material, &material_textures);
+                            materials.insert(*object_index, material);
+                            material
+                        }
+                    };
+                    primitives.push((object.mesh_object.gpu_mesh.to_cpu(context), Some(material)));
+                }
+                let primitives: Vec<(&CpuMesh, Option<usize>)> =
+                    primitives.iter().map(|(m, i)| (m, *i)).collect();
+                let mesh = self.add_mesh(node.name.as_deref(), &primitives);
+                meshes.insert(objects, mesh);
+                Some(mesh)
+            };
+            let index = self.add_node(node.name.as_deref(), &node.local_transform(), mesh);
+            if !node.weights.is_empty() {
+                self.root.nodes[index].weights = Some(node.weights.clone());
+            }
+            nodes.push(index);
+        }
+        for (index, node) in scene.nodes.iter().enumerate() {
+            for child in node.children.iter() {
+                self.add_child(nodes[index], nodes[child.0]);
+            }
+        }
+        self.root.scenes[0].name = scene.name.clone();
+        Ok(())
+    }
+
+    /// The binary gltf file.
+    pub fn to_glb(&self) -> Result<Vec<u8>, ExportError> {
+        let mut root = self.root.clone();
+        if !self.bin.is_empty() {
+            root.push(json::Buffer {
+                byte_length: USize64::from(self.bin.len()),
+                name: None,
+                uri: None,
+                extensions: None,
+                extras: Default::default(),
+            });
+        }
+        let json = json::serialize::to_vec(&root)?;
+        let glb = gltf::binary::Glb {
+            header: gltf::binary::Header {
+                magic: *b"glTF",
+                version: 2,
+                // Calculated when writing.
+                length: 0,
+            },
+            json: json.into(),
+            bin: (!self.bin.is_empty()).then(|| self.bin.as_slice().into()),
+        };
+        Ok(glb.to_vec()?)
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), ExportError> {
+        std::fs::write(path, self.to_glb()?).map_err(|source| ExportError::Io {
+            path: path.to_owned(),
+            source,
+        })
+    }
+}
+
+impl Default for GlbExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The texture transform as a raw extension value, for the texture infos that don't have a typed extension.
+fn transform_extension(info: json::extensions::texture::Info) -> Option<(String, json::Value)> {
+    let value = json::serialize::to_value(info.texture_transform?).ok()?;
+    Some(("KHR_texture_transform".to_owned(), value))
+}
+
+/// Read the first mip level of the texture back from the gpu.
+fn read_texture(
+    context: &crate::Context,
+    texture: &SampledTexture,
+) -> Result<image::RgbaImage, ExportError> {
+    let texture = &texture.texture;
+    let format = texture.format();
+    if !matches!(
+        format,
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb
+    ) {
+        return Err(ExportError::TextureFormat(format));
+    }
+    let data = crate::wgpu_util::read_texture(context, texture);
+    Ok(image::RgbaImage::from_raw(texture.width(), texture.height(), data).unwrap())
+}
+
+/// Write meshes to a binary gltf file, each mesh is placed by its own root node and has the default material.
+pub fn export_meshes(meshes: &[CpuMesh], path: &std::path::Path) -> Result<(), ExportError> {
+    let mut exporter = GlbExporter::new();
+    for mesh in meshes.iter() {
+        let mesh_index = exporter.add_mesh(mesh.name.as_deref(), &[(mesh, None)]);
+        exporter.add_node(mesh.name.as_deref(), &Mat4::IDENTITY, Some(mesh_index));
+    }
+    exporter.save(path)
+}
+
+/// Write a scene to a binary gltf file, see [`GlbExporter::add_scene`].
+pub fn export_scene(
+    context: &crate::Context,
+    scene: &Scene,
+    path: &std::path::Path,
+) -> Result<(), ExportError> {
+    let mut exporter = GlbExporter::new();
+    exporter.add_scene(context, scene)?;
+    exporter.save(path)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use glam::{Quat, Vec2, Vec4};
+
+    /// Add a 2x2 red image and a material that uses it as base color.
+    pub(crate) fn add_base_color_material(
+        exporter: &mut GlbExporter,
+        material: &MaterialUniform,
+        transform: TextureTransform,
+    ) -> usize {
+        let image = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 0, 0, 255]));
+        let texture = exporter.add_image(&image).unwrap();
+        exporter.add_material(
+            material,
+            &[MaterialTexture {
+                texture,
+                texture_type: TextureType::BaseColor,
+                tex_coord: 0,
+                transform,
+            }],
+        )
+    }
+
+    /// An exporter with a node at the origin with a textured triangle, returns the triangle and the index of its mesh.
+    pub(crate) fn textured_triangle() -> (GlbExporter, CpuMesh, usize) {
+        let mut exporter = GlbExporter::new();
+        let material = add_base_color_material(
+            &mut exporter,
+            &MaterialUniform::default(),
+            Default::default(),
+        );
+        let mut mesh = CpuMesh::new(vec![Vec3::ZERO, Vec3::X, Vec3::Y], vec![0, 1, 2]);
+        mesh.uv = Some(mesh.position.iter().map(|p| p.truncate()).collect());
+        mesh.calculate_normals();
+        let mesh_index = exporter.add_mesh(None, &[(&mesh, Some(material))]);
+        exporter.add_node(None, &Mat4::IDENTITY, Some(mesh_index));
+        (exporter, mesh, mesh_index)
+    }
+
+    #[test]
+    fn test_export_glb() {
+        let mut mesh = CpuMesh::axis_frame();
+        mesh.uv = Some(mesh.position.iter().map(|p| p.truncate()).collect());
+        mesh.tangents = Some(vec![Vec4::new(1.0, 0.0, 0.0, 1.0); mesh.position.len()]);
+        let points = CpuMesh::new(vec![Vec3::ZERO, Vec3::ONE], vec![0, 1])
+            .with_topology(wgpu::PrimitiveTopology::PointList);
+
+        let mut exporter = GlbExporter::new();
+        let material = MaterialUniform {
+            metallic_factor: 0.25,
+            alpha_mode: AlphaMode::Mask,
+            ..Default::default()
+        };
+        let transform = TextureTransform {
+            scale: Vec2::new(2.0, 2.0),
+            ..Default::default()
+        };
+        let material = add_base_color_material(&mut exporter, &material, transform);
+        let mesh_index =
+            exporter.add_mesh(Some("frame"), &[(&mesh, Some(material)), (&points, None)]);
+        let parent = exporter.add_node(Some("parent"), &Mat4::from_translation(Vec3::X), None);
+        let child_transform = Mat4::from_quat(Quat::from_rotation_z(1.0));
+        let child = exporter.add_node(Some("child"), &child_transform, Some(mesh_index));
+        exporter.add_child(parent, child);
+        let glb = exporter.to_glb().unwrap();
+
+        let (document, buffers, images) = gltf::import_slice(&glb).unwrap();
+        let scene = document.default_scene().unwrap();
+        let roots: Vec<_> = scene.nodes().collect();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].name(), Some("parent"));
+        let child = roots[0].children().next().unwrap();
+        assert!(
+            Mat4::from_cols_array_2d(&child.transform().matrix())
+                .abs_diff_eq(child_transform, 1e-6)
+        );
+        assert_eq!(images[0].width, 2);
+
+        // The primitives load back into the same meshes.
+        let gltf_mesh = child.mesh().unwrap();
+        let primitives: Vec<_> = gltf_mesh.primitives().collect();
+        let loaded =
+            super::super::load_gltf_primitive_mesh(&primitives[0], &gltf_mesh, &buffers).unwrap();
+        assert_eq!(loaded.position, mesh.position);
+        assert_eq!(loaded.index, mesh.index);
+        assert_eq!(loaded.normal, mesh.normal);
+        assert_eq!(loaded.color, mesh.color);
+        assert_eq!(loaded.uv, mesh.uv);
+        assert_eq!(loaded.tangents, mesh.tangents);
+        let loaded_points =
+            super::super::load_gltf_primitive_mesh(&primitives[1], &gltf_mesh, &buffers).unwrap();
+        assert_eq!(loaded_points.topology, wgpu::PrimitiveTopology::PointList);
+        assert_eq!(loaded_points.position, points.position);
+
+        let gltf_material = primitives[0].material();
+        assert_eq!(
+            gltf_material.pbr_metallic_roughness().metallic_factor(),
+            0.25
+        );
+        assert_eq!(gltf_material.alpha_mode(), gltf::material::AlphaMode::Mask);
+        let base_color = gltf_material
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .unwrap();
+        assert_eq!(base_color.texture_transform().unwrap().scale(), [2.0, 2.0]);
+    }
+}
@@ -0,0 +1,181 @@
+// OBJ, STL and PLY files, read and decoded without the gpu, such that the background loader can do that on a worker
+// thread. The upload builds the scene from the meshes and images.
+
+use super::{LoadOptions, LoaderError, load_gltf_texture};
+use crate::animation;
+use crate::fragment::mesh_object_textured::MeshObjectTextured;
+use crate::lights::CpuLights;
+use crate::scene::{self, ObjectInstance, Scene};
+use crate::texture::mipmap::MipmapGenerator;
+use crate::texture::{MaterialUniform, SampledTexture, TextureType};
+use crate::vertex::mesh::CpuMesh;
+use crate::vertex::mesh_object::MeshObject;
+use glam::Mat4;
+use log::*;
+use std::path::{Path, PathBuf};
+
+/// A mesh of the file with its material.
+pub(super) struct MeshFileObject {
+    pub mesh: CpuMesh,
+    pub material: MaterialUniform,
+    /// Indices into the images of the file, with the way the material uses them.
+    pub textures: Vec<(usize, TextureType)>,
+}
+
+/// An image of the file, decoded.
+pub(super) struct MeshFileImage {
+    pub path: PathBuf,
+    pub image: image::RgbaImage,
+    /// The format the image is sampled as.
+    pub format: wgpu::TextureFormat,
+}
+
+/// The contents of an OBJ, STL or PLY file, in the world convention, ready for the upload.
+pub(super) struct MeshFileContents {
+    pub name: Option<String>,
+    /// The objects of the nodes refer to `objects`.
+    pub nodes: Vec<scene::Node>,
+    pub objects: Vec<MeshFileObject>,
+    /// Shared by the objects.
+    pub images: Vec<MeshFileImage>,
+}
+
+impl MeshFileContents {
+    /// Upload the meshes and images, images that fail to upload are left out with a warning.
+    pub fn upload(self, context: &crate::Context) -> super::GltfContents {
+        let mut mipmap_generator = MipmapGenerator::new(&context.device);
+        let textures: Vec<_> = self
+            .images
+            .into_iter()
+            .map(|image| {
+                let path = image.path.clone();
+                let texture = upload_image(context, image, &mut mipmap_generator);
+                if texture.is_none() {
+                    warn!("failed to upload {path:?}");
+                }
+                texture
+            })
+            .collect();
+
+        let mut scene = Scene::new(self.nodes).with_name(self.name.as_deref());
+        scene.objects = self
+            .objects
+            .into_iter()
+            .map(|object| {
+                let object_textures: Vec<_> = object
+                    .textures
+                    .iter()
+                    .filter_map(|(image, texture_type)| {
+                        let mut sampled_texture = textures[*image].clone()?;
+                        sampled_texture.texture_type = *texture_type;
+                        Some(sampled_texture)
+                    })
+                    .collect();
+                let mesh_object = MeshObject::new(context.clone(), object.mesh.to_gpu(context))
+                    .with_single_transform(&Mat4::IDENTITY);
+                MeshObjectTextured::new_with_material(
+                    context.clone(),
+                    mesh_object,
+                    &object_textures,
+                    object.material,
+                )
+            })
+            .collect();
+        scene.update_objects();
+        super::GltfContents {
+            scene_names: vec![scene.name.clone()],
+            scene,
+            lights: CpuLights::new(context.clone()),
+            cameras: vec![],
+            animation: animation::AnimationPlayer::new(vec![]),
+        }
+    }
+}
+
+/// Read an OBJ, STL or PLY file, the format is picked by the extension; None for other files.
+pub(super) fn read_mesh_file(
+    path: &Path,
+    options: &LoadOptions,
+) -> Option<Result<MeshFileContents, LoaderError>> {
+    let extension = path
+        .extension()
+        .and_then(|z| z.to_str())
+        .map(|z| z.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("obj") => Some(super::obj::read_obj(path, options)),
+        Some("stl") => Some(read_single_mesh(path, super::stl::parse_stl, options)),
+        Some("ply") => Some(read_single_mesh(path, super::ply::parse_ply, options)),
+        _ => None,
+    }
+}
+
+/// Read a file that holds a single mesh, like STL and PLY files, as a scene with one node.
+fn read_single_mesh(
+    path: &Path,
+    parse: fn(&[u8]) -> Result<CpuMesh, String>,
+    options: &LoadOptions,
+) -> Result<MeshFileContents, LoaderError> {
+    let bytes = std::fs::read(path).map_err(|source| LoaderError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    let mut mesh = parse(&bytes).map_err(|reason| LoaderError::Invalid {
+        path: path.to_owned(),
+        reason,
+    })?;
+    options.conversion().convert_mesh(&mut mesh);
+    Ok(MeshFileContents {
+        name: path
+            .file_stem()
+            .and_then(|z| z.to_str())
+            .map(|z| z.to_owned()),
+        nodes: vec![scene::Node {
+            objects: vec![ObjectInstance {
+                object: 0,
+                instance: 0,
+            }],
+            ..Default::default()
+        }],
+        objects: vec![MeshFileObject {
+            mesh,
+            // These formats have no materials, the surface is a white dielectric, tinted by vertex colors.
+            material: MaterialUniform {
+                metallic_factor: 0.0,
+                ..Default::default()
+            },
+            textures: vec![],
+        }],
+        images: vec![],
+    })
+}
+
+/// Upload an image as a texture, with a repeating trilinear sampler.
+fn upload_image(
+    context: &crate::Context,
+    image: MeshFileImage,
+    mipmap_generator: &mut MipmapGenerator,
+) -> Option<SampledTexture> {
+    let data = gltf::image::Data {
+        width: image.image.width(),
+        height: image.image.height(),
+        format: gltf::image::Format::R8G8B8A8,
+        pixels: image.image.into_raw(),
+    };
+    let texture = load_gltf_texture(context, &data, image.format, mipmap_generator)?;
+    Some(SampledTexture {
+        sampler: context.device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy_clamp: super::MAX_ANISOTROPY,
+            ..Default::default()
+        }),
+        texture,
+        texture_type: TextureType::None,
+        tex_coord: 0,
+        transform: Default::default(),
+    })
+}
@@ -0,0 +1,614 @@
+// Decoding of EXT_meshopt_compression buffer views.
+// https://github.com/KhronosGroup/glTF/blob/main/extensions/2.0/Vendor/EXT_meshopt_compression/README.md
+//
+// The bitstreams are those of meshoptimizer, this follows the reference decoders in vertexcodec.cpp, indexcodec.cpp
+// and vertexfilter.cpp of https://github.com/zeux/meshoptimizer, without the simd paths.
+//
+// Compressed views point into a fallback buffer that holds no data, we allocate those zeroed and decode into them,
+// such that the accessors can be read as if the file was never compressed.
+
+use gltf::json::Value;
+
+pub const EXTENSION_NAME: &str = "EXT_meshopt_compression";
+
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const SEQUENCE_HEADER: u8 = 0xd0;
+
+const VERTEX_BLOCK_SIZE_BYTES: usize = 8192;
+const VERTEX_BLOCK_MAX_SIZE: usize = 256;
+const BYTE_GROUP_SIZE: usize = 16;
+const TAIL_MAX_SIZE: usize = 32;
+
+/// Whether the buffer is a fallback buffer, it has no data of its own if the decoder is used.
+pub fn is_fallback(buffer: &gltf::Buffer) -> bool {
+    buffer
+        .extension_value(EXTENSION_NAME)
+        .and_then(|v| v.get("fallback"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Decode all compressed buffer views into the buffers they are views of.
+pub fn decode_buffer_views(
+    document: &gltf::Document,
+    buffers: &mut [gltf::buffer::Data],
+) -> Result<(), (usize, String)> {
+    for view in document.views() {
+        let Some(extension) = view.extension_value(EXTENSION_NAME) else {
+            continue;
+        };
+        decode_buffer_view(&view, extension, buffers).map_err(|reason| (view.index(), reason))?;
+    }
+    Ok(())
+}
+
+fn decode_buffer_view(
+    view: &gltf::buffer::View,
+    extension: &Value,
+    buffers: &mut [gltf::buffer::Data],
+) -> Result<(), String> {
+    let field = |name: &str| {
+        extension
+            .get(name)
+            .and_then(Value::as_u64)
+            .map(|v| v as usize)
+    };
+    let text = |name: &str| extension.get(name).and_then(Value::as_str);
+    let source_buffer = field("buffer").ok_or("missing buffer")?;
+    let source_offset = field("byteOffset").unwrap_or(0);
+    let source_length = field("byteLength").ok_or("missing byteLength")?;
+    let stride = field("byteStride").ok_or("missing byteStride")?;
+    let count = field("count").ok_or("missing count")?;
+    let mode = text("mode").ok_or("missing mode")?;
+    let filter = text("filter").unwrap_or("NONE");
+
+    // The sizes come from the json, so they are checked before anything is allocated or indexed with them.
+    let source = source_offset
+        .checked_add(source_length)
+        .and_then(|end| buffers.get(source_buffer)?.get(source_offset..end))
+        .ok_or("compressed data is out of range")?
+        .to_vec();
+    let decoded_length = count
+        .checked_mul(stride)
+        .filter(|length| *length <= view.length())
+        .ok_or_else(|| {
+            format!(
+                "count {count} and byteStride {stride} exceed the byteLength {}",
+                view.length()
+            )
+        })?;
+
+    let mut decoded = vec![0u8; decoded_length];
+    match mode {
+        "ATTRIBUTES" => {
+            if stride == 0 || stride > 256 || stride % 4 != 0 {
+                return Err(format!("invalid byteStride {stride} for attributes"));
+            }
+            decode_vertex_buffer(&mut decoded, count, stride, &source)?;
+        }
+        "TRIANGLES" => {
+            if count % 3 != 0 || !(stride == 2 || stride == 4) {
+                return Err(format!(
+                    "invalid count {count} or byteStride {stride} for triangles"
+                ));
+            }
+            decode_index_buffer(&mut decoded, count, stride, &source)?;
+        }
+        "INDICES" => {
+            if !(stride == 2 || stride == 4) {
+                return Err(format!("invalid byteStride {stride} for indices"));
+            }
+            decode_index_sequence(&mut decoded, count, stride, &source)?;
+        }
+        _ => return Err(format!("unknown mode {mode}")),
+    }
+    match filter {
+        "NONE" => {}
+        "OCTAHEDRAL" if stride == 4 => filter_octahedral_i8(&mut decoded),
+        "OCTAHEDRAL" if stride == 8 => filter_octahedral_i16(&mut decoded),
+        "QUATERNION" if stride == 8 => filter_quaternion(&mut decoded),
+        "EXPONENTIAL" if stride % 4 == 0 => filter_exponential(&mut decoded),
+        _ => {
+            return Err(format!(
+                "unsupported filter {filter} for byteStride {stride}"
+            ));
+        }
+    }
+
+    let target = buffers
+        .get_mut(view.buffer().index())
+        .and_then(|b| {
+            let end = view.offset().checked_add(decoded.len())?;
+            b.0.get_mut(view.offset()..end)
+        })
+        .ok_or("decoded data does not fit the buffer view")?;
+    target.copy_from_slice(&decoded);
+    Ok(())
+}
+
+fn unzigzag8(v: u8) -> u8 {
+    (0u8.wrapping_sub(v & 1)) ^ (v >> 1)
+}
+
+/// Read bytes from the stream, failing if the stream is too short.
+fn take<'a>(data: &mut &'a [u8], count: usize) -> Result<&'a [u8], String> {
+    if data.len() < count {
+        return Err("unexpected end of data".to_owned());
+    }
+    let (head, tail) = data.split_at(count);
+    *data = tail;
+    Ok(head)
+}
+
+/// Decode a group of 16 bytes, each stored with 0, 2, 4 or 8 bits, where the maximum value escapes to a full byte.
+fn decode_bytes_group(data: &mut &[u8], out: &mut [u8], bitslog2: u8) -> Result<(), String> {
+    match bitslog2 {
+        0 => out.fill(0),
+        3 => out.copy_from_slice(take(data, BYTE_GROUP_SIZE)?),
+        _ => {
+            let bits = 1usize << bitslog2;
+            let packed = take(data, BYTE_GROUP_SIZE * bits / 8)?;
+            let escape = ((1u16 << bits) - 1) as u8;
+            for (i, out) in out.iter_mut().enumerate() {
+                let bit = i * bits;
+                let enc = (packed[bit / 8] >> (8 - bits - bit % 8)) & escape;
+                *out = if enc == escape {
+                    take(data, 1)?[0]
+                } else {
+                    enc
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+fn decode_bytes(data: &mut &[u8], out: &mut [u8]) -> Result<(), String> {
+    let groups = out.len() / BYTE_GROUP_SIZE;
+    let header = take(data, groups.div_ceil(4))?;
+    for (group, out) in out.chunks_mut(BYTE_GROUP_SIZE).enumerate() {
+        let bitslog2 = (header[group / 4] >> ((group % 4) * 2)) & 3;
+        decode_bytes_group(data, out, bitslog2)?;
+    }
+    Ok(())
+}
+
+fn decode_vertex_buffer(
+    out: &mut [u8],
+    count: usize,
+    stride: usize,
+    source: &[u8],
+) -> Result<(), String> {
+    if source.len() < 1 + stride || source[0] != VERTEX_HEADER {
+        return Err("invalid vertex data header".to_owned());
+    }
+    let mut last_vertex = source[source.len() - stride..].to_vec();
+    let block_size =
+        ((VERTEX_BLOCK_SIZE_BYTES / stride) & !(BYTE_GROUP_SIZE - 1)).min(VERTEX_BLOCK_MAX_SIZE);
+    let mut data = &source[1..];
+    let mut bytes = [0u8; VERTEX_BLOCK_MAX_SIZE];
+    let mut start = 0;
+    while start < count {
+        let block_count = block_size.min(count - start);
+        let aligned = block_count.next_multiple_of(BYTE_GROUP_SIZE);
+        let block = &mut out[start * stride..(start + block_count) * stride];
+        // Every byte of the vertex is stored as its own stream of deltas.
+        for k in 0..stride {
+            decode_bytes(&mut data, &mut bytes[..aligned])?;
+            let mut previous = last_vertex[k];
+            for (i, byte) in bytes[..block_count].iter().enumerate() {
+                previous = unzigzag8(*byte).wrapping_add(previous);
+                block[i * stride + k] = previous;
+            }
+            last_vertex[k] = previous;
+        }
+        start += block_count;
+    }
+    if data.len() != stride.max(TAIL_MAX_SIZE) {
+        return Err("trailing vertex data".to_owned());
+    }
+    Ok(())
+}
+
+fn write_index(out: &mut [u8], i: usize, stride: usize, value: u32) {
+    if stride == 2 {
+        out[i * 2..i * 2 + 2].copy_from_slice(&(value as u16).to_le_bytes());
+    } else {
+        out[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn decode_vbyte(data: &mut &[u8]) -> Result<u32, String> {
+    let lead = take(data, 1)?[0];
+    if lead < 128 {
+        return Ok(lead as u32);
+    }
+    let mut result = (lead & 127) as u32;
+    let mut shift = 7;
+    for _ in 0..4 {
+        let group = take(data, 1)?[0];
+        result |= ((group & 127) as u32) << shift;
+        shift += 7;
+        if group < 128 {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+/// Indices are stored as zigzag deltas to the previous one.
+fn decode_index(data: &mut &[u8], last: u32) -> Result<u32, String> {
+    let v = decode_vbyte(data)?;
+    let delta = (v >> 1) ^ 0u32.wrapping_sub(v & 1);
+    Ok(last.wrapping_add(delta))
+}
+
+fn decode_index_buffer(
+    out: &mut [u8],
+    count: usize,
+    stride: usize,
+    source: &[u8],
+) -> Result<(), String> {
+    if source.len() < 1 + count / 3 + 16 || source[0] & 0xf0 != INDEX_HEADER {
+        return Err("invalid index data header".to_owned());
+    }
+    let version = source[0] & 0x0f;
+    if version > 1 {
+        return Err(format!("unsupported index codec version {version}"));
+    }
+    let fecmax = if version >= 1 { 13 } else { 15 };
+
+    // The fifos with recently seen edges and vertices, the offsets wrap around.
+    let mut edge_fifo = [[u32::MAX; 2]; 16];
+    let mut vertex_fifo = [u32::MAX; 16];
+    let mut edge_offset = 0usize;
+    let mut vertex_offset = 0usize;
+    let push_edge = |fifo: &mut [[u32; 2]; 16], offset: &mut usize, a: u32, b: u32| {
+        fifo[*offset] = [a, b];
+        *offset = (*offset + 1) & 15;
+    };
+    let push_vertex = |fifo: &mut [u32; 16], offset: &mut usize, v: u32, advance: bool| {
+        fifo[*offset] = v;
+        *offset = (*offset + advance as usize) & 15;
+    };
+
+    let mut next = 0u32;
+    let mut last = 0u32;
+    let codes = &source[1..1 + count / 3];
+    let codeaux_table = &source[source.len() - 16..];
+    let mut data = &source[1 + count / 3..source.len() - 16];
+
+    for (triangle, code) in codes.iter().copied().enumerate() {
+        let (a, b, c);
+        if code < 0xf0 {
+            let fe = (code >> 4) as usize;
+            [a, b] = edge_fifo[(edge_offset.wrapping_sub(1 + fe)) & 15];
+            let fec = (code & 15) as usize;
+            if fec < fecmax {
+                c = if fec == 0 {
+                    next
+                } else {
+                    vertex_fifo[(vertex_offset.wrapping_sub(1 + fec)) & 15]
+                };
+                next += (fec == 0) as u32;
+                push_vertex(&mut vertex_fifo, &mut vertex_offset, c, fec == 0);
+            } else {
+                // 13 and 14 are a delta of -1 and 1 to the last free index.
+                c = if fec != 15 {
+                    last.wrapping_add((fec as u32).wrapping_sub(fec as u32 ^ 3))
+                } else {
+                    decode_index(&mut data, last)?
+                };
+                last = c;
+                push_vertex(&mut vertex_fifo, &mut vertex_offset, c, true);
+            }
+            push_edge(&mut edge_fifo, &mut edge_offset, c, b);
+            push_edge(&mut edge_fifo, &mut edge_offset, a, c);
+        } else {
+            let (fea, feb, fec);
+            if code < 0xfe {
+                let codeaux = codeaux_table[(code & 15) as usize];
+                fea = 0;
+                feb = (codeaux >> 4) as usize;
+                fec = (codeaux & 15) as usize;
+            } else {
+                let codeaux = take(&mut data, 1)?[0];
+                if codeaux == 0 {
+                    next = 0;
+                }
+                fea = if code == 0xfe { 0 } else { 15 };
+                feb = (codeaux >> 4) as usize;
+                fec = (codeaux & 15) as usize;
+            }
+            let mut fifo_or_next = |fe: usize| {
+                if fe == 0 {
+                    next += 1;
+                    next - 1
+                } else {
+                    vertex_fifo[(vertex_offset.wrapping_sub(fe)) & 15]
+                }
+            };
+            let mut va = if fea == 0 { fifo_or_next(0) } else { 0 };
+            let mut vb = fifo_or_next(feb);
+            let mut vc = fifo_or_next(fec);
+            // Free indices only occur in the escaped form, the table never holds them.
+            if fea == 15 {
+                va = decode_index(&mut data, last)?;
+                last = va;
+            }
+            if feb == 15 {
+                vb = decode_index(&mut data, last)?;
+                last = vb;
+            }
+            if fec == 15 {
+                vc = decode_index(&mut data, last)?;
+                last = vc;
+            }
+            (a, b, c) = (va, vb, vc);
+            push_vertex(&mut vertex_fifo, &mut vertex_offset, a, true);
+            push_vertex(
+                &mut vertex_fifo,
+                &mut vertex_offset,
+                b,
+                feb == 0 || feb == 15,
+            );
+            push_vertex(
+                &mut vertex_fifo,
+                &mut vertex_offset,
+                c,
+                fec == 0 || fec == 15,
+            );
+            push_edge(&mut edge_fifo, &mut edge_offset, b, a);
+            push_edge(&mut edge_fifo, &mut edge_offset, c, b);
+            push_edge(&mut edge_fifo, &mut edge_offset, a, c);
+        }
+        write_index(out, triangle * 3, stride, a);
+        write_index(out, triangle * 3 + 1, stride, b);
+        write_index(out, triangle * 3 + 2, stride, c);
+    }
+    if !data.is_empty() {
+        return Err("trailing index data".to_owned());
+    }
+    Ok(())
+}
+
+fn decode_index_sequence(
+    out: &mut [u8],
+    count: usize,
+    stride: usize,
+    source: &[u8],
+) -> Result<(), String> {
+    if source.len() < 1 + count + 4 || source[0] & 0xf0 != SEQUENCE_HEADER {
+        return Err("invalid index sequence header".to_owned());
+    }
+    let version = source[0] & 0x0f;
+    if version > 1 {
+        return Err(format!("unsupported index sequence version {version}"));
+    }
+    let mut data = &source[1..source.len() - 4];
+    // Two baselines, the lowest bit of each value selects the one the delta is relative to.
+    let mut last = [0u32; 2];
+    for i in 0..count {
+        let v = decode_vbyte(&mut data)?;
+        let baseline = (v & 1) as usize;
+        let v = v >> 1;
+        let delta = (v >> 1) ^ 0u32.wrapping_sub(v & 1);
+        last[baseline] = last[baseline].wrapping_add(delta);
+        write_index(out, i, stride, last[baseline]);
+    }
+    if !data.is_empty() {
+        return Err("trailing index sequence data".to_owned());
+    }
+    Ok(())
+}
+
+/// Round a float to the nearest integer, away from zero on ties.
+fn round_signed(v: f32) -> i32 {
+    (v + if v >= 0.0 { 0.5 } else { -0.5 }) as i32
+}
+
+/// Reconstruct unit vectors from octahedral encoding, the fourth component is left as is.
+fn filter_octahedral([x, y, z]: [f32; 3], max: f32) -> [i32; 3] {
+    let z = z - x.abs() - y.abs();
+    // Fold the lower hemisphere back.
+    let t = z.min(0.0);
+    let x = x + if x >= 0.0 { t } else { -t };
+    let y = y + if y >= 0.0 { t } else { -t };
+    let s = max / (x * x + y * y + z * z).sqrt();
+    [x * s, y * s, z * s].map(round_signed)
+}
+
+fn filter_octahedral_i8(data: &mut [u8]) {
+    for chunk in data.chunks_exact_mut(4) {
+        let v = [chunk[0], chunk[1], chunk[2]].map(|c| c as i8 as f32);
+        for (out, value) in chunk.iter_mut().zip(filter_octahedral(v, 127.0)) {
+            *out = value as i8 as u8;
+        }
+    }
+}
+
+fn filter_octahedral_i16(data: &mut [u8]) {
+    for chunk in data.chunks_exact_mut(8) {
+        let read = |i: usize| i16::from_le_bytes([chunk[i * 2], chunk[i * 2 + 1]]) as f32;
+        let v = filter_octahedral([read(0), read(1), read(2)], 32767.0);
+        for (i, value) in v.into_iter().enumerate() {
+            chunk[i * 2..i * 2 + 2].copy_from_slice(&(value as i16).to_le_bytes());
+        }
+    }
+}
+
+/// Reconstruct quaternions stored as three components and the index of the omitted largest one.
+fn filter_quaternion(data: &mut [u8]) {
+    let scale = 1.0 / 2.0f32.sqrt();
+    for chunk in data.chunks_exact_mut(8) {
+        let read = |i: usize| i16::from_le_bytes([chunk[i * 2], chunk[i * 2 + 1]]);
+        let encoded = read(3);
+        // The scale is stored in the high bits of the fourth component.
+        let ss = scale / (encoded | 3) as f32;
+        let x = read(0) as f32 * ss;
+        let y = read(1) as f32 * ss;
+        let z = read(2) as f32 * ss;
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+        let qc = (encoded & 3) as usize;
+        let components = [
+            ((qc + 1) & 3, round_signed(x * 32767.0)),
+            ((qc + 2) & 3, round_signed(y * 32767.0)),
+            ((qc + 3) & 3, round_signed(z * 32767.0)),
+            (qc, (w * 32767.0 + 0.5) as i32),
+        ];
+        for (index, value) in components {
+            chunk[index * 2..index * 2 + 2].copy_from_slice(&(value as i16).to_le_bytes());
+        }
+    }
+}
+
+/// Reconstruct floats stored as a 24 bit mantissa and an 8 bit exponent.
+fn filter_exponential(data: &mut [u8]) {
+    for chunk in data.chunks_exact_mut(4) {
+        let v = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let mantissa = ((v << 8) as i32) >> 8;
+        let exponent = (v as i32) >> 24;
+        let value = f32::from_bits(((exponent + 127) as u32) << 23) * mantissa as f32;
+        chunk.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loader::read_dequantized;
+
+    #[test]
+    fn test_decode_vertex_buffer() {
+        // Two vertices of four bytes, the deltas are zigzag encoded and the tail holds a zero base vertex.
+        let mut source = vec![VERTEX_HEADER];
+        let raw_group = |deltas: &[u8]| {
+            let mut group = vec![0x03];
+            group.extend(deltas);
+            group.resize(1 + BYTE_GROUP_SIZE, 0);
+            group
+        };
+        source.extend(raw_group(&[2, 4]));
+        // Two bits per byte, the first value escapes to a full byte.
+        source.extend([0x01, 0b1100_0000, 0, 0, 0, 4]);
+        source.extend(raw_group(&[6, 3]));
+        source.extend(raw_group(&[8, 0]));
+        source.extend([0; TAIL_MAX_SIZE]);
+        let mut out = vec![0; 8];
+        decode_vertex_buffer(&mut out, 2, 4, &source).unwrap();
+        assert_eq!(out, vec![1, 2, 3, 4, 3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn test_decode_index_buffer() {
+        // A table triangle, one reusing an edge with the next vertex and one with three free indices.
+        let mut source = vec![INDEX_HEADER | 1, 0xf0, 0x10, 0xff, 0xff, 0x0e, 0x04, 0x01];
+        source.extend([0; 16]);
+        let mut out = vec![0; 9 * 4];
+        decode_index_buffer(&mut out, 9, 4, &source).unwrap();
+        let indices: Vec<u32> = out
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(indices, vec![0, 1, 2, 2, 1, 3, 7, 9, 8]);
+    }
+
+    #[test]
+    fn test_decode_index_sequence() {
+        // From the meshoptimizer tests.
+        let source = [
+            0xd1, 0x00, 0x04, 0xcd, 0x01, 0x04, 0x07, 0x98, 0x1f, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut out = vec![0; 6 * 2];
+        decode_index_sequence(&mut out, 6, 2, &source).unwrap();
+        let indices: Vec<u16> = out
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        assert_eq!(indices, vec![0, 1, 51, 2, 49, 1000]);
+    }
+
+    #[test]
+    fn test_filters() {
+        // The corner of the octahedron is the negative z axis, the fourth byte is untouched.
+        let mut octahedral = vec![127, 127, 127, 55];
+        filter_octahedral_i8(&mut octahedral);
+        assert_eq!(octahedral, vec![0, 0, (-127i8) as u8, 55]);
+
+        // The omitted component is w, so this is the identity.
+        let mut quaternion: Vec<u8> = [0i16, 0, 0, 0x7ff3]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        filter_quaternion(&mut quaternion);
+        assert_eq!(&quaternion[6..8], &32767i16.to_le_bytes());
+
+        // A mantissa of 3 with an exponent of -1.
+        let mut exponential = 0xff00_0003u32.to_le_bytes().to_vec();
+        filter_exponential(&mut exponential);
+        assert_eq!(exponential, 1.5f32.to_le_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_decode_buffer_view_sizes() {
+        let decode = |extension: &str| {
+            let json = format!(
+                r#"{{"asset": {{"version": "2.0"}}, "buffers": [{{"byteLength": 64}}],
+                "bufferViews": [{{"buffer": 0, "byteLength": 16,
+                    "extensions": {{"{EXTENSION_NAME}": {extension}}}}}]}}"#
+            );
+            let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+            let mut buffers = vec![gltf::buffer::Data(vec![0; 64])];
+            decode_buffer_views(&document, &mut buffers)
+        };
+        let attributes = r#""mode": "ATTRIBUTES", "buffer": 0, "byteStride": 4"#;
+        // Sizes that overflow or exceed the view are errors, not panics or huge allocations.
+        let overflow =
+            format!(r#"{{{attributes}, "byteLength": 16, "count": 4611686018427387904}}"#);
+        assert!(decode(&overflow).is_err());
+        let too_large = format!(r#"{{{attributes}, "byteLength": 16, "count": 1000000000}}"#);
+        assert!(decode(&too_large).is_err());
+        let out_of_range = format!(
+            r#"{{{attributes}, "byteOffset": 18446744073709551615, "byteLength": 16, "count": 4}}"#
+        );
+        assert!(decode(&out_of_range).is_err());
+        // Four vertices fit, but the zeroed source is not a valid vertex stream.
+        let invalid = format!(r#"{{{attributes}, "byteLength": 16, "count": 4}}"#);
+        assert_eq!(
+            decode(&invalid),
+            Err((0, "invalid vertex data header".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_read_dequantized() {
+        // KHR_mesh_quantization stores attributes as normalized or plain integers.
+        let json = r#"{"asset": {"version": "2.0"}, "buffers": [{"byteLength": 16}],
+            "bufferViews": [
+                {"buffer": 0, "byteLength": 8, "byteStride": 4},
+                {"buffer": 0, "byteOffset": 8, "byteLength": 8}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5120, "normalized": true, "count": 2, "type": "VEC3"},
+                {"bufferView": 1, "componentType": 5123, "normalized": true, "count": 2, "type": "VEC2"},
+                {"bufferView": 1, "componentType": 5122, "count": 2, "type": "VEC2"}]}"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let mut data = vec![127, 0x80, 0, 0, 0xc0, 64, 1, 0];
+        for v in [65535u16, 0, 32768, 1] {
+            data.extend(v.to_le_bytes());
+        }
+        let buffers = [gltf::buffer::Data(data)];
+        let accessor = |i: usize| document.accessors().nth(i).unwrap();
+        // Signed normalized values are clamped at -1, as -128 would be slightly below it.
+        let bytes = read_dequantized::<3>(accessor(0), &buffers).unwrap();
+        assert_eq!(bytes[0], [1.0, -1.0, 0.0]);
+        assert_eq!(bytes[1], [-64.0 / 127.0, 64.0 / 127.0, 1.0 / 127.0]);
+        let shorts = read_dequantized::<2>(accessor(1), &buffers).unwrap();
+        assert_eq!(shorts, vec![[1.0, 0.0], [32768.0 / 65535.0, 1.0 / 65535.0]]);
+        // Without normalization the integers are used as is.
+        let integers = read_dequantized::<2>(accessor(2), &buffers).unwrap();
+        assert_eq!(integers, vec![[-1.0, 0.0], [-32768.0, 1.0]]);
+        // The number of components must match.
+        assert!(read_dequantized::<3>(accessor(1), &buffers).is_none());
+    }
+}
@@ -1,30 +1,209 @@
-use crate::vertex::mesh::CpuMesh;
+use crate::animation;
+use crate::axes::{AxisConversion, CoordinateSystem};
+use crate::lights::{CpuLights, Light};
+use crate::scene::{self, NodeHandle, ObjectInstance, Scene};
+use crate::texture::mipmap::{MipmapGenerator, mip_level_count};
+use crate::vertex::mesh::{CpuMesh, GpuMesh, MorphTarget};
+use crate::view::camera::{Camera, Projection};
 use crate::{fragment::mesh_object_textured::MeshObjectTextured, vertex::mesh_object::MeshObject};
 use anyhow::Context as _;
-use glam::{Mat4, Vec2, Vec3, Vec3A, Vec4, vec2, vec3, vec3a, vec4};
+use glam::{Mat4, UVec4, Vec2, Vec3, Vec3A, Vec4, vec2, vec3, vec3a};
 use log::*;
+use thiserror::Error;
 
-struct MeshIndex(usize);
+pub mod background;
+mod cache;
+pub mod export;
+mod mesh_file;
+mod meshopt;
+pub mod obj;
+pub mod ply;
+mod prepare;
+pub mod stl;
+mod structure;
+
+/// Errors while loading a file, naming the part of the document that failed.
+#[derive(Error, Debug)]
+pub enum LoaderError {
+    #[error("failed to import {path:?}: {source}")]
+    Import {
+        path: std::path::PathBuf,
+        source: gltf::Error,
+    },
+    #[error("failed to read {path:?}: {source}")]
+    Io {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("{path:?}, line {line}: {reason}")]
+    Parse {
+        path: std::path::PathBuf,
+        line: usize,
+        reason: String,
+    },
+    #[error("{path:?}: {reason}")]
+    Invalid {
+        path: std::path::PathBuf,
+        reason: String,
+    },
+    #[error("buffer view {view}: {reason}")]
+    BufferView { view: usize, reason: String },
+    #[error("the document does not contain a scene")]
+    NoScene,
+    #[error("there is no scene at index {0}")]
+    SceneIndex(usize),
+    #[error("there is no mesh at index {0}")]
+    MeshIndex(usize),
+    #[error("mesh {mesh_index} ({mesh:?}), primitive {primitive}: {reason}")]
+    Primitive {
+        mesh_index: usize,
+        mesh: Option<String>,
+        primitive: usize,
+        reason: String,
+    },
+    #[error("texture {texture_index} ({texture:?}): {reason}")]
+    Texture {
+        texture_index: usize,
+        texture: Option<String>,
+        reason: String,
+    },
+}
+
+/// Convert the indices of a primitive to the list topology it is drawn with, strips, fans and loops are expanded into
+/// separate primitives and incomplete trailing primitives are dropped.
+fn indices_to_list(mode: gltf::mesh::Mode, indices: &[u32]) -> (wgpu::PrimitiveTopology, Vec<u32>) {
+    use gltf::mesh::Mode;
+    use wgpu::PrimitiveTopology;
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#meshes-overview
+    let complete =
+        |per_primitive: usize| indices[..indices.len() - indices.len() % per_primitive].to_vec();
+    match mode {
+        Mode::Points => (PrimitiveTopology::PointList, indices.to_vec()),
+        Mode::Lines => (PrimitiveTopology::LineList, complete(2)),
+        Mode::LineStrip => (
+            PrimitiveTopology::LineList,
+            indices.windows(2).flatten().copied().collect(),
+        ),
+        Mode::LineLoop => {
+            let mut lines: Vec<u32> = indices.windows(2).flatten().copied().collect();
+            if let (Some(first), Some(last)) = (indices.first(), indices.last())
+                && indices.len() > 2
+            {
+                lines.extend([*last, *first]);
+            }
+            (PrimitiveTopology::LineList, lines)
+        }
+        Mode::Triangles => (PrimitiveTopology::TriangleList, complete(3)),
+        Mode::TriangleStrip => (
+            PrimitiveTopology::TriangleList,
+            (0..indices.len().saturating_sub(2))
+                .flat_map(|i| {
+                    // Every other triangle is flipped to keep the winding order consistent.
+                    let (b, c) = if i % 2 == 0 {
+                        (i + 1, i + 2)
+                    } else {
+                        (i + 2, i + 1)
+                    };
+                    [indices[i], indices[b], indices[c]]
+                })
+                .collect(),
+        ),
+        Mode::TriangleFan => (
+            PrimitiveTopology::TriangleList,
+            (0..indices.len().saturating_sub(2))
+                .flat_map(|i| [indices[i + 1], indices[i + 2], indices[0]])
+                .collect(),
+        ),
+    }
+}
+
+/// Read a float attribute, dequantizing the integer component types allowed by KHR_mesh_quantization. Returns none if
+/// the accessor doesn't have `N` components or has an unsupported component type.
+fn read_dequantized<const N: usize>(
+    accessor: gltf::Accessor,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Vec<[f32; N]>>
+where
+    [f32; N]: gltf::accessor::Item,
+    [i8; N]: gltf::accessor::Item,
+    [u8; N]: gltf::accessor::Item,
+    [i16; N]: gltf::accessor::Item,
+    [u16; N]: gltf::accessor::Item,
+{
+    use gltf::accessor::{DataType, Item, Iter};
+    fn convert<T: Copy, const N: usize>(
+        accessor: gltf::Accessor,
+        buffers: &[gltf::buffer::Data],
+        to_f32: impl Fn(T) -> f32,
+    ) -> Option<Vec<[f32; N]>>
+    where
+        [T; N]: Item,
+    {
+        let iter = Iter::<[T; N]>::new(accessor, |buffer: gltf::Buffer| {
+            buffers.get(buffer.index()).map(|data| &data[..])
+        })?;
+        Some(iter.map(|v| v.map(&to_f32)).collect())
+    }
+    if accessor.dimensions().multiplicity() != N {
+        return None;
+    }
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#animations has the normalization equations.
+    let normalized = accessor.normalized();
+    match accessor.data_type() {
+        DataType::F32 => convert::<f32, N>(accessor, buffers, |c| c),
+        DataType::I8 if normalized => {
+            convert::<i8, N>(accessor, buffers, |c| (c as f32 / 127.0).max(-1.0))
+        }
+        DataType::I8 => convert::<i8, N>(accessor, buffers, |c| c as f32),
+        DataType::U8 if normalized => convert::<u8, N>(accessor, buffers, |c| c as f32 / 255.0),
+        DataType::U8 => convert::<u8, N>(accessor, buffers, |c| c as f32),
+        DataType::I16 if normalized => {
+            convert::<i16, N>(accessor, buffers, |c| (c as f32 / 32767.0).max(-1.0))
+        }
+        DataType::I16 => convert::<i16, N>(accessor, buffers, |c| c as f32),
+        DataType::U16 if normalized => convert::<u16, N>(accessor, buffers, |c| c as f32 / 65535.0),
+        DataType::U16 => convert::<u16, N>(accessor, buffers, |c| c as f32),
+        DataType::U32 => None,
+    }
+}
 
 fn load_gltf_primitive_mesh(
     primitive: &gltf::Primitive,
-    document: &gltf::Document,
+    mesh: &gltf::Mesh,
     buffers: &[gltf::buffer::Data],
-    name: &Option<String>,
-) -> CpuMesh {
+) -> Result<CpuMesh, LoaderError> {
+    let name = mesh.name().map(|z| z.to_owned());
+    let primitive_error = |reason: String| LoaderError::Primitive {
+        mesh_index: mesh.index(),
+        mesh: name.clone(),
+        primitive: primitive.index(),
+        reason,
+    };
     let mut vertex_buffer = Vec::<Vec3>::new();
     let mut index_buffer: Vec<u32> = Vec::new();
     let mut normal_buffer: Option<Vec<Vec3A>> = None;
+    let mut tangent_buffer: Option<Vec<Vec4>> = None;
     let mut uv_buffer: Option<Vec<Vec2>> = None;
+    let mut uv1_buffer: Option<Vec<Vec2>> = None;
     let mut color_buffer: Option<Vec<Vec4>> = None;
+    let mut joints_buffer: Option<Vec<UVec4>> = None;
+    let mut weights_buffer: Option<Vec<Vec4>> = None;
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
 
-    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    // The float attributes may be quantized, so they are read through the accessors directly.
+    let read_attribute = |semantic: gltf::Semantic| primitive.get(&semantic);
 
     // Access vertex positions
-    if let Some(positions) = reader.read_positions() {
-        for p in positions {
-            vertex_buffer.push(vec3(p[0], p[1], p[2]));
-        }
+    let Some(positions) = read_attribute(gltf::Semantic::Positions)
+        .and_then(|accessor| read_dequantized::<3>(accessor, buffers))
+    else {
+        return Err(primitive_error(
+            "missing the POSITION attribute, or its type is unsupported".to_owned(),
+        ));
+    };
+    for p in positions {
+        vertex_buffer.push(vec3(p[0], p[1], p[2]));
     }
     // Access indices
     if let Some(indices) = reader.read_indices() {
@@ -39,26 +218,21 @@ fn load_gltf_primitive_mesh(
                 index_buffer.extend(iter);
             }
         }
+    } else {
+        // Non-indexed primitives use the vertices in order.
+        index_buffer.extend(0..vertex_buffer.len() as u32);
     }
 
     // Access colors
     if let Some(colors) = reader.read_colors(0) {
         let color_container = color_buffer.get_or_insert_default();
-        match colors {
-            gltf::mesh::util::ReadColors::RgbU8(_iter) => todo!(),
-            gltf::mesh::util::ReadColors::RgbU16(_iter) => todo!(),
-            gltf::mesh::util::ReadColors::RgbF32(iter) => {
-                color_container.extend(iter.map(|v| vec4(v[0], v[1], v[2], 1.0)));
-            }
-            gltf::mesh::util::ReadColors::RgbaU8(_iter) => todo!(),
-            gltf::mesh::util::ReadColors::RgbaU16(_iter) => todo!(),
-            gltf::mesh::util::ReadColors::RgbaF32(iter) => {
-                color_container.extend(iter.map(|v| vec4(v[0], v[1], v[2], v[3])));
-            }
-        }
+        // Normalized integers are mapped to [0, 1], rgb colors get an alpha of one.
+        color_container.extend(colors.into_rgba_f32().map(Vec4::from_array));
     }
     // Access normals
-    if let Some(normals) = reader.read_normals() {
+    if let Some(normals) = read_attribute(gltf::Semantic::Normals)
+        .and_then(|accessor| read_dequantized::<3>(accessor, buffers))
+    {
         let normal_container = normal_buffer.get_or_insert_default();
         // normal_container.resize(vertex_buffer.len(), Default::default());
 
@@ -69,31 +243,122 @@ fn load_gltf_primitive_mesh(
             // println!("normal: {:?}", normal_container[ni]);
         }
     }
+    // Authored tangents, these take precedence over generated ones.
+    if let Some(tangents) = read_attribute(gltf::Semantic::Tangents)
+        .and_then(|accessor| read_dequantized::<4>(accessor, buffers))
+    {
+        tangent_buffer = Some(tangents.into_iter().map(Vec4::from_array).collect());
+    }
     // Access texture coordinates (TexCoords)
-    if let Some(tex_coords) = reader.read_tex_coords(0) {
+    if let Some(tex_coords) = read_attribute(gltf::Semantic::TexCoords(0))
+        .and_then(|accessor| read_dequantized::<2>(accessor, buffers))
+    {
         let texture_container = uv_buffer.get_or_insert_default();
-        for tc in tex_coords.into_f32() {
+        for tc in tex_coords {
+            texture_container.push(vec2(tc[0], tc[1]));
+        }
+    }
+    // And the second set, which some materials use for specific textures.
+    if let Some(tex_coords) = read_attribute(gltf::Semantic::TexCoords(1))
+        .and_then(|accessor| read_dequantized::<2>(accessor, buffers))
+    {
+        let texture_container = uv1_buffer.get_or_insert_default();
+        for tc in tex_coords {
             texture_container.push(vec2(tc[0], tc[1]));
         }
     }
 
-    let mut this_mesh = CpuMesh::new(vertex_buffer, index_buffer);
+    // Skinning data, we only support a single set of four joints per vertex.
+    if let Some(joints) = reader.read_joints(0) {
+        let joints_container = joints_buffer.get_or_insert_default();
+        joints_container.extend(
+            joints
+                .into_u16()
+                .map(|j| UVec4::from_array(j.map(|v| v as u32))),
+        );
+    }
+    if let Some(weights) = reader.read_weights(0) {
+        let weights_container = weights_buffer.get_or_insert_default();
+        weights_container.extend(weights.into_f32().map(Vec4::from_array));
+    }
+
+    // Morph targets, attributes that a target doesn't displace are zero on the gpu, a target
+    // without positions still needs them for every vertex as the mesh indexes into them.
+    let read_deltas = |accessor: Option<gltf::Accessor>| {
+        accessor
+            .and_then(|accessor| read_dequantized::<3>(accessor, buffers))
+            .map(|deltas| deltas.into_iter().map(Vec3::from_array).collect())
+    };
+    let morph_targets: Vec<MorphTarget> = primitive
+        .morph_targets()
+        .map(|target| MorphTarget {
+            position: read_deltas(target.positions())
+                .unwrap_or_else(|| vec![Vec3::ZERO; vertex_buffer.len()]),
+            normal: read_deltas(target.normals()),
+            tangent: read_deltas(target.tangents()),
+        })
+        .collect();
+
+    let (topology, index_buffer) = indices_to_list(primitive.mode(), &index_buffer);
+    let mut this_mesh = CpuMesh::new(vertex_buffer, index_buffer).with_topology(topology);
     this_mesh.color = color_buffer;
     this_mesh.normal = normal_buffer;
+    this_mesh.tangents = tangent_buffer;
     this_mesh.uv = uv_buffer;
+    this_mesh.uv1 = uv1_buffer;
+    this_mesh.joints = joints_buffer;
+    this_mesh.weights = weights_buffer;
+    this_mesh.morph_targets = morph_targets;
     this_mesh.name = name.clone();
 
-    let tangents_calculated = this_mesh.calculate_tangents();
-    if !tangents_calculated {
-        warn!("Could not calculate tangents for {:?}", this_mesh.name);
+    // Validate before anything indexes into the attributes.
+    let vertex_count = this_mesh.position.len();
+    if let Some(index) = this_mesh
+        .index
+        .iter()
+        .find(|i| **i as usize >= vertex_count)
+    {
+        return Err(primitive_error(format!(
+            "index {index} is out of range for {vertex_count} vertices"
+        )));
+    }
+    let target_lengths = this_mesh.morph_targets.iter().flat_map(|target| {
+        [
+            ("target POSITION", Some(target.position.len())),
+            ("target NORMAL", target.normal.as_ref().map(|v| v.len())),
+            ("target TANGENT", target.tangent.as_ref().map(|v| v.len())),
+        ]
+    });
+    let attribute_lengths = [
+        ("NORMAL", this_mesh.normal.as_ref().map(|v| v.len())),
+        ("TANGENT", this_mesh.tangents.as_ref().map(|v| v.len())),
+        ("COLOR_0", this_mesh.color.as_ref().map(|v| v.len())),
+        ("TEXCOORD_0", this_mesh.uv.as_ref().map(|v| v.len())),
+        ("TEXCOORD_1", this_mesh.uv1.as_ref().map(|v| v.len())),
+        ("JOINTS_0", this_mesh.joints.as_ref().map(|v| v.len())),
+        ("WEIGHTS_0", this_mesh.weights.as_ref().map(|v| v.len())),
+    ];
+    for (attribute, length) in attribute_lengths.into_iter().chain(target_lengths) {
+        if let Some(length) = length.filter(|l| *l != vertex_count) {
+            return Err(primitive_error(format!(
+                "{attribute} has {length} elements for {vertex_count} vertices"
+            )));
+        }
+    }
+
+    if this_mesh.tangents.is_none() {
+        let tangents_calculated = this_mesh.calculate_tangents();
+        if !tangents_calculated {
+            warn!("Could not calculate tangents for {:?}", this_mesh.name);
+        }
     }
-    this_mesh
+    Ok(this_mesh)
 }
 
 fn load_gltf_meshes(
     document: &gltf::Document,
     buffers: &[gltf::buffer::Data],
-) -> Vec<(MeshIndex, CpuMesh)> {
+) -> Result<Vec<CpuMesh>, LoaderError> {
     let mut result = vec![];
     for scene in document.scenes() {
         for (node_index, node) in scene.nodes().enumerate() {
@@ -107,54 +372,82 @@ fn load_gltf_meshes(
                     continue;
                 }
 
-                let name = mesh.name().map(|z| z.to_owned());
-                let primitive = primitives.first().unwrap();
+                let primitive = &primitives[0];
                 {
-                    let this_mesh = load_gltf_primitive_mesh(primitive, document, buffers, &name);
-                    result.push((MeshIndex(mesh.index()), this_mesh));
+                    let this_mesh = load_gltf_primitive_mesh(primitive, &mesh, buffers)?;
+                    result.push(this_mesh);
                 }
             }
         }
     }
-    result
+    Ok(result)
 }
 
 pub fn load_gltf(
     document: &gltf::Document,
     buffers: &[gltf::buffer::Data],
     desired_index: usize,
-) -> CpuMesh {
-    load_gltf_meshes(&document, buffers)[desired_index]
-        .1
-        .clone()
-}
-
-fn gltf_to_rgba8unorm(image: &gltf::image::Data) -> image::RgbaImage {
-    match &image.format {
-        gltf::image::Format::R8 => todo!(),
-        gltf::image::Format::R8G8 => todo!(),
-        gltf::image::Format::R8G8B8 => {
-            let orig = image::ImageBuffer::<image::Rgb<u8>, _>::from_raw(
-                image.width,
-                image.height,
-                image.pixels.clone(),
-            )
-            .unwrap();
-            image::DynamicImage::ImageRgb8(orig).to_rgba8()
+) -> Result<CpuMesh, LoaderError> {
+    load_gltf_meshes(document, buffers)?
+        .into_iter()
+        .nth(desired_index)
+        .ok_or(LoaderError::MeshIndex(desired_index))
+}
+
+/// Convert any of the gltf image formats, returns none if the pixel data doesn't match the dimensions.
+fn gltf_to_rgba8unorm(image: &gltf::image::Data) -> Option<image::RgbaImage> {
+    use gltf::image::Format;
+    use image::{DynamicImage, ImageBuffer};
+    let (width, height) = (image.width, image.height);
+    // The gltf crate stores wider channels as native endian bytes.
+    let pixels_u16 = || -> Vec<u16> {
+        image
+            .pixels
+            .chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+            .collect()
+    };
+    let pixels_f32 = || -> Vec<f32> {
+        image
+            .pixels
+            .chunks_exact(4)
+            .map(|c| f32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .collect()
+    };
+    // Single and dual channel images come from grayscale (with alpha) sources, so they convert as luma.
+    let dynamic = match &image.format {
+        Format::R8 => {
+            DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, image.pixels.clone())?)
         }
-        gltf::image::Format::R8G8B8A8 => image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(
-            image.width,
-            image.height,
-            image.pixels.clone(),
-        )
-        .unwrap(),
-        gltf::image::Format::R16 => todo!(),
-        gltf::image::Format::R16G16 => todo!(),
-        gltf::image::Format::R16G16B16 => todo!(),
-        gltf::image::Format::R16G16B16A16 => todo!(),
-        gltf::image::Format::R32G32B32FLOAT => todo!(),
-        gltf::image::Format::R32G32B32A32FLOAT => todo!(),
-    }
+        Format::R8G8 => {
+            DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, image.pixels.clone())?)
+        }
+        Format::R8G8B8 => {
+            DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, image.pixels.clone())?)
+        }
+        Format::R8G8B8A8 => {
+            return ImageBuffer::from_raw(width, height, image.pixels.clone());
+        }
+        Format::R16 => {
+            DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, pixels_u16())?)
+        }
+        Format::R16G16 => {
+            DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, pixels_u16())?)
+        }
+        Format::R16G16B16 => {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, pixels_u16())?)
+        }
+        Format::R16G16B16A16 => {
+            DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, pixels_u16())?)
+        }
+        Format::R32G32B32FLOAT => {
+            DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, pixels_f32())?)
+        }
+        Format::R32G32B32A32FLOAT => {
+            DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, pixels_f32())?)
+        }
+    };
+    Some(dynamic.to_rgba8())
 }
 
 trait WrappingModeToWgpu {
@@ -182,6 +475,8 @@ impl MagFilterModeToWgpu for gltf::texture::MagFilter {
 }
 trait MinFilterModeToWgpu {
     fn to_wgpu(&self) -> wgpu::FilterMode;
+    /// The filter between mip levels, none if the filter only samples the first level.
+    fn mipmap_filter(&self) -> Option<wgpu::FilterMode>;
 }
 impl MinFilterModeToWgpu for gltf::texture::MinFilter {
     fn to_wgpu(&self) -> wgpu::FilterMode {
@@ -190,10 +485,68 @@ impl MinFilterModeToWgpu for gltf::texture::MinFilter {
             gltf::texture::MinFilter::Linear => wgpu::FilterMode::Linear,
             gltf::texture::MinFilter::NearestMipmapNearest => wgpu::FilterMode::Nearest,
             gltf::texture::MinFilter::LinearMipmapNearest => wgpu::FilterMode::Linear,
-            gltf::texture::MinFilter::NearestMipmapLinear => wgpu::FilterMode::Linear,
+            gltf::texture::MinFilter::NearestMipmapLinear => wgpu::FilterMode::Nearest,
             gltf::texture::MinFilter::LinearMipmapLinear => wgpu::FilterMode::Linear,
         }
     }
+    fn mipmap_filter(&self) -> Option<wgpu::FilterMode> {
+        match self {
+            gltf::texture::MinFilter::Nearest | gltf::texture::MinFilter::Linear => None,
+            gltf::texture::MinFilter::NearestMipmapNearest
+            | gltf::texture::MinFilter::LinearMipmapNearest => Some(wgpu::FilterMode::Nearest),
+            gltf::texture::MinFilter::NearestMipmapLinear
+            | gltf::texture::MinFilter::LinearMipmapLinear => Some(wgpu::FilterMode::Linear),
+        }
+    }
+}
+
+/// The anisotropy for samplers that filter linearly, wgpu ignores it on devices without anisotropic filtering.
+const MAX_ANISOTROPY: u16 = 16;
+
+trait TextureTransformToUniform {
+    fn to_uniform(&self) -> crate::texture::TextureTransform;
+    fn tex_coord_override(&self) -> Option<u32>;
+}
+impl TextureTransformToUniform for gltf::texture::TextureTransform<'_> {
+    fn to_uniform(&self) -> crate::texture::TextureTransform {
+        crate::texture::TextureTransform {
+            offset: Vec2::from_array(self.offset()),
+            scale: Vec2::from_array(self.scale()),
+            rotation: self.rotation(),
+            ..Default::default()
+        }
+    }
+    fn tex_coord_override(&self) -> Option<u32> {
+        self.tex_coord()
+    }
+}
+impl TextureTransformToUniform for gltf::json::extensions::texture::TextureTransform {
+    fn to_uniform(&self) -> crate::texture::TextureTransform {
+        crate::texture::TextureTransform {
+            offset: Vec2::from_array(self.offset.0),
+            scale: Vec2::from_array(self.scale.0),
+            rotation: self.rotation.0,
+            ..Default::default()
+        }
+    }
+    fn tex_coord_override(&self) -> Option<u32> {
+        self.tex_coord
+    }
+}
+
+/// The gltf crate only exposes the texture transform for the plain texture info, for the normal and occlusion textures
+/// we have to parse it from the raw extension value.
+fn parse_texture_transform(
+    value: Option<&gltf::json::Value>,
+) -> Option<gltf::json::extensions::texture::TextureTransform> {
+    let value = value?.clone();
+    match gltf::json::deserialize::from_value(value) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warn!("Could not parse KHR_texture_transform: {e:?}");
+            None
+        }
+    }
 }
 
 trait TransformToGlam {
@@ -220,28 +573,48 @@ impl TransformToGlam for gltf::scene::Transform {
     }
 }
 
+/// Upload the image and generate its mip levels.
 pub fn load_gltf_texture(
     context: &crate::Context,
     image: &gltf::image::Data,
     texture_format: wgpu::TextureFormat,
-) -> wgpu::Texture {
+    mipmap_generator: &mut MipmapGenerator,
+) -> Option<wgpu::Texture> {
     // Do we need to do any color space mapping?
-    let rgba8_image = gltf_to_rgba8unorm(image);
+    let rgba8_image = gltf_to_rgba8unorm(image)?;
+    Some(upload_rgba8_texture(
+        context,
+        &rgba8_image,
+        texture_format,
+        mipmap_generator,
+    ))
+}
+
+/// Upload an rgba8 image and generate its mip levels.
+fn upload_rgba8_texture(
+    context: &crate::Context,
+    rgba8_image: &image::RgbaImage,
+    texture_format: wgpu::TextureFormat,
+    mipmap_generator: &mut MipmapGenerator,
+) -> wgpu::Texture {
     let texture_size = wgpu::Extent3d {
         width: rgba8_image.width(),
         height: rgba8_image.height(),
         depth_or_array_layers: 1,
     };
-    // Lets just do a single mip level for now.
     let texture = context.device.create_texture(&wgpu::TextureDescriptor {
         size: texture_size,
-        mip_level_count: 1,
+        mip_level_count: mip_level_count(texture_size.width, texture_size.height),
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
         // format: wgpu::TextureFormat::Rgba8Unorm,
         format: texture_format,
         // format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        // Copied from by the exporter.
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::RENDER_ATTACHMENT,
         label: None, // TODO
         view_formats: &[],
     });
@@ -264,287 +637,943 @@ pub fn load_gltf_texture(
         },
         texture_size,
     );
-    context.queue.submit([]);
+    if !mipmap_generator.generate(context, &texture) {
+        warn!("Could not generate mip levels for {texture_format:?}");
+    }
     texture
 }
 
-fn load_sampled_texture(
-    context: &crate::Context,
+/// The image a texture samples, the ktx2 image of `KHR_texture_basisu` takes precedence over the regular source, which
+/// is then used as the fallback. Basis universal payloads can't be transcoded, those textures always use the fallback.
+fn texture_image<'a>(
+    texture: &gltf::Texture<'a>,
+    images: &[gltf::Image<'a>],
+) -> Option<gltf::Image<'a>> {
+    // https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_texture_basisu
+    texture
+        .extension_value("KHR_texture_basisu")
+        .and_then(|v| v.get("source"))
+        .and_then(|v| v.as_u64())
+        .and_then(|index| images.get(index as usize).cloned())
+        .or(texture.source())
+}
+
+/// Read the encoded bytes of a ktx2 image, none if the image is in another format.
+fn read_ktx2_bytes(
+    image: &gltf::Image<'_>,
+    buffers: &[gltf::buffer::Data],
+    base: Option<&std::path::Path>,
+) -> Option<Result<Vec<u8>, String>> {
+    const KTX2_MIME_TYPE: &str = "image/ktx2";
+    match image.source() {
+        gltf::image::Source::View { view, mime_type } => {
+            let start = view.offset();
+            let bytes = buffers
+                .get(view.buffer().index())
+                .and_then(|b| b.get(start..start + view.length()))
+                .map(|b| b.to_vec())
+                .ok_or_else(|| format!("buffer view {} is out of bounds", view.index()));
+            let is_ktx2 = mime_type == KTX2_MIME_TYPE
+                || bytes
+                    .as_ref()
+                    .is_ok_and(|b| crate::texture::ktx2::Ktx2Image::is_ktx2(b));
+            is_ktx2.then_some(bytes)
+        }
+        gltf::image::Source::Uri { uri, mime_type } => {
+            let is_ktx2 = mime_type == Some(KTX2_MIME_TYPE) || uri.ends_with(".ktx2");
+            if !is_ktx2 {
+                return None;
+            }
+            if uri.starts_with("data:") {
+                return Some(Err("ktx2 images in data uris are not supported".to_owned()));
+            }
+            let path = base.map(|b| b.join(uri)).unwrap_or_else(|| uri.into());
+            Some(std::fs::read(&path).map_err(|e| format!("failed to read {path:?}: {e}")))
+        }
+    }
+}
+
+/// Identifies an uploaded texture, the same image may be sampled both as srgb and as linear data.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+struct SampledTextureKey {
+    texture_index: usize,
+    format: wgpu::TextureFormat,
+}
+
+/// Each primitive is only loaded and uploaded once, even if many nodes reference its mesh.
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+struct PrimitiveKey {
+    mesh_index: usize,
+    primitive_index: usize,
+}
+
+/// Textures and meshes that are already on the gpu when the scene is built, anything missing is loaded on first use.
+#[derive(Debug, Default)]
+struct Uploaded {
+    textures: std::collections::HashMap<SampledTextureKey, crate::texture::SampledTexture>,
+    meshes: std::collections::HashMap<PrimitiveKey, GpuMesh>,
+}
+
+/// The pixels of a texture, decoded on the cpu but not yet uploaded.
+#[derive(Debug, Clone)]
+enum DecodedImage {
+    Rgba8(image::RgbaImage),
+    Ktx2 {
+        image: crate::texture::ktx2::Ktx2Image,
+        /// The regular image of the texture, kept for formats that can only be used if the device supports them.
+        fallback: Option<image::RgbaImage>,
+    },
+}
+
+fn texture_error(texture: &gltf::Texture<'_>, reason: String) -> LoaderError {
+    LoaderError::Texture {
+        texture_index: texture.index(),
+        texture: texture
+            .name()
+            .or(texture.source().and_then(|i| i.name()))
+            .map(|z| z.to_owned()),
+        reason,
+    }
+}
+
+/// Read and decode the image of a texture, this doesn't touch the gpu.
+fn decode_texture(
     texture: &gltf::Texture<'_>,
-    texture_format: wgpu::TextureFormat,
+    images: &[gltf::Image<'_>],
     buffers: &[gltf::buffer::Data],
-) -> anyhow::Result<crate::texture::SampledTexture> {
-    let sampler = texture.sampler();
-    let image_source = texture.source();
-    let image_data = gltf::image::Data::from_source(image_source.source(), None, buffers)?;
-    // println!(
-    //     "image data: {:?}, {:?}",
-    //     image_data.format, image_data.width,
-    // );
-    let texture = load_gltf_texture(&context, &image_data, texture_format);
+    base: Option<&std::path::Path>,
+) -> Result<DecodedImage, LoaderError> {
+    let image = texture_image(texture, images)
+        .ok_or_else(|| texture_error(texture, "texture has no image source".to_owned()))?;
+    let Some(bytes) = read_ktx2_bytes(&image, buffers, base) else {
+        return decode_rgba8_image(texture, &image, buffers, base).map(DecodedImage::Rgba8);
+    };
+    // With KHR_texture_basisu the regular source is a png or jpeg for loaders that can't use the ktx2 image.
+    let fallback = texture.source().filter(|i| i.index() != image.index());
+    let ktx2 =
+        bytes.and_then(|b| crate::texture::ktx2::Ktx2Image::parse(&b).map_err(|e| e.to_string()));
+    match (ktx2, fallback) {
+        (Ok(ktx2), Some(fallback)) if !ktx2.has_rgba8_fallback() => Ok(DecodedImage::Ktx2 {
+            image: ktx2,
+            fallback: Some(decode_rgba8_image(texture, &fallback, buffers, base)?),
+        }),
+        (Ok(ktx2), _) => Ok(DecodedImage::Ktx2 {
+            image: ktx2,
+            fallback: None,
+        }),
+        (Err(e), Some(fallback)) => {
+            warn!("texture {}: {e}, using its fallback image", texture.index());
+            decode_rgba8_image(texture, &fallback, buffers, base).map(DecodedImage::Rgba8)
+        }
+        (Err(e), None) => Err(texture_error(texture, e)),
+    }
+}
+
+/// Decode a png, jpeg or other regular image to rgba8.
+fn decode_rgba8_image(
+    texture: &gltf::Texture<'_>,
+    image: &gltf::Image<'_>,
+    buffers: &[gltf::buffer::Data],
+    base: Option<&std::path::Path>,
+) -> Result<image::RgbaImage, LoaderError> {
+    let image_data = gltf::image::Data::from_source(image.source(), base, buffers)
+        .map_err(|e| texture_error(texture, e.to_string()))?;
+    gltf_to_rgba8unorm(&image_data).ok_or_else(|| {
+        texture_error(
+            texture,
+            format!(
+                "pixel data of {:?} does not match {}x{}",
+                image_data.format, image_data.width, image_data.height
+            ),
+        )
+    })
+}
+
+/// Upload a decoded image and create the sampler of the texture.
+fn upload_sampled_texture(
+    context: &crate::Context,
+    key: SampledTextureKey,
+    structure: &structure::TextureStructure,
+    decoded: &DecodedImage,
+    mipmap_generator: &mut MipmapGenerator,
+) -> Result<crate::texture::SampledTexture, LoaderError> {
+    let texture_format = key.format;
+    let texture = match decoded {
+        DecodedImage::Ktx2 {
+            image,
+            fallback: Some(fallback),
+        } if !image.is_supported(context) => {
+            upload_rgba8_texture(context, fallback, texture_format, mipmap_generator)
+        }
+        DecodedImage::Ktx2 { image, .. } => image
+            .clone()
+            .with_srgb(texture_format.is_srgb())
+            .to_texture(context)
+            .map_err(|e| LoaderError::Texture {
+                texture_index: key.texture_index,
+                texture: structure.name.clone(),
+                reason: e.to_string(),
+            })?,
+        DecodedImage::Rgba8(rgba8_image) => {
+            upload_rgba8_texture(context, rgba8_image, texture_format, mipmap_generator)
+        }
+    };
+    let (mag_filter, min_filter, mipmap_filter) = (
+        structure.mag_filter,
+        structure.min_filter,
+        structure.mipmap_filter,
+    );
+    // Anisotropic filtering requires all filters to be linear.
+    let anisotropy_clamp = if [Some(mag_filter), Some(min_filter), mipmap_filter]
+        .iter()
+        .all(|f| *f == Some(wgpu::FilterMode::Linear))
+    {
+        MAX_ANISOTROPY
+    } else {
+        1
+    };
     Ok(crate::texture::SampledTexture {
         sampler: context.device.create_sampler(&wgpu::SamplerDescriptor {
-            // S(U) and T(V): https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_metallicroughnesstexture
-            address_mode_u: sampler.wrap_s().to_wgpu(),
-            address_mode_v: sampler.wrap_t().to_wgpu(),
-            address_mode_w: sampler.wrap_t().to_wgpu(), // no w in gltf?
-            mag_filter: sampler
-                .mag_filter()
-                .unwrap_or(gltf::texture::MagFilter::Linear)
-                .to_wgpu(), // Nearest
-            min_filter: sampler
-                .min_filter()
-                .unwrap_or(gltf::texture::MinFilter::Linear)
-                .to_wgpu(),
-            mipmap_filter: wgpu::FilterMode::Linear,
+            address_mode_u: structure.address_mode_u,
+            address_mode_v: structure.address_mode_v,
+            address_mode_w: structure.address_mode_v, // no w in gltf?
+            mag_filter,
+            min_filter,
+            mipmap_filter: mipmap_filter.unwrap_or(wgpu::FilterMode::Nearest),
+            // Filters without mipmapping only sample the first level.
+            lod_max_clamp: if mipmap_filter.is_some() { 32.0 } else { 0.0 },
+            anisotropy_clamp,
             ..Default::default()
         }),
         texture: texture,
         texture_type: crate::texture::TextureType::None,
+        tex_coord: 0,
+        transform: Default::default(),
     })
 }
 
-pub fn load_gltf_objects(
-    context: &crate::Context,
-    gltf_path: &std::path::Path,
-) -> Result<Vec<MeshObjectTextured>, anyhow::Error> {
-    let (document, buffers, images) = gltf::import(gltf_path)?;
-    let _ = images;
-    info!("document: {document:#?}");
-    // This doesn't handle instancing nicely atm... but this is already a non-tested hour long bender.
-
-    // Okay, so we have a sampler specification.
-    // Textures then point to sampler specification.
-    //
-    // Nodes builds the scene tree-wise.
-    //  Nodes have a mesh and potentially children.
-    //  Nodes have a transform.
-    //
-    // Mesh has a name and a list of primitives.
-    //   Primitive holds:
-    //      Material, model, and mesh attributes (positions, normals, texcoords)
-    //
-    // Material has... a bunch of properties which we can't handle, but for the helmet they're 1.0...
-    //   pbr_metallic_roughness:
-    //       base color texture
-    //       metallic roughness texture
-    //       normal t
-    //  normal texture
-    //  occlusion texture
-    //  emissive texture
-    //   (emissive factor)
-    //
-    // Accessors, some buffers and buffer views, which I'm not sure how to deal with.
-
-    // Okay, so not the end of the world.
-    //  First: Obtain auxiliary data; collect the textures & samplers.
-    //         Collect a bunch of raw meshes.
-    //  Traverse the nodes to propagate the transforms and combine the textures with the meshes as MeshObjectTextured
-
-    let meshes_by_index = load_gltf_meshes(&document, &buffers);
-
-    // Load some textures... this doesn't actually get me the samplers.
-    // let textures: Vec<wgpu::Texture> = images
-    //     .iter()
-    //     .map(|z| load_gltf_texture(&context, z))
-    //     .collect();
-
-    let textures_by_index: Vec<_> = document.textures().collect();
-
-    #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
-    struct SampledTextureKey {
-        texture_index: usize,
-        format: wgpu::TextureFormat,
-    }
-    #[derive(Debug)]
-    struct SampledTextureCache<'a> {
-        cache: std::collections::HashMap<SampledTextureKey, crate::texture::SampledTexture>,
-        textures_by_index: Vec<gltf::Texture<'a>>,
-        context: crate::Context,
-        buffers: Vec<gltf::buffer::Data>,
-    }
-    impl<'a> SampledTextureCache<'a> {
-        pub fn retrieve(
-            &mut self,
-            texture_index: usize,
-            format: wgpu::TextureFormat,
-        ) -> crate::texture::SampledTexture {
-            self.cache
-                .entry(SampledTextureKey {
-                    texture_index,
-                    format,
-                })
-                .or_insert_with(|| {
-                    load_sampled_texture(
-                        &self.context,
-                        &self.textures_by_index[texture_index],
-                        format,
-                        &self.buffers,
-                    )
-                    .expect("loading texture failed")
-                })
-                .clone()
-        }
+/// Collect the global factors of a material.
+fn load_material_uniform(material: &gltf::Material) -> crate::texture::MaterialUniform {
+    let pbr = material.pbr_metallic_roughness();
+    crate::texture::MaterialUniform {
+        base_color_factor: Vec4::from_array(pbr.base_color_factor()),
+        emissive_factor: Vec3::from_array(material.emissive_factor()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_normaltextureinfo_scale
+        normal_scale: material.normal_texture().map(|t| t.scale()).unwrap_or(1.0),
+        // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_occlusiontextureinfo_strength
+        occlusion_strength: material
+            .occlusion_texture()
+            .map(|t| t.strength())
+            .unwrap_or(1.0),
+        // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_alphacutoff
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => crate::texture::AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => crate::texture::AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => crate::texture::AlphaMode::Blend,
+        },
+        // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_doublesided
+        double_sided: material.double_sided() as u32,
+        ..Default::default()
     }
-    let mut sampled_texture_cache: SampledTextureCache = SampledTextureCache {
-        cache: Default::default(),
-        textures_by_index: textures_by_index.clone(),
-        context: context.clone(),
-        buffers: buffers.clone(),
-    };
+}
 
-    // Okay, so now we have the textures & samplers combined at the ready... now, we should be able to mostly iterate
-    // through the nodes, fiddle a bit with transforms and when we reach a mesh with a material check its material and
-    // then pick resources from the our textures that are now ready to go.
+/// Convert a KHR_lights_punctual light, placing it with the world transform of the node it is attached to.
+fn load_gltf_light(light: &gltf::khr_lights_punctual::Light, transform: &Mat4) -> Light {
+    use gltf::khr_lights_punctual::Kind;
+    // https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_lights_punctual#light-types
+    // Lights point along the local -z axis of their node.
+    let position = transform.transform_point3(Vec3::ZERO);
+    let direction = transform
+        .transform_vector3(vec3(0.0, 0.0, -1.0))
+        .normalize_or_zero();
+    let base = match light.kind() {
+        Kind::Directional => Light::directional(),
+        Kind::Point => Light::omni(),
+        Kind::Spot {
+            inner_cone_angle,
+            outer_cone_angle,
+        } => Light::spot().with_cone_angles(inner_cone_angle, outer_cone_angle),
+    };
+    base.with_position(position)
+        .with_direction(direction)
+        .with_color(light.color())
+        .with_intensity(light.intensity())
+        .with_range(light.range().unwrap_or(0.0))
+}
 
-    let mut output = vec![];
+/// Convert a gltf camera, placing it with the world transform of the node it is attached to.
+fn load_gltf_camera(camera: &gltf::Camera, transform: &Mat4) -> Camera {
+    use gltf::camera::Projection as GltfProjection;
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#cameras
+    // Cameras look along the local -z axis of their node, with +y up.
+    let eye = transform.transform_point3(Vec3::ZERO);
+    let forward = transform
+        .transform_vector3(vec3(0.0, 0.0, -1.0))
+        .normalize_or_zero();
+    let up = transform
+        .transform_vector3(vec3(0.0, 1.0, 0.0))
+        .normalize_or_zero();
+    // Camera is expressed as a target, place it at unit distance along the view direction.
+    let mut res = Camera {
+        eye,
+        target: eye + forward,
+        up,
+        ..Camera::new(1, 1)
+    };
+    match camera.projection() {
+        GltfProjection::Perspective(p) => {
+            res.fovy = p.yfov().to_degrees();
+            res.znear = p.znear();
+            res.zfar = p.zfar().unwrap_or(f32::INFINITY);
+            if let Some(aspect) = p.aspect_ratio() {
+                res.aspect = aspect;
+            }
+            res.projection = Projection::Perspective;
+        }
+        GltfProjection::Orthographic(o) => {
+            res.znear = o.znear();
+            res.zfar = o.zfar();
+            if o.ymag() != 0.0 {
+                res.aspect = o.xmag() / o.ymag();
+            }
+            res.projection = Projection::Orthographic { ymag: o.ymag() };
+        }
+    }
+    res
+}
 
-    // Need to recursively traverse the tree of nodes, lets keep a stack to do so.
-    #[derive(Debug, Clone)]
-    struct Stack<'a> {
-        transform: Mat4,
-        nodes: Vec<gltf::Node<'a>>,
+/// Convert the node hierarchy, with the transforms decomposed such that animations can replace parts of them.
+fn load_gltf_nodes(document: &gltf::Document) -> Vec<scene::Node> {
+    let mut nodes: Vec<scene::Node> = document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            let weights = node
+                .weights()
+                .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+                .map(|w| w.to_vec())
+                .unwrap_or_default();
+            scene::Node {
+                name: node.name().map(|z| z.to_owned()),
+                translation: Vec3::from_array(translation),
+                rotation: glam::Quat::from_array(rotation),
+                scale: Vec3::from_array(scale),
+                weights,
+                extras: node.extras().as_ref().map(|z| z.get().to_owned()),
+                skin: node.skin().map(|skin| skin.index()),
+                ..Default::default()
+            }
+        })
+        .collect();
+    for node in document.nodes() {
+        for child in node.children() {
+            nodes[child.index()].parent = Some(NodeHandle(node.index()));
+        }
     }
-    // let flat_nodes = document.nodes().collect::<Vec<_>>();
-    let mut stack = std::collections::VecDeque::new();
+    nodes
+}
 
-    // let flat_nodes: Vec<_> = document.nodes().collect();
-    stack.push_back(Stack {
-        transform: Mat4::IDENTITY,
-        nodes: document
-            .scenes()
-            .next()
-            .expect("exected at least one scene")
-            .nodes()
+/// Load the joints and inverse bind matrices of a skin.
+fn load_gltf_skin(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> scene::Skin {
+    let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+    scene::Skin {
+        joints: skin
+            .joints()
+            .map(|joint| NodeHandle(joint.index()))
             .collect(),
-    });
-    let flat_materials = document.materials().collect::<Vec<_>>();
-    while let Some(top) = stack.pop_front() {
-        let nodes_this_level = top.nodes;
-
-        for this_node in nodes_this_level.iter() {
-            // Apply this nodes' transform.
-            let this_transform = top.transform * this_node.transform().to_glam();
-            println!("this_transform: {this_transform:#?}");
-
-            if let Some(mesh) = this_node.mesh() {
-                // Okay we have a mesh... now we need to do actual hard work to build our desired output object.
-                // Retrieve the mesh from our already processed entries.
-                let actual_geometry = meshes_by_index.iter().find(|z| z.0.0 == mesh.index());
-                // if actual_geometry.is_none() {
-                //     warn!("Missing geometry for {this_node:?}");
-                //     continue;
-                // }
-                // let actual_geometry = actual_geometry.unwrap();
-                // This cuts a corner, but we don't handle multiple primitives atm anyway.
-                let primitives = mesh.primitives().collect::<Vec<_>>();
-                // if primitives.len() > 1 {
-                //     todo!("we don't handle meshes with multiple primitives");
-                // }
-                for this_primitive in primitives {
-                    let mut this_primitive_textures = vec![];
-                    // Now, we do something with the material
-                    if let Some(material_index) = this_primitive.material().index() {
-                        let this_material = &flat_materials[material_index];
-                        // let double_sided = this_material.double_sided();
-                        let alpha_mode = this_material.alpha_mode();
-                        if alpha_mode != gltf::material::AlphaMode::Opaque {
-                            //todo!();
-                        }
-
-                        if let Some(emissive_texture) = this_material.emissive_texture() {
-                            let texture_index = emissive_texture.texture().index();
-                            // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_emissivetexture
-                            // is in srgb
-                            let mut with_sampler = sampled_texture_cache
-                                .retrieve(texture_index, wgpu::TextureFormat::Rgba8UnormSrgb);
-                            with_sampler.texture_type = crate::texture::TextureType::Emissive;
-                            this_primitive_textures.push(with_sampler);
-                        }
-
-                        if let Some(base_color_texture) =
-                            this_material.pbr_metallic_roughness().base_color_texture()
-                        {
-                            let texture_index = base_color_texture.texture().index();
-                            // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_basecolortexture
-                            // must be srgb
-                            let mut with_sampler = sampled_texture_cache
-                                .retrieve(texture_index, wgpu::TextureFormat::Rgba8UnormSrgb);
-                            with_sampler.texture_type = crate::texture::TextureType::BaseColor;
-                            this_primitive_textures.push(with_sampler);
-                        }
-
-                        if let Some(metallic_roughness_texture) = this_material
-                            .pbr_metallic_roughness()
-                            .metallic_roughness_texture()
-                        {
-                            let texture_index = metallic_roughness_texture.texture().index();
-                            // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_metallicroughnesstexture
-                            // is in linear transform function, so NOT srgb!
-                            let mut with_sampler = sampled_texture_cache
-                                .retrieve(texture_index, wgpu::TextureFormat::Rgba8Unorm);
-                            with_sampler.texture_type =
-                                crate::texture::TextureType::MetallicRoughness;
-                            this_primitive_textures.push(with_sampler);
-                        }
-                        // let metallic_factor = this_material.pbr_metallic_roughness().metallic_factor();
-                        // let base_color_factor =
-                        //     this_material.pbr_metallic_roughness().base_color_factor();
-                        // let roughness_factor =
-                        //     this_material.pbr_metallic_roughness().roughness_factor();
-                        // I should do something with these global factors.
-
-                        if let Some(normal_texture) = this_material.normal_texture() {
-                            // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_normaltexture
-                            // Linear transfer function.
-                            let texture_index = normal_texture.texture().index();
-                            let mut with_sampler = sampled_texture_cache
-                                .retrieve(texture_index, wgpu::TextureFormat::Rgba8Unorm);
-                            with_sampler.texture_type = crate::texture::TextureType::Normal;
-                            this_primitive_textures.push(with_sampler);
-                        }
-                        if let Some(occlusion_texture) = this_material.occlusion_texture() {
-                            let texture_index = occlusion_texture.texture().index();
-                            // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_occlusiontexture
-                            // Linear transfer function.
-                            let mut with_sampler = sampled_texture_cache
-                                .retrieve(texture_index, wgpu::TextureFormat::Rgba8Unorm);
-                            with_sampler.texture_type = crate::texture::TextureType::Occlusion;
-                            this_primitive_textures.push(with_sampler);
-                        }
-                    }
-
-                    // Now that we have processed the material, we have obtained the textures... we can instantiate our
-                    // desired MeshObjectTextured.
-                    let cpu_mesh = load_gltf_primitive_mesh(
-                        &this_primitive,
-                        &document,
-                        &buffers,
-                        &mesh.name().map(|z| z.to_owned()),
-                    );
-                    let gpu_mesh = cpu_mesh.to_gpu(&context);
-                    let mut mesh_object = MeshObject::new(context.clone(), gpu_mesh);
-                    mesh_object.set_single_transform(&this_transform);
-                    mesh_object.replace_gpu_data();
-
-                    output.push(MeshObjectTextured::new(
-                        context.clone(),
-                        mesh_object,
-                        &this_primitive_textures,
-                    ));
+        // When absent, the inverse bind matrices are identity matrices.
+        inverse_bind_matrices: reader
+            .read_inverse_bind_matrices()
+            .map(|iter| iter.map(|m| Mat4::from_cols_array_2d(&m)).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Load the keyframes of all channels of an animation, channels with invalid data are skipped.
+fn load_gltf_animation(
+    animation: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+) -> animation::Animation {
+    use gltf::animation::util::ReadOutputs;
+    let mut channels = vec![];
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+        let times: Vec<f32> = inputs.collect();
+        let (property, values, components): (_, Vec<f32>, _) = match outputs {
+            ReadOutputs::Translations(iter) => (
+                animation::ChannelProperty::Translation,
+                iter.flatten().collect(),
+                3,
+            ),
+            ReadOutputs::Rotations(iter) => (
+                animation::ChannelProperty::Rotation,
+                iter.into_f32().flatten().collect(),
+                4,
+            ),
+            ReadOutputs::Scales(iter) => (
+                animation::ChannelProperty::Scale,
+                iter.flatten().collect(),
+                3,
+            ),
+            ReadOutputs::MorphTargetWeights(iter) => {
+                let values: Vec<f32> = iter.into_f32().collect();
+                // The weights of all targets are stored per element, derive the target count from the totals.
+                let elements = match channel.sampler().interpolation() {
+                    gltf::animation::Interpolation::CubicSpline => times.len() * 3,
+                    _ => times.len(),
+                };
+                let components = values.len().checked_div(elements).unwrap_or(0);
+                (animation::ChannelProperty::MorphWeights, values, components)
+            }
+        };
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Step => animation::Interpolation::Step,
+            gltf::animation::Interpolation::Linear => animation::Interpolation::Linear,
+            gltf::animation::Interpolation::CubicSpline => animation::Interpolation::CubicSpline,
+        };
+        let this_channel = animation::Channel {
+            node: NodeHandle(channel.target().node().index()),
+            property,
+            interpolation,
+            times,
+            values,
+            components,
+        };
+        if !this_channel.is_valid() {
+            warn!(
+                "Skipping invalid channel of animation {:?} on node {:?}",
+                animation.name(),
+                this_channel.node
+            );
+            continue;
+        }
+        channels.push(this_channel);
+    }
+    animation::Animation {
+        name: animation.name().map(|z| z.to_owned()),
+        channels,
+    }
+}
+
+/// Options that control what is loaded from a file.
+#[derive(Clone, Debug)]
+pub struct LoadOptions {
+    /// The scene to load, the default scene of the document, or its first scene, if none.
+    pub scene: Option<usize>,
+    /// The convention of the file, glTF files are always in the glTF convention but other formats vary.
+    pub coordinate_system: CoordinateSystem,
+    /// The convention of the world the contents are loaded into.
+    pub world: CoordinateSystem,
+    /// Scale from the units of the file to the units of the world, like 0.001 for millimeters to meters.
+    pub unit_scale: f32,
+    /// Directory for caching the structure and the prepared meshes and textures of gltf files, none to not cache. A
+    /// load from the cache skips importing the document, generating tangents and decoding images.
+    pub cache_dir: Option<std::path::PathBuf>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            coordinate_system: CoordinateSystem::GLTF,
+            world: CoordinateSystem::GLTF,
+            unit_scale: 1.0,
+            cache_dir: None,
+        }
+    }
+}
+
+impl LoadOptions {
+    pub fn with_scene(mut self, scene: usize) -> Self {
+        self.scene = Some(scene);
+        self
+    }
+    pub fn with_coordinate_system(mut self, coordinate_system: CoordinateSystem) -> Self {
+        self.coordinate_system = coordinate_system;
+        self
+    }
+    pub fn with_world(mut self, world: CoordinateSystem) -> Self {
+        self.world = world;
+        self
+    }
+    pub fn with_unit_scale(mut self, unit_scale: f32) -> Self {
+        self.unit_scale = unit_scale;
+        self
+    }
+    pub fn with_cache_dir(mut self, cache_dir: impl Into<std::path::PathBuf>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// The conversion from the file to the world.
+    pub fn conversion(&self) -> AxisConversion {
+        AxisConversion::new(self.coordinate_system, self.world).with_scale(self.unit_scale)
+    }
+}
+
+/// Everything we load from a gltf file.
+pub struct GltfContents {
+    /// The node hierarchy of the loaded scene, which owns the renderable objects.
+    pub scene: Scene,
+    /// The names of all scenes in the document, by index, to select another one through [`LoadOptions`].
+    pub scene_names: Vec<Option<String>>,
+    /// The lights from the KHR_lights_punctual extension.
+    pub lights: CpuLights,
+    /// The cameras from the document, in traversal order, activate one with [`crate::State::set_camera`].
+    pub cameras: Vec<Camera>,
+    /// Plays the animations of the document on the scene.
+    pub animation: animation::AnimationPlayer,
+}
+
+/// Extensions that are required by some files and handled here, the gltf crate would reject those.
+const HANDLED_EXTENSIONS: &[&str] = &[meshopt::EXTENSION_NAME, "KHR_mesh_quantization"];
+
+/// Import the document and its buffers, compressed buffer views are decoded into their buffers.
+///
+/// Images are not loaded here, the textures are read from the buffers when a material uses them.
+pub fn import_gltf(
+    gltf_path: &std::path::Path,
+) -> Result<(gltf::Document, Vec<gltf::buffer::Data>), LoaderError> {
+    let import_error = |source: gltf::Error| LoaderError::Import {
+        path: gltf_path.to_owned(),
+        source,
+    };
+    let bytes = std::fs::read(gltf_path).map_err(|e| import_error(gltf::Error::Io(e)))?;
+    let gltf::Gltf { document, mut blob } =
+        gltf::Gltf::from_slice_without_validation(&bytes).map_err(import_error)?;
+    // Validate everything except the extensions we handle.
+    let mut json = document.into_json();
+    json.extensions_required
+        .retain(|e| !HANDLED_EXTENSIONS.contains(&e.as_str()));
+    let document = gltf::Document::from_json(json).map_err(import_error)?;
+    check_node_hierarchy(&document).map_err(|reason| LoaderError::Invalid {
+        path: gltf_path.to_owned(),
+        reason,
+    })?;
+
+    let base = gltf_path.parent();
+    let mut buffers = vec![];
+    for buffer in document.buffers() {
+        // Fallback buffers may not have data, the compressed views decode into them.
+        let data = if meshopt::is_fallback(&buffer) {
+            gltf::buffer::Data(vec![0; buffer.length()])
+        } else {
+            gltf::buffer::Data::from_source_and_blob(buffer.source(), base, &mut blob)
+                .map_err(import_error)?
+        };
+        if data.len() < buffer.length() {
+            return Err(import_error(gltf::Error::BufferLength {
+                buffer: buffer.index(),
+                expected: buffer.length(),
+                actual: data.len(),
+            }));
+        }
+        buffers.push(data);
+    }
+    meshopt::decode_buffer_views(&document, &mut buffers)
+        .map_err(|(view, reason)| LoaderError::BufferView { view, reason })?;
+    Ok((document, buffers))
+}
+
+/// Check that the children of the nodes don't form a cycle, the json validation doesn't and walking the hierarchy would
+/// never end.
+fn check_node_hierarchy(document: &gltf::Document) -> Result<(), String> {
+    #[derive(Copy, Clone, PartialEq)]
+    enum Visit {
+        Unvisited,
+        OnPath,
+        Done,
+    }
+    let nodes: Vec<_> = document.nodes().collect();
+    let mut visits = vec![Visit::Unvisited; nodes.len()];
+    for start in nodes.iter() {
+        if visits[start.index()] != Visit::Unvisited {
+            continue;
+        }
+        visits[start.index()] = Visit::OnPath;
+        let mut path = vec![(start.index(), start.children())];
+        while let Some((index, children)) = path.last_mut() {
+            let index = *index;
+            let Some(child) = children.next() else {
+                visits[index] = Visit::Done;
+                path.pop();
+                continue;
+            };
+            match visits[child.index()] {
+                Visit::Unvisited => {
+                    visits[child.index()] = Visit::OnPath;
+                    path.push((child.index(), child.children()));
                 }
+                Visit::OnPath => {
+                    return Err(format!("node {} is a descendant of itself", child.index()));
+                }
+                Visit::Done => {}
             }
-            let this_node_children: Vec<_> = this_node.children().collect();
-            if !this_node_children.is_empty() {
-                stack.push_back(Stack {
-                    transform: this_transform,
-                    nodes: this_node_children,
+        }
+    }
+    Ok(())
+}
+
+/// Load the objects of a gltf, OBJ, STL or PLY file, the format is picked by the extension.
+pub fn load_objects(
+    context: &crate::Context,
+    path: &std::path::Path,
+) -> Result<Scene, LoaderError> {
+    Ok(load_contents(context, path)?.scene)
+}
+
+/// Load a gltf, OBJ, STL or PLY file, the format is picked by the extension; only gltf files have lights, cameras
+/// and animations.
+pub fn load_contents(
+    context: &crate::Context,
+    path: &std::path::Path,
+) -> Result<GltfContents, LoaderError> {
+    load_contents_with_options(context, path, &LoadOptions::default())
+}
+
+pub fn load_contents_with_options(
+    context: &crate::Context,
+    path: &std::path::Path,
+    options: &LoadOptions,
+) -> Result<GltfContents, LoaderError> {
+    match mesh_file::read_mesh_file(path, options) {
+        Some(contents) => Ok(contents?.upload(context)),
+        None => load_gltf_contents_with_options(context, path, options),
+    }
+}
+
+pub fn load_gltf_objects(
+    context: &crate::Context,
+    gltf_path: &std::path::Path,
+) -> Result<Scene, LoaderError> {
+    Ok(load_gltf_contents(context, gltf_path)?.scene)
+}
+
+pub fn load_gltf_contents(
+    context: &crate::Context,
+    gltf_path: &std::path::Path,
+) -> Result<GltfContents, LoaderError> {
+    load_gltf_contents_with_options(context, gltf_path, &LoadOptions::default())
+}
+
+pub fn load_gltf_contents_with_options(
+    context: &crate::Context,
+    gltf_path: &std::path::Path,
+    options: &LoadOptions,
+) -> Result<GltfContents, LoaderError> {
+    let (structure, uploaded) = prepare::prepare_and_upload(context, gltf_path, options)?;
+    build_gltf_contents(context, &structure, options, uploaded)
+}
+
+/// The scene selected by the options.
+fn select_scene<'a>(
+    document: &'a gltf::Document,
+    options: &LoadOptions,
+) -> Result<gltf::Scene<'a>, LoaderError> {
+    match options.scene {
+        Some(index) => document
+            .scenes()
+            .nth(index)
+            .ok_or(LoaderError::SceneIndex(index)),
+        None => document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or(LoaderError::NoScene),
+    }
+}
+
+/// Build the scene from the structure of a document, the meshes and textures it uses must all be in `uploaded`.
+fn build_gltf_contents(
+    context: &crate::Context,
+    structure: &structure::GltfStructure,
+    options: &LoadOptions,
+    uploaded: Uploaded,
+) -> Result<GltfContents, LoaderError> {
+    // Everything is converted to the world convention as it is loaded.
+    let conversion = options.conversion();
+    let mut nodes = structure.nodes.clone();
+    for node in nodes.iter_mut() {
+        conversion.convert_node(node);
+    }
+    let mut scene = Scene::new(nodes).with_name(structure.scene_name());
+    scene.roots = structure.roots.iter().map(|n| NodeHandle(*n)).collect();
+    scene.update_world_transforms();
+    scene.skins = structure.skins.clone();
+    for skin in scene.skins.iter_mut() {
+        conversion.convert_skin(skin);
+    }
+    let mut animations = structure.animations.clone();
+    for channel in animations.iter_mut().flat_map(|a| a.channels.iter_mut()) {
+        conversion.convert_channel(channel);
+    }
+    let animation_player = animation::AnimationPlayer::new(animations);
+
+    let mut lights = CpuLights::new(context.clone());
+    for light in structure.lights.iter() {
+        lights.add_lights(&[conversion.convert_light(light)]);
+    }
+    let cameras = structure
+        .cameras
+        .iter()
+        .map(|camera| conversion.convert_camera(camera))
+        .collect();
+
+    // Traverse the nodes breadth first and combine the textures with the meshes as MeshObjectTextured, meshes are
+    // shared by all nodes that reference them.
+    let mut queue: std::collections::VecDeque<usize> = structure.roots.iter().copied().collect();
+    // The objects created per mesh index, for meshes that are placed as instances; the material is fixed per
+    // primitive so nodes with the same mesh only differ by their transform.
+    let mut instanced_objects: std::collections::HashMap<usize, Vec<usize>> = Default::default();
+    while let Some(node_index) = queue.pop_front() {
+        let node = &structure.hierarchy[node_index];
+        queue.extend(node.children.iter().copied());
+        let Some(mesh_index) = node.mesh else {
+            continue;
+        };
+        let primitives = &structure.meshes[mesh_index];
+        let world_transform = scene.world_transforms[node_index];
+        let skin = scene.nodes[node_index].skin;
+        // Joint matrices and morph weights are per object, so skinned or morphed nodes get their own objects.
+        let instanced = skin.is_none() && primitives.iter().all(|p| !p.has_morph_targets);
+        if instanced && let Some(object_indices) = instanced_objects.get(&mesh_index) {
+            for object_index in object_indices.iter() {
+                let mesh_object = &mut scene.objects[*object_index].mesh_object;
+                let instance = mesh_object.instances.len();
+                mesh_object.instances.push(world_transform);
+                scene.nodes[node_index].objects.push(ObjectInstance {
+                    object: *object_index,
+                    instance,
                 });
             }
+            continue;
+        }
+
+        let mut object_indices = vec![];
+        for (primitive_index, primitive) in primitives.iter().enumerate() {
+            let mut textures = vec![];
+            for material_texture in primitive.textures.iter() {
+                let key = material_texture.key;
+                let mut sampled_texture =
+                    uploaded
+                        .textures
+                        .get(&key)
+                        .cloned()
+                        .ok_or_else(|| LoaderError::Texture {
+                            texture_index: key.texture_index,
+                            texture: structure.textures[key.texture_index].name.clone(),
+                            reason: "the texture was not prepared".to_owned(),
+                        })?;
+                sampled_texture.texture_type = material_texture.texture_type;
+                sampled_texture.tex_coord = material_texture.tex_coord;
+                sampled_texture.transform = material_texture.transform;
+                textures.push(sampled_texture);
+            }
+            let key = PrimitiveKey {
+                mesh_index,
+                primitive_index,
+            };
+            let gpu_mesh =
+                uploaded
+                    .meshes
+                    .get(&key)
+                    .cloned()
+                    .ok_or_else(|| LoaderError::Primitive {
+                        mesh_index,
+                        mesh: None,
+                        primitive: primitive_index,
+                        reason: "the primitive was not prepared".to_owned(),
+                    })?;
+            let mut mesh_object = MeshObject::new(context.clone(), gpu_mesh);
+            mesh_object.set_single_transform(&world_transform);
+            if let Some(skin) = skin {
+                mesh_object.set_joint_matrices(
+                    &scene.skins[skin].joint_matrices(&scene.world_transforms, &world_transform),
+                );
+            }
+            // The default weights come from the node, or the mesh if the node doesn't override them.
+            mesh_object.set_morph_weights(&scene.nodes[node_index].weights);
+
+            let object_index = scene.objects.len();
+            object_indices.push(object_index);
+            scene.nodes[node_index].objects.push(ObjectInstance {
+                object: object_index,
+                instance: 0,
+            });
+            scene.objects.push(MeshObjectTextured::new_with_material(
+                context.clone(),
+                mesh_object,
+                &textures,
+                primitive.material,
+            ));
         }
+        if instanced {
+            instanced_objects.insert(mesh_index, object_indices);
+        }
+    }
+
+    // The instances are complete now, upload them.
+    for object in scene.objects.iter_mut() {
+        object.mesh_object.replace_gpu_data();
     }
 
-    println!("element count: {:?}", output.len());
+    println!("element count: {:?}", scene.objects.len());
 
-    Ok(output)
+    Ok(GltfContents {
+        scene,
+        scene_names: structure.scene_names.clone(),
+        lights,
+        cameras,
+        animation: animation_player,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use gltf::mesh::Mode;
+    use wgpu::PrimitiveTopology;
+
+    #[test]
+    fn test_indices_to_list() {
+        let indices = [0, 1, 2, 3];
+        assert_eq!(
+            indices_to_list(Mode::TriangleStrip, &indices),
+            (PrimitiveTopology::TriangleList, vec![0, 1, 2, 1, 3, 2])
+        );
+        assert_eq!(
+            indices_to_list(Mode::TriangleFan, &indices),
+            (PrimitiveTopology::TriangleList, vec![1, 2, 0, 2, 3, 0])
+        );
+        assert_eq!(
+            indices_to_list(Mode::LineLoop, &indices[..3]),
+            (PrimitiveTopology::LineList, vec![0, 1, 1, 2, 2, 0])
+        );
+        assert_eq!(
+            indices_to_list(Mode::Triangles, &indices),
+            (PrimitiveTopology::TriangleList, vec![0, 1, 2])
+        );
+    }
+
+    #[test]
+    fn test_morph_target_lengths() {
+        // Two points, a target that only moves their normals and one with a short NORMAL.
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "buffers": [{"byteLength": 60}],
+            "bufferViews": [{"buffer": 0, "byteLength": 60}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3",
+                 "min": [0, 0, 0], "max": [1, 1, 1]},
+                {"bufferView": 0, "byteOffset": 24, "componentType": 5126, "count": 2, "type": "VEC3"},
+                {"bufferView": 0, "byteOffset": 48, "componentType": 5126, "count": 1, "type": "VEC3"}
+            ],
+            "meshes": [{"primitives": [
+                {"attributes": {"POSITION": 0}, "mode": 0, "targets": [{"NORMAL": 1}]},
+                {"attributes": {"POSITION": 0}, "mode": 0, "targets": [{"NORMAL": 2}]}
+            ]}]
+        }"#;
+        let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+        let buffers = [gltf::buffer::Data(vec![0; 60])];
+        let mesh = document.meshes().next().unwrap();
+        let mut primitives = mesh.primitives();
+
+        let points =
+            load_gltf_primitive_mesh(&primitives.next().unwrap(), &mesh, &buffers).unwrap();
+        assert_eq!(points.morph_targets[0].position, vec![Vec3::ZERO; 2]);
+        let sprites = points.to_point_sprites();
+        assert_eq!(
+            sprites.morph_targets[0].position.len(),
+            sprites.position.len()
+        );
+
+        let short = load_gltf_primitive_mesh(&primitives.next().unwrap(), &mesh, &buffers);
+        assert!(matches!(short, Err(LoaderError::Primitive { .. })));
+    }
+
+    #[test]
+    fn test_node_cycle() {
+        let nodes = |children: &str| {
+            let json = format!(
+                r#"{{"asset": {{"version": "2.0"}}, "scenes": [{{"nodes": [0]}}], "nodes": {children}}}"#
+            );
+            gltf::Gltf::from_slice(json.as_bytes()).unwrap().document
+        };
+        // Shared children are not a cycle.
+        let shared = nodes(r#"[{"children": [1, 2]}, {"children": [2]}, {}]"#);
+        assert!(check_node_hierarchy(&shared).is_ok());
+        let cycle = nodes(r#"[{"children": [1]}, {"children": [2]}, {"children": [1]}]"#);
+        assert!(check_node_hierarchy(&cycle).is_err());
+        let own_child = nodes(r#"[{"children": [0]}]"#);
+        assert!(check_node_hierarchy(&own_child).is_err());
+
+        let path = std::env::temp_dir().join("test_node_cycle.gltf");
+        std::fs::write(&path, cycle.into_json().to_string_pretty().unwrap()).unwrap();
+        let imported = import_gltf(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(imported, Err(LoaderError::Invalid { .. })));
+    }
+
+    #[test]
+    fn test_ktx2_fallback_image() {
+        use crate::texture::ktx2::test::ktx2_file;
+        let mut png = std::io::Cursor::new(vec![]);
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([1, 2, 3, 4]))
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+        // A basis universal image has no vkFormat, a bc7 image can't be decoded on the cpu.
+        let basis = ktx2_file(0, 4, 4, &[0; 16]);
+        let bc7 = ktx2_file(145, 4, 4, &[0; 16]);
+        let decode = |ktx2: &[u8], fallback: bool| {
+            let mut data = ktx2.to_vec();
+            data.extend(&png);
+            let source = if fallback { r#""source": 1,"# } else { "" };
+            let json = format!(
+                r#"{{"asset": {{"version": "2.0"}}, "extensionsUsed": ["KHR_texture_basisu"],
+                "buffers": [{{"byteLength": {}}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteLength": {}}},
+                    {{"buffer": 0, "byteOffset": {}, "byteLength": {}}}],
+                "images": [
+                    {{"bufferView": 0, "mimeType": "image/ktx2"}},
+                    {{"bufferView": 1, "mimeType": "image/png"}}],
+                "textures": [{{{source} "extensions": {{"KHR_texture_basisu": {{"source": 0}}}}}}]}}"#,
+                data.len(),
+                ktx2.len(),
+                ktx2.len(),
+                png.len()
+            );
+            let document = gltf::Gltf::from_slice(json.as_bytes()).unwrap().document;
+            let images: Vec<_> = document.images().collect();
+            let texture = document.textures().next().unwrap();
+            decode_texture(&texture, &images, &[gltf::buffer::Data(data)], None)
+        };
+
+        // Images that can't be decoded use the fallback, if there is one.
+        assert!(
+            matches!(decode(&basis, true), Ok(DecodedImage::Rgba8(image)) if image.dimensions() == (4, 4))
+        );
+        assert!(matches!(
+            decode(&basis, false),
+            Err(LoaderError::Texture { .. })
+        ));
+        // Those the device may support keep the fallback along.
+        assert!(matches!(
+            decode(&bc7, true),
+            Ok(DecodedImage::Ktx2 {
+                fallback: Some(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            decode(&bc7, false),
+            Ok(DecodedImage::Ktx2 { fallback: None, .. })
+        ));
+    }
 }
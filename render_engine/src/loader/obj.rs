@@ -0,0 +1,594 @@
+// Wavefront OBJ and MTL files, https://paulbourke.net/dataformats/obj/ and https://paulbourke.net/dataformats/mtl/
+//
+// Every group (`g` or `o`) becomes a node, the faces of a group are split by material into objects.
+
+use super::mesh_file::{MeshFileContents, MeshFileImage, MeshFileObject};
+use super::{LoadOptions, LoaderError};
+use crate::scene::{Node, ObjectInstance, Scene};
+use crate::texture::{AlphaMode, MaterialUniform, TextureType};
+use crate::vertex::mesh::CpuMesh;
+use glam::{Vec2, Vec3, Vec3A, Vec4};
+use log::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A material from an MTL file, mapped onto the PBR factors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub material: MaterialUniform,
+    /// The texture files, relative to the MTL file.
+    pub base_color_texture: Option<String>,
+    pub normal_texture: Option<String>,
+    pub emissive_texture: Option<String>,
+}
+
+/// The faces of a group that use the same material.
+#[derive(Clone)]
+pub struct ObjMesh {
+    pub group: Option<String>,
+    pub material: Option<String>,
+    pub mesh: CpuMesh,
+}
+
+/// The contents of an OBJ file.
+#[derive(Clone, Default)]
+pub struct ObjData {
+    /// The MTL files, relative to the OBJ file.
+    pub material_libraries: Vec<String>,
+    pub meshes: Vec<ObjMesh>,
+}
+
+/// Identifies the vertices of a mesh, corners without normals are only shared within a smoothing group.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+    smoothing: u64,
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: HashMap<VertexKey, u32>,
+    keys: Vec<VertexKey>,
+    index: Vec<u32>,
+}
+
+impl MeshBuilder {
+    fn vertex(&mut self, key: VertexKey) -> u32 {
+        *self.vertices.entry(key).or_insert_with(|| {
+            self.keys.push(key);
+            (self.keys.len() - 1) as u32
+        })
+    }
+
+    /// Resolve the vertices, normals that are not specified are the area weighted normals of the faces around them.
+    fn build(
+        self,
+        positions: &[Vec3],
+        colors: &[Option<Vec3>],
+        uvs: &[Vec2],
+        normals: &[Vec3],
+    ) -> CpuMesh {
+        let position: Vec<Vec3> = self.keys.iter().map(|k| positions[k.position]).collect();
+        let mut normal: Vec<Vec3> = self
+            .keys
+            .iter()
+            .map(|k| k.normal.map(|n| normals[n]).unwrap_or(Vec3::ZERO))
+            .collect();
+        for triangle in self.index.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            let face_normal = (position[b] - position[a]).cross(position[c] - position[a]);
+            for v in [a, b, c] {
+                if self.keys[v].normal.is_none() {
+                    normal[v] += face_normal;
+                }
+            }
+        }
+        let mut mesh = CpuMesh::new(position, self.index);
+        mesh.normal = Some(
+            normal
+                .into_iter()
+                .map(|n| Vec3A::from(n.normalize_or_zero()))
+                .collect(),
+        );
+        if self.keys.iter().any(|k| k.uv.is_some()) {
+            mesh.uv = Some(
+                self.keys
+                    .iter()
+                    .map(|k| k.uv.map(|i| uvs[i]).unwrap_or(Vec2::ZERO))
+                    .collect(),
+            );
+        }
+        if self.keys.iter().any(|k| colors[k.position].is_some()) {
+            mesh.color = Some(
+                self.keys
+                    .iter()
+                    .map(|k| colors[k.position].unwrap_or(Vec3::ONE).extend(1.0))
+                    .collect(),
+            );
+        }
+        mesh
+    }
+}
+
+fn parse_floats<const N: usize>(args: &[&str]) -> Result<[f32; N], String> {
+    if args.len() < N {
+        return Err(format!("expected {N} numbers, got {}", args.len()));
+    }
+    let mut res = [0.0; N];
+    for (v, arg) in res.iter_mut().zip(args) {
+        *v = arg
+            .parse()
+            .map_err(|_| format!("{arg:?} is not a number"))?;
+    }
+    Ok(res)
+}
+
+/// Resolve a one based index, negative indices are relative to the end.
+fn parse_index(index: &str, len: usize) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("{index:?} is not an index"))?;
+    let resolved = if value < 0 {
+        len as i64 + value
+    } else {
+        value - 1
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("index {value} is out of bounds for {len} elements"));
+    }
+    Ok(resolved as usize)
+}
+
+/// Parse an OBJ file, polygons are triangulated as fans; errors hold the line number and the reason.
+pub fn parse_obj(text: &str) -> Result<ObjData, (usize, String)> {
+    let mut positions = vec![];
+    let mut colors = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut material_libraries = vec![];
+
+    // The meshes in order of appearance, keyed by group and material.
+    let mut builders: Vec<(Option<String>, Option<String>, MeshBuilder)> = vec![];
+    let mut builder_by_key: HashMap<(Option<String>, Option<String>), usize> = HashMap::new();
+    let mut group: Option<String> = None;
+    let mut material: Option<String> = None;
+    // Smoothing group zero is off, then every face gets its own normals.
+    let mut smoothing = 0u64;
+    let mut face_count = 0u64;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let error = |reason: String| (line_number, reason);
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+        let rest = || Some(args.join(" ")).filter(|z| !z.is_empty());
+        match keyword {
+            "v" => {
+                positions.push(Vec3::from_array(parse_floats::<3>(&args).map_err(error)?));
+                // Some exporters append a vertex color.
+                colors.push(if args.len() >= 6 {
+                    Some(Vec3::from_array(
+                        parse_floats::<3>(&args[3..]).map_err(error)?,
+                    ))
+                } else {
+                    None
+                });
+            }
+            "vt" => {
+                let [u, v] = parse_floats::<2>(&args)
+                    .or_else(|_| parse_floats::<1>(&args).map(|[u]| [u, 0.0]))
+                    .map_err(error)?;
+                // The origin of OBJ is in the bottom left, ours is the top left.
+                uvs.push(Vec2::new(u, 1.0 - v));
+            }
+            "vn" => normals.push(Vec3::from_array(parse_floats::<3>(&args).map_err(error)?)),
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(format!(
+                        "a face needs 3 vertices, got {}",
+                        args.len()
+                    )));
+                }
+                face_count += 1;
+                let key = (group.clone(), material.clone());
+                let builder_index = *builder_by_key.entry(key.clone()).or_insert_with(|| {
+                    builders.push((key.0, key.1, MeshBuilder::default()));
+                    builders.len() - 1
+                });
+                let builder = &mut builders[builder_index].2;
+                let mut corners = vec![];
+                for arg in args.iter() {
+                    let mut parts = arg.split('/');
+                    let position = parse_index(parts.next().unwrap_or_default(), positions.len())
+                        .map_err(error)?;
+                    let uv = match parts.next() {
+                        Some(i) if !i.is_empty() => Some(parse_index(i, uvs.len()).map_err(error)?),
+                        _ => None,
+                    };
+                    let normal = match parts.next() {
+                        Some(i) if !i.is_empty() => {
+                            Some(parse_index(i, normals.len()).map_err(error)?)
+                        }
+                        _ => None,
+                    };
+                    let smoothing = match (normal, smoothing) {
+                        (Some(_), _) => 0,
+                        (None, 0) => u64::from(u32::MAX) + face_count,
+                        (None, s) => s,
+                    };
+                    corners.push(builder.vertex(VertexKey {
+                        position,
+                        uv,
+                        normal,
+                        smoothing,
+                    }));
+                }
+                for i in 1..corners.len() - 1 {
+                    builder
+                        .index
+                        .extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "g" | "o" => group = rest(),
+            "usemtl" => material = rest(),
+            "mtllib" => material_libraries.extend(args.iter().map(|z| z.to_string())),
+            "s" => {
+                smoothing = match args.first().copied() {
+                    None | Some("off") => 0,
+                    Some("on") => 1,
+                    Some(s) => s
+                        .parse::<u32>()
+                        .map_err(|_| error(format!("{s:?} is not a smoothing group")))?
+                        .into(),
+                }
+            }
+            // Points, lines, curves and surfaces are not supported.
+            _ => {}
+        }
+    }
+
+    Ok(ObjData {
+        material_libraries,
+        meshes: builders
+            .into_iter()
+            .filter(|(_, _, builder)| !builder.index.is_empty())
+            .map(|(group, material, builder)| ObjMesh {
+                mesh: builder
+                    .build(&positions, &colors, &uvs, &normals)
+                    .with_name(group.as_deref().unwrap_or("obj")),
+                group,
+                material,
+            })
+            .collect(),
+    })
+}
+
+/// Split the options from a texture statement, returns the file name and the bump multiplier.
+fn parse_texture_statement(args: &[&str]) -> Result<(String, Option<f32>), String> {
+    let mut bump_multiplier = None;
+    let mut i = 0;
+    while i < args.len() && args[i].starts_with('-') {
+        let option = args[i];
+        i += 1;
+        let arg_count = match option {
+            "-mm" => 2,
+            // Offset, scale and turbulence take one to three numbers.
+            "-o" | "-s" | "-t" => args[i..]
+                .iter()
+                .take(3)
+                .take_while(|z| z.parse::<f32>().is_ok())
+                .count(),
+            _ => 1,
+        };
+        if option == "-bm" {
+            bump_multiplier = Some(parse_floats::<1>(&args[i..])?[0]);
+        }
+        i += arg_count;
+    }
+    if i >= args.len() {
+        return Err("missing texture file".to_owned());
+    }
+    Ok((args[i..].join(" "), bump_multiplier))
+}
+
+/// Parse an MTL file; errors hold the line number and the reason.
+///
+/// The phong parameters are mapped onto the metallic roughness model: Kd is the base color, the Ns exponent is
+/// converted to a roughness and materials are dielectric. The PBR extension statements Pr, Pm and Ke take precedence.
+pub fn parse_mtl(text: &str) -> Result<Vec<ObjMaterial>, (usize, String)> {
+    #[derive(Default)]
+    struct Phong {
+        specular: Vec3,
+        exponent: f32,
+        roughness: Option<f32>,
+        metallic: Option<f32>,
+    }
+    let finish = |mut material: ObjMaterial, phong: &Phong| {
+        // Beckmann roughness for a blinn phong exponent, without specular color the surface is fully rough.
+        let roughness = if phong.specular.max_element() > 0.0 {
+            (2.0 / (phong.exponent.max(0.0) + 2.0)).sqrt()
+        } else {
+            1.0
+        };
+        material.material.roughness_factor = phong.roughness.unwrap_or(roughness);
+        material.material.metallic_factor = phong.metallic.unwrap_or(0.0);
+        if material.material.base_color_factor.w < 1.0 {
+            material.material.alpha_mode = AlphaMode::Blend;
+        }
+        material
+    };
+
+    let mut materials = vec![];
+    let mut current: Option<(ObjMaterial, Phong)> = None;
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |reason: String| (line_index + 1, reason);
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            if let Some((material, phong)) = current.take() {
+                materials.push(finish(material, &phong));
+            }
+            let mut material = ObjMaterial {
+                name: args.join(" "),
+                ..Default::default()
+            };
+            // MTL materials are non-metallic unless specified otherwise.
+            material.material.metallic_factor = 0.0;
+            current = Some((material, Phong::default()));
+            continue;
+        }
+        let Some((material, phong)) = current.as_mut() else {
+            return Err(error(format!("{keyword} before newmtl")));
+        };
+        let factors = &mut material.material;
+        match keyword {
+            "Kd" => {
+                let [r, g, b] = parse_floats::<3>(&args).map_err(error)?;
+                factors.base_color_factor = Vec4::new(r, g, b, factors.base_color_factor.w);
+            }
+            "d" => factors.base_color_factor.w = parse_floats::<1>(&args).map_err(error)?[0],
+            "Tr" => factors.base_color_factor.w = 1.0 - parse_floats::<1>(&args).map_err(error)?[0],
+            "Ks" => phong.specular = Vec3::from_array(parse_floats::<3>(&args).map_err(error)?),
+            "Ns" => phong.exponent = parse_floats::<1>(&args).map_err(error)?[0],
+            "Pr" => phong.roughness = Some(parse_floats::<1>(&args).map_err(error)?[0]),
+            "Pm" => phong.metallic = Some(parse_floats::<1>(&args).map_err(error)?[0]),
+            "Ke" => {
+                factors.emissive_factor = Vec3::from_array(parse_floats::<3>(&args).map_err(error)?)
+            }
+            "map_Kd" => {
+                material.base_color_texture = Some(parse_texture_statement(&args).map_err(error)?.0)
+            }
+            "map_Ke" => {
+                material.emissive_texture = Some(parse_texture_statement(&args).map_err(error)?.0);
+                // The emissive texture is multiplied with the factor, which defaults to black.
+                if factors.emissive_factor == Vec3::ZERO {
+                    factors.emissive_factor = Vec3::ONE;
+                }
+            }
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                let (file, bump_multiplier) = parse_texture_statement(&args).map_err(error)?;
+                material.normal_texture = Some(file);
+                if let Some(scale) = bump_multiplier {
+                    factors.normal_scale = scale;
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((material, phong)) = current.take() {
+        materials.push(finish(material, &phong));
+    }
+    Ok(materials)
+}
+
+/// Read an OBJ file and decode its materials and textures, with a root node for every group.
+///
+/// Missing material libraries and textures are reported as warnings, as these are common in exported files.
+pub(super) fn read_obj(
+    obj_path: &Path,
+    options: &LoadOptions,
+) -> Result<MeshFileContents, LoaderError> {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path).map_err(|source| LoaderError::Io {
+            path: path.to_owned(),
+            source,
+        })
+    };
+    let parse_error = |path: &Path| {
+        let path = path.to_owned();
+        move |(line, reason)| LoaderError::Parse { path, line, reason }
+    };
+    let obj = parse_obj(&read(obj_path)?).map_err(parse_error(obj_path))?;
+    let base = obj_path.parent().unwrap_or(Path::new(""));
+
+    // The materials by name, with the directory their textures are relative to.
+    let mut materials: HashMap<String, (ObjMaterial, PathBuf)> = HashMap::new();
+    for library in obj.material_libraries.iter() {
+        let path = base.join(library);
+        let text = match read(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("{e}");
+                continue;
+            }
+        };
+        let directory = path.parent().unwrap_or(Path::new("")).to_owned();
+        for material in parse_mtl(&text).map_err(parse_error(&path))? {
+            materials.insert(material.name.clone(), (material, directory.clone()));
+        }
+    }
+
+    // The images by path and format, each is decoded once; None if it failed to decode.
+    let mut images: Vec<MeshFileImage> = vec![];
+    let mut image_indices: HashMap<(PathBuf, wgpu::TextureFormat), Option<usize>> = HashMap::new();
+    let mut texture = |directory: &Path, file: &Option<String>, format, texture_type| {
+        let path = directory.join(file.as_ref()?);
+        let index =
+            *image_indices
+                .entry((path.clone(), format))
+                .or_insert_with(|| match image::open(&path) {
+                    Ok(image) => {
+                        images.push(MeshFileImage {
+                            path,
+                            image: image.to_rgba8(),
+                            format,
+                        });
+                        Some(images.len() - 1)
+                    }
+                    Err(e) => {
+                        warn!("failed to load {path:?}: {e}");
+                        None
+                    }
+                });
+        Some((index?, texture_type))
+    };
+
+    let mut nodes: Vec<Node> = vec![];
+    let mut node_by_group: HashMap<Option<String>, usize> = HashMap::new();
+    let mut objects = vec![];
+    for obj_mesh in obj.meshes {
+        let node_index = *node_by_group
+            .entry(obj_mesh.group.clone())
+            .or_insert_with(|| {
+                nodes.push(Node {
+                    name: obj_mesh.group.clone(),
+                    ..Default::default()
+                });
+                nodes.len() - 1
+            });
+        let mut mesh = obj_mesh.mesh;
+        options.conversion().convert_mesh(&mut mesh);
+        let mut textures = vec![];
+        // Without a material the surface is a white dielectric, like the materials from MTL files.
+        let mut material = MaterialUniform {
+            metallic_factor: 0.0,
+            ..Default::default()
+        };
+        if let Some((obj_material, directory)) =
+            obj_mesh.material.as_ref().and_then(|m| materials.get(m))
+        {
+            material = obj_material.material;
+            textures.extend(texture(
+                directory,
+                &obj_material.base_color_texture,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                TextureType::BaseColor,
+            ));
+            textures.extend(texture(
+                directory,
+                &obj_material.emissive_texture,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                TextureType::Emissive,
+            ));
+            if let Some(normal) = texture(
+                directory,
+                &obj_material.normal_texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+                TextureType::Normal,
+            ) && mesh.calculate_tangents()
+            {
+                textures.push(normal);
+            }
+        }
+        nodes[node_index].objects.push(ObjectInstance {
+            object: objects.len(),
+            instance: 0,
+        });
+        objects.push(MeshFileObject {
+            mesh,
+            material,
+            textures,
+        });
+    }
+
+    Ok(MeshFileContents {
+        name: obj_path
+            .file_stem()
+            .and_then(|z| z.to_str())
+            .map(|z| z.to_owned()),
+        nodes,
+        objects,
+        images,
+    })
+}
+
+/// Load an OBJ file and its materials into a scene, with a root node for every group.
+///
+/// Missing material libraries and textures are reported as warnings, as these are common in exported files.
+pub fn load_obj_objects(
+    context: &crate::Context,
+    obj_path: &Path,
+    options: &LoadOptions,
+) -> Result<Scene, LoaderError> {
+    Ok(read_obj(obj_path, options)?.upload(context).scene)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_obj() {
+        let obj = parse_obj(
+            "mtllib a.mtl\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 1\n\
+             g quad\nusemtl red\ns 1\n\
+             f 1/1 2/2 3/2 4/1\n\
+             usemtl blue\ns off\n\
+             f -4 -3 -2\n",
+        )
+        .unwrap();
+        assert_eq!(obj.material_libraries, vec!["a.mtl".to_owned()]);
+        assert_eq!(obj.meshes.len(), 2);
+        let red = &obj.meshes[0];
+        assert_eq!(red.group.as_deref(), Some("quad"));
+        assert_eq!(red.material.as_deref(), Some("red"));
+        assert_eq!(red.mesh.index, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(red.mesh.uv.as_ref().unwrap()[1], Vec2::new(1.0, 0.0));
+        // Smoothed vertices have the normal of the faces around them.
+        assert_eq!(red.mesh.normal.as_ref().unwrap()[0], Vec3A::Z);
+        let blue = &obj.meshes[1];
+        assert_eq!(blue.mesh.position.len(), 3);
+        assert!(blue.mesh.uv.is_none());
+
+        assert_eq!(parse_obj("v 0 0 0\nf 1 2 3\n").err().unwrap().0, 2);
+    }
+
+    #[test]
+    fn test_parse_mtl() {
+        let materials = parse_mtl(
+            "newmtl shiny\n\
+             Kd 1 0 0\nKs 1 1 1\nNs 98\nd 0.5\n\
+             map_Kd albedo map.png\n\
+             map_Bump -bm 0.5 normal.png\n\
+             newmtl matte\nKd 0 1 0\n",
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 2);
+        let shiny = &materials[0];
+        assert_eq!(
+            shiny.material.base_color_factor,
+            Vec4::new(1.0, 0.0, 0.0, 0.5)
+        );
+        assert_eq!(shiny.material.alpha_mode, AlphaMode::Blend);
+        assert!((shiny.material.roughness_factor - 0.1414).abs() < 1e-3);
+        assert_eq!(shiny.material.metallic_factor, 0.0);
+        assert_eq!(shiny.base_color_texture.as_deref(), Some("albedo map.png"));
+        assert_eq!(shiny.normal_texture.as_deref(), Some("normal.png"));
+        assert_eq!(shiny.material.normal_scale, 0.5);
+        assert_eq!(materials[1].material.roughness_factor, 1.0);
+        assert_eq!(materials[1].material.alpha_mode, AlphaMode::Opaque);
+    }
+}
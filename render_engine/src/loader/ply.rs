@@ -0,0 +1,351 @@
+// PLY files, in the ASCII and both binary variants, https://paulbourke.net/dataformats/ply/
+//
+// The vertex element provides the positions, normals and colors, the face element the polygons. Files without faces
+// are point clouds.
+
+use crate::texture::srgb_to_linear;
+use crate::vertex::mesh::CpuMesh;
+use glam::{Vec3, Vec3A, Vec4};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(format!("unknown property type {name:?}")),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    /// The value that maps to one for colors stored as integers.
+    fn color_scale(&self) -> f64 {
+        match self {
+            Self::F32 | Self::F64 => 1.0,
+            Self::U16 | Self::I16 => 65535.0,
+            _ => 255.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum PropertyKind {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values of the body, in the format of the file.
+struct BodyReader<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    offset: usize,
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl<'a> BodyReader<'a> {
+    fn new(format: Format, body: &'a [u8]) -> Result<Self, String> {
+        let text = match format {
+            Format::Ascii => std::str::from_utf8(body).map_err(|e| e.to_string())?,
+            _ => "",
+        };
+        Ok(Self {
+            format,
+            bytes: body,
+            offset: 0,
+            tokens: text.split_ascii_whitespace(),
+        })
+    }
+
+    fn read(&mut self, ty: ScalarType) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            let token = self.tokens.next().ok_or("unexpected end of file")?;
+            return token
+                .parse()
+                .map_err(|_| format!("{token:?} is not a number"));
+        }
+        let size = ty.size();
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + size)
+            .ok_or("unexpected end of file")?;
+        self.offset += size;
+        let mut b = [0u8; 8];
+        b[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            b[..size].reverse();
+        }
+        Ok(match ty {
+            ScalarType::I8 => b[0] as i8 as f64,
+            ScalarType::U8 => b[0] as f64,
+            ScalarType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+/// Split the header from the body and parse its elements.
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, &[u8]), String> {
+    const END_HEADER: &[u8] = b"end_header";
+    let end = bytes
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .ok_or("missing end_header")?;
+    // The body starts after the line break that ends the header.
+    let body_start = bytes[end..]
+        .iter()
+        .position(|b| *b == b'\n')
+        .map(|p| end + p + 1)
+        .unwrap_or(bytes.len());
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|e| e.to_string())?;
+
+    let mut lines = header.lines();
+    if lines.next().map(|z| z.trim()) != Some("ply") {
+        return Err("not a ply file".to_owned());
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(format!("unknown format {name:?}")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("{count:?} is not an element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or("property before element")?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::List {
+                        count: ScalarType::parse(count)?,
+                        item: ScalarType::parse(item)?,
+                    },
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or("property before element")?
+                .properties
+                .push(Property {
+                    name: name.to_string(),
+                    kind: PropertyKind::Scalar(ScalarType::parse(ty)?),
+                }),
+            _ => {}
+        }
+    }
+    Ok((
+        format.ok_or("missing format")?,
+        elements,
+        &bytes[body_start..],
+    ))
+}
+
+/// Parse a PLY file, polygons are triangulated as fans and files without faces become point lists.
+///
+/// Colors are expected to be srgb encoded, as is common for scans, and are converted to linear.
+pub fn parse_ply(bytes: &[u8]) -> Result<CpuMesh, String> {
+    let (format, elements, body) = parse_header(bytes)?;
+    let mut reader = BodyReader::new(format, body)?;
+
+    let mut position = vec![];
+    let mut normal = vec![];
+    let mut color = vec![];
+    let mut index = vec![];
+    let mut has_faces = false;
+    for element in elements.iter() {
+        let property = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let position_properties = ["x", "y", "z"].map(property);
+        let normal_properties = ["nx", "ny", "nz"].map(property);
+        let color_properties = ["red", "green", "blue", "alpha"].map(property);
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        has_faces |= is_face && element.count > 0;
+        if is_vertex && position_properties.iter().any(|p| p.is_none()) {
+            return Err("vertex element without x, y and z".to_owned());
+        }
+
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            for (i, p) in element.properties.iter().enumerate() {
+                match p.kind {
+                    PropertyKind::Scalar(ty) => values[i] = reader.read(ty)?,
+                    PropertyKind::List { count, item } => {
+                        let count = reader.read(count)? as usize;
+                        let items = (0..count)
+                            .map(|_| reader.read(item))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let is_indices = p.name == "vertex_indices" || p.name == "vertex_index";
+                        if is_face && is_indices {
+                            for k in 1..count.saturating_sub(1) {
+                                index.extend([items[0], items[k], items[k + 1]].map(|v| v as u32));
+                            }
+                        }
+                    }
+                }
+            }
+            if !is_vertex {
+                continue;
+            }
+            let get = |p: Option<usize>| p.map(|p| values[p] as f32);
+            position.push(Vec3::from_array(
+                position_properties.map(|p| get(p).unwrap()),
+            ));
+            if normal_properties.iter().all(|p| p.is_some()) {
+                normal.push(Vec3A::from_array(
+                    normal_properties.map(|p| get(p).unwrap()),
+                ));
+            }
+            if color_properties[..3].iter().all(|p| p.is_some()) {
+                let channel = |p: Option<usize>| {
+                    p.map(|p| {
+                        let ty = match element.properties[p].kind {
+                            PropertyKind::Scalar(ty) => ty,
+                            PropertyKind::List { item, .. } => item,
+                        };
+                        (values[p] / ty.color_scale()) as f32
+                    })
+                };
+                let [r, g, b, a] = color_properties.map(channel);
+                color.push(Vec4::new(
+                    srgb_to_linear(r.unwrap()),
+                    srgb_to_linear(g.unwrap()),
+                    srgb_to_linear(b.unwrap()),
+                    a.unwrap_or(1.0),
+                ));
+            }
+        }
+    }
+
+    if let Some(v) = index.iter().find(|v| **v as usize >= position.len()) {
+        return Err(format!(
+            "face index {v} is out of bounds for {} vertices",
+            position.len()
+        ));
+    }
+    let vertex_count = position.len();
+    let mut mesh = if has_faces {
+        CpuMesh::new(position, index)
+    } else {
+        CpuMesh::new(position, (0..vertex_count as u32).collect())
+            .with_topology(wgpu::PrimitiveTopology::PointList)
+    };
+    if !color.is_empty() {
+        mesh.color = Some(color);
+    }
+    if !normal.is_empty() {
+        mesh.normal = Some(normal);
+    } else {
+        mesh.calculate_smooth_normals();
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_ply_ascii() {
+        let ply = "ply\nformat ascii 1.0\ncomment a quad\n\
+            element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n\
+            4 0 1 2 3\n";
+        let mesh = parse_ply(ply.as_bytes()).unwrap();
+        assert_eq!(mesh.topology, wgpu::PrimitiveTopology::TriangleList);
+        assert_eq!(mesh.index, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(
+            mesh.color.as_ref().unwrap()[1],
+            Vec4::new(0.0, 1.0, 0.0, 1.0)
+        );
+        assert_eq!(mesh.normal.as_ref().unwrap()[0], Vec3A::Z);
+    }
+
+    #[test]
+    fn test_parse_ply_point_cloud() {
+        let mut ply = b"ply\nformat binary_big_endian 1.0\n\
+            element vertex 2\nproperty double x\nproperty double y\nproperty double z\n\
+            property float nx\nproperty float ny\nproperty float nz\nend_header\n"
+            .to_vec();
+        for (p, n) in [
+            ([1.0f64, 2.0, 3.0], [0.0f32, 1.0, 0.0]),
+            ([4.0, 5.0, 6.0], [1.0, 0.0, 0.0]),
+        ] {
+            p.iter().for_each(|v| ply.extend(v.to_be_bytes()));
+            n.iter().for_each(|v| ply.extend(v.to_be_bytes()));
+        }
+        let mesh = parse_ply(&ply).unwrap();
+        assert_eq!(mesh.topology, wgpu::PrimitiveTopology::PointList);
+        assert_eq!(mesh.index, vec![0, 1]);
+        assert_eq!(mesh.position[1], Vec3::new(4.0, 5.0, 6.0));
+        assert_eq!(mesh.normal.as_ref().unwrap()[1], Vec3A::X);
+        assert!(mesh.color.is_none());
+
+        // Each point becomes a quad of four vertices.
+        let sprites = mesh.to_point_sprites();
+        assert_eq!(sprites.position.len(), 8);
+        assert_eq!(sprites.position[5], Vec3::new(4.0, 5.0, 6.0));
+        assert_eq!(&sprites.index[6..], &[4, 5, 6, 6, 5, 7]);
+    }
+}
@@ -0,0 +1,321 @@
+// Preparing the contents of a gltf file on the cpu, which is where most of the loading time goes: importing the
+// document, reading and converting the meshes, generating their tangents and decoding the images. None of this touches
+// the gpu, so it can run on worker threads. It is shared by the background loader and the cache.
+
+use super::cache::{Cache, CacheError};
+use super::structure::{GltfStructure, TextureStructure};
+use super::{
+    DecodedImage, LoadOptions, LoaderError, PrimitiveKey, SampledTextureKey, Uploaded,
+    decode_texture, import_gltf, load_gltf_primitive_mesh, upload_sampled_texture,
+};
+use crate::axes::AxisConversion;
+use crate::texture::ktx2::Ktx2Image;
+use crate::texture::mipmap::{MipmapGenerator, rgba8_mip_levels};
+use crate::vertex::mesh::CpuMesh;
+use log::*;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A mesh or texture to prepare.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub(super) enum Job {
+    Mesh(PrimitiveKey),
+    Texture(SampledTextureKey),
+}
+
+/// A prepared mesh or texture, ready for upload.
+#[derive(Clone)]
+pub(super) enum Prepared {
+    Mesh(PrimitiveKey, Box<CpuMesh>),
+    Texture(SampledTextureKey, DecodedImage),
+}
+
+impl Prepared {
+    pub fn job(&self) -> Job {
+        match self {
+            Prepared::Mesh(key, _) => Job::Mesh(*key),
+            Prepared::Texture(key, _) => Job::Texture(*key),
+        }
+    }
+}
+
+/// Runs the jobs for a document.
+pub(super) struct Preparer<'a> {
+    meshes: Vec<gltf::Mesh<'a>>,
+    textures: Vec<gltf::Texture<'a>>,
+    images: Vec<gltf::Image<'a>>,
+    buffers: &'a [gltf::buffer::Data],
+    base: Option<&'a Path>,
+    conversion: AxisConversion,
+    /// Compute the mip levels of decoded images on the cpu, such that they can be stored in the cache.
+    mip_levels: bool,
+}
+
+impl<'a> Preparer<'a> {
+    pub fn new(
+        document: &'a gltf::Document,
+        buffers: &'a [gltf::buffer::Data],
+        gltf_path: &'a Path,
+        options: &LoadOptions,
+    ) -> Self {
+        Self {
+            meshes: document.meshes().collect(),
+            textures: document.textures().collect(),
+            images: document.images().collect(),
+            buffers,
+            base: gltf_path.parent(),
+            conversion: options.conversion(),
+            mip_levels: options.cache_dir.is_some(),
+        }
+    }
+
+    pub fn prepare(&self, job: Job) -> Result<Prepared, LoaderError> {
+        match job {
+            Job::Mesh(key) => {
+                let mesh = &self.meshes[key.mesh_index];
+                let primitive = mesh
+                    .primitives()
+                    .nth(key.primitive_index)
+                    .expect("primitive is collected from the mesh");
+                let mut cpu_mesh = load_gltf_primitive_mesh(&primitive, mesh, self.buffers)?;
+                self.conversion.convert_mesh(&mut cpu_mesh);
+                Ok(Prepared::Mesh(key, Box::new(cpu_mesh)))
+            }
+            Job::Texture(key) => {
+                let texture = &self.textures[key.texture_index];
+                let decoded = match decode_texture(texture, &self.images, self.buffers, self.base)?
+                {
+                    DecodedImage::Rgba8(image) if self.mip_levels => {
+                        let (width, height) = image.dimensions();
+                        DecodedImage::Ktx2 {
+                            image: Ktx2Image {
+                                format: key.format,
+                                width,
+                                height,
+                                levels: rgba8_mip_levels(
+                                    width,
+                                    height,
+                                    image.into_raw(),
+                                    key.format.is_srgb(),
+                                ),
+                            },
+                            fallback: None,
+                        }
+                    }
+                    decoded => decoded,
+                };
+                Ok(Prepared::Texture(key, decoded))
+            }
+        }
+    }
+
+    /// Prepare the jobs, spread over the available cores. The results are passed to `sink` in the order they finish,
+    /// the remaining jobs are skipped once it returns false.
+    pub fn prepare_all(
+        &self,
+        jobs: &[Job],
+        sink: impl Fn(Result<Prepared, LoaderError>) -> bool + Sync,
+    ) {
+        let next = AtomicUsize::new(0);
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        std::thread::scope(|s| {
+            for _ in 0..workers.min(jobs.len()) {
+                s.spawn(|| {
+                    while let Some(job) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        if !sink(self.prepare(*job)) {
+                            // Make the other workers stop as well.
+                            next.store(jobs.len(), Ordering::Relaxed);
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// Import a gltf file and prepare everything its selected scene uses, or read all of that from the cache directory of
+/// the options if it holds an entry for this file, without importing the document. The structure is passed to
+/// `on_structure` before the meshes and textures, which are passed to `sink` like [`Preparer::prepare_all`]; nothing
+/// is prepared if `on_structure` returns false.
+///
+/// Without a valid entry one is written once all jobs succeeded, failing to read or write the cache never fails the
+/// load.
+pub(super) fn prepare_gltf(
+    gltf_path: &Path,
+    options: &LoadOptions,
+    on_structure: impl FnOnce(&GltfStructure) -> bool,
+    sink: impl Fn(Result<Prepared, LoaderError>) -> bool + Sync,
+) -> Result<GltfStructure, LoaderError> {
+    let cache =
+        options
+            .cache_dir
+            .as_deref()
+            .and_then(|dir| match Cache::new(dir, gltf_path, options) {
+                Ok(cache) => Some(cache),
+                Err(e) => {
+                    warn!("not caching {gltf_path:?}: {e}");
+                    None
+                }
+            });
+    if let Some(cache) = cache.as_ref() {
+        match cache.read() {
+            Ok((structure, prepared)) => {
+                debug!("loading {gltf_path:?} from {:?}", cache.path());
+                if on_structure(&structure) {
+                    for item in prepared {
+                        if !sink(Ok(item)) {
+                            break;
+                        }
+                    }
+                }
+                return Ok(structure);
+            }
+            Err(CacheError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("rebuilding the cache entry {:?}: {e}", cache.path()),
+        }
+    }
+
+    let (document, buffers) = import_gltf(gltf_path)?;
+    info!("document: {document:#?}");
+    let structure = GltfStructure::new(&document, &buffers, options)?;
+    if !on_structure(&structure) {
+        return Ok(structure);
+    }
+    let jobs = structure.jobs();
+    let preparer = Preparer::new(&document, &buffers, gltf_path, options);
+    let Some(cache) = cache else {
+        preparer.prepare_all(&jobs, sink);
+        return Ok(structure);
+    };
+    let resources = cache.resources(&document);
+    let prepared = Mutex::new(Vec::with_capacity(jobs.len()));
+    preparer.prepare_all(&jobs, |result| {
+        if let Ok(item) = &result {
+            prepared.lock().unwrap().push(item.clone());
+        }
+        sink(result)
+    });
+    // Failed or cancelled loads are missing items, and are not cached.
+    let prepared = prepared.into_inner().unwrap();
+    if prepared.len() == jobs.len()
+        && let Err(e) = cache.write(&resources, &structure, &prepared)
+    {
+        warn!("failed to write the cache entry {:?}: {e}", cache.path());
+    }
+    Ok(structure)
+}
+
+/// Prepare everything the selected scene uses and upload it, with the cache of the options.
+pub(super) fn prepare_and_upload(
+    context: &crate::Context,
+    gltf_path: &Path,
+    options: &LoadOptions,
+) -> Result<(GltfStructure, Uploaded), LoaderError> {
+    let results = Mutex::new(vec![]);
+    let structure = prepare_gltf(
+        gltf_path,
+        options,
+        |_| true,
+        |result| {
+            let ok = result.is_ok();
+            results.lock().unwrap().push(result);
+            ok
+        },
+    )?;
+    let mut mipmap_generator = MipmapGenerator::new(&context.device);
+    let mut uploaded = Uploaded::default();
+    for result in results.into_inner().unwrap() {
+        uploaded.upload(context, &structure.textures, result?, &mut mipmap_generator)?;
+    }
+    Ok((structure, uploaded))
+}
+
+impl Uploaded {
+    /// Upload a prepared mesh or texture, `textures` are those of the document.
+    pub(super) fn upload(
+        &mut self,
+        context: &crate::Context,
+        textures: &[TextureStructure],
+        prepared: Prepared,
+        mipmap_generator: &mut MipmapGenerator,
+    ) -> Result<(), LoaderError> {
+        match prepared {
+            Prepared::Mesh(key, mesh) => {
+                self.meshes.insert(key, mesh.to_gpu(context));
+            }
+            Prepared::Texture(key, decoded) => {
+                let sampled_texture = upload_sampled_texture(
+                    context,
+                    key,
+                    &textures[key.texture_index],
+                    &decoded,
+                    mipmap_generator,
+                )?;
+                self.textures.insert(key, sampled_texture);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loader::export::test::textured_triangle;
+
+    #[test]
+    fn test_prepare_with_cache() {
+        let (exporter, _, _) = textured_triangle();
+        let dir =
+            std::env::temp_dir().join(format!("test_prepare_with_cache_{}", std::process::id()));
+        let path = dir.join("triangle.glb");
+        std::fs::create_dir_all(&dir).unwrap();
+        exporter.save(&path).unwrap();
+
+        let options = LoadOptions::default().with_cache_dir(dir.join("cache"));
+        let prepare = || {
+            let results = Mutex::new(vec![]);
+            let structure = prepare_gltf(
+                &path,
+                &options,
+                |_| true,
+                |result| {
+                    results.lock().unwrap().push(result.unwrap());
+                    true
+                },
+            )
+            .unwrap();
+            (structure, results.into_inner().unwrap())
+        };
+
+        // The first load imports the document and writes the entry, with the mip levels of the image.
+        let (structure, prepared) = prepare();
+        assert_eq!(prepared.len(), 2);
+        let cache = Cache::new(options.cache_dir.as_ref().unwrap(), &path, &options).unwrap();
+        let (cached_structure, cached) = cache.read().unwrap();
+        assert_eq!(format!("{cached_structure:?}"), format!("{structure:?}"));
+        assert!(cached.iter().any(|p| matches!(
+            p,
+            Prepared::Texture(_, DecodedImage::Ktx2 { image, .. }) if image.levels.len() == 2
+        )));
+        // The next load reads the structure from the entry as well.
+        let (structure, prepared) = prepare();
+        assert_eq!(prepared.len(), 2);
+        assert_eq!(format!("{cached_structure:?}"), format!("{structure:?}"));
+
+        // A damaged entry is rebuilt, and the load still succeeds.
+        let mut bytes = std::fs::read(cache.path()).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(cache.path(), &bytes).unwrap();
+        assert_eq!(prepare().1.len(), 2);
+        assert!(cache.read().is_ok());
+
+        // Other options use another key.
+        let scaled = options.clone().with_unit_scale(0.001);
+        let other = Cache::new(options.cache_dir.as_ref().unwrap(), &path, &scaled).unwrap();
+        assert!(matches!(other.read(), Err(CacheError::Stale)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
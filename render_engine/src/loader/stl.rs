@@ -0,0 +1,105 @@
+// STL files, in the binary and the ASCII variant, https://en.wikipedia.org/wiki/STL_(file_format)
+//
+// Triangles don't share vertices in STL, so the normals are the flat face normals.
+
+use crate::vertex::mesh::CpuMesh;
+use glam::Vec3;
+
+const HEADER_SIZE: usize = 80;
+const TRIANGLE_SIZE: usize = 50;
+
+/// Parse an STL file, the normals in the file are ignored and calculated from the triangles.
+pub fn parse_stl(bytes: &[u8]) -> Result<CpuMesh, String> {
+    // ASCII files start with 'solid', but so do some binary files, so the size of the binary layout decides.
+    let binary_count = bytes
+        .get(HEADER_SIZE..HEADER_SIZE + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize);
+    let is_binary =
+        binary_count.is_some_and(|count| bytes.len() == HEADER_SIZE + 4 + count * TRIANGLE_SIZE);
+    let position = if is_binary {
+        parse_binary(&bytes[HEADER_SIZE + 4..])
+    } else if bytes.trim_ascii_start().starts_with(b"solid") {
+        let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        parse_ascii(text)?
+    } else {
+        return Err("neither a binary nor an ASCII STL file".to_owned());
+    };
+    let index = (0..position.len() as u32).collect();
+    let mut mesh = CpuMesh::new(position, index);
+    mesh.calculate_normals();
+    Ok(mesh)
+}
+
+fn parse_binary(triangles: &[u8]) -> Vec<Vec3> {
+    let f32_at =
+        |b: &[u8], offset: usize| f32::from_le_bytes(b[offset..offset + 4].try_into().unwrap());
+    triangles
+        .chunks_exact(TRIANGLE_SIZE)
+        .flat_map(|triangle| {
+            // The face normal comes first, then the three vertices and a two byte attribute.
+            [1, 2, 3].map(|v| {
+                let offset = v * 12;
+                Vec3::new(
+                    f32_at(triangle, offset),
+                    f32_at(triangle, offset + 4),
+                    f32_at(triangle, offset + 8),
+                )
+            })
+        })
+        .collect()
+}
+
+fn parse_ascii(text: &str) -> Result<Vec<Vec3>, String> {
+    let mut position = vec![];
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("vertex") {
+            continue;
+        }
+        let mut v = [0.0; 3];
+        for value in v.iter_mut() {
+            let token = tokens.next().ok_or("vertex with less than 3 coordinates")?;
+            *value = token
+                .parse()
+                .map_err(|_| format!("{token:?} is not a number"))?;
+        }
+        position.push(Vec3::from_array(v));
+    }
+    if position.len() % 3 != 0 {
+        return Err(format!("{} vertices do not form triangles", position.len()));
+    }
+    Ok(position)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use glam::Vec3A;
+
+    #[test]
+    fn test_parse_stl() {
+        let ascii = "solid test\n\
+            facet normal 0 0 1\n outer loop\n\
+              vertex 0 0 0\n vertex 1 0 0\n vertex 0 1 0\n\
+            endloop\n endfacet\n\
+            endsolid test\n";
+        let mesh = parse_stl(ascii.as_bytes()).unwrap();
+        assert_eq!(mesh.position[1], Vec3::X);
+        assert_eq!(mesh.index, vec![0, 1, 2]);
+        assert_eq!(mesh.normal.as_ref().unwrap()[0], Vec3A::Z);
+
+        // The same triangle in binary, with a header that starts like an ASCII file.
+        let mut binary = b"solid but actually binary".to_vec();
+        binary.resize(HEADER_SIZE, 0);
+        binary.extend(1u32.to_le_bytes());
+        for value in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend(value.to_le_bytes());
+        }
+        binary.extend([0, 0]);
+        let binary_mesh = parse_stl(&binary).unwrap();
+        assert_eq!(binary_mesh.position, mesh.position);
+        assert_eq!(binary_mesh.normal, mesh.normal);
+    }
+}
@@ -0,0 +1,319 @@
+// The structure of a gltf document: everything the scene is built from besides the meshes and textures. That is the
+// node hierarchy of the selected scene, the materials and samplers, the skins and animations, and the lights and
+// cameras. It is extracted right after the import, such that the cache can store it and a load from the cache doesn't
+// need the document at all.
+
+use super::prepare::Job;
+use super::{
+    LoadOptions, LoaderError, MagFilterModeToWgpu, MinFilterModeToWgpu, PrimitiveKey,
+    SampledTextureKey, TextureTransformToUniform, TransformToGlam, WrappingModeToWgpu,
+    load_gltf_animation, load_gltf_camera, load_gltf_light, load_gltf_nodes, load_gltf_skin,
+    load_material_uniform, parse_texture_transform, select_scene,
+};
+use crate::animation;
+use crate::lights::Light;
+use crate::scene;
+use crate::texture::{MaterialUniform, TextureTransform, TextureType};
+use crate::view::camera::Camera;
+use glam::Mat4;
+use std::collections::{HashSet, VecDeque};
+
+/// A texture of a material, as it is sampled by the primitives with that material.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(super) struct MaterialTexture {
+    pub key: SampledTextureKey,
+    pub texture_type: TextureType,
+    /// The uv map, with the override of the texture transform applied.
+    pub tex_coord: u32,
+    pub transform: TextureTransform,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct PrimitiveStructure {
+    pub material: MaterialUniform,
+    pub textures: Vec<MaterialTexture>,
+    pub has_morph_targets: bool,
+}
+
+/// The name and sampler of a texture.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct TextureStructure {
+    /// The name of the texture, or of its image.
+    pub name: Option<String>,
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    /// None if the texture only samples its first level.
+    pub mipmap_filter: Option<wgpu::FilterMode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct NodeStructure {
+    pub mesh: Option<usize>,
+    /// In the order of the document, which is the order the scene is built in.
+    pub children: Vec<usize>,
+}
+
+/// Everything of a document that the scene is built from besides the meshes and textures, in the convention of the
+/// file. Indices are those of the document.
+#[derive(Debug, Clone)]
+pub(super) struct GltfStructure {
+    /// The names of all scenes in the document.
+    pub scene_names: Vec<Option<String>>,
+    /// The selected scene.
+    pub scene: usize,
+    /// The root nodes of the selected scene.
+    pub roots: Vec<usize>,
+    pub nodes: Vec<scene::Node>,
+    pub hierarchy: Vec<NodeStructure>,
+    /// The primitives of each mesh.
+    pub meshes: Vec<Vec<PrimitiveStructure>>,
+    pub textures: Vec<TextureStructure>,
+    pub skins: Vec<scene::Skin>,
+    pub animations: Vec<animation::Animation>,
+    /// The lights and cameras of the selected scene, placed by the nodes they are attached to, in traversal order.
+    pub lights: Vec<Light>,
+    pub cameras: Vec<Camera>,
+}
+
+impl GltfStructure {
+    pub fn new(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        options: &LoadOptions,
+    ) -> Result<Self, LoaderError> {
+        let gltf_scene = select_scene(document, options)?;
+
+        // Lights and cameras are placed with the transform of the path the traversal reached them by.
+        let mut lights = vec![];
+        let mut cameras = vec![];
+        let mut queue: VecDeque<_> = gltf_scene.nodes().map(|n| (Mat4::IDENTITY, n)).collect();
+        while let Some((parent_transform, node)) = queue.pop_front() {
+            let transform = parent_transform * node.transform().to_glam();
+            if let Some(light) = node.light() {
+                lights.push(load_gltf_light(&light, &transform));
+            }
+            if let Some(camera) = node.camera() {
+                cameras.push(load_gltf_camera(&camera, &transform));
+            }
+            queue.extend(node.children().map(|child| (transform, child)));
+        }
+
+        Ok(Self {
+            scene_names: document
+                .scenes()
+                .map(|s| s.name().map(|z| z.to_owned()))
+                .collect(),
+            scene: gltf_scene.index(),
+            roots: gltf_scene.nodes().map(|n| n.index()).collect(),
+            nodes: load_gltf_nodes(document),
+            hierarchy: document
+                .nodes()
+                .map(|node| NodeStructure {
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    children: node.children().map(|child| child.index()).collect(),
+                })
+                .collect(),
+            meshes: document
+                .meshes()
+                .map(|mesh| {
+                    mesh.primitives()
+                        .map(|primitive| PrimitiveStructure {
+                            material: load_material_uniform(&primitive.material()),
+                            textures: material_textures(&primitive.material()),
+                            has_morph_targets: primitive.morph_targets().next().is_some(),
+                        })
+                        .collect()
+                })
+                .collect(),
+            textures: document.textures().map(|t| texture_structure(&t)).collect(),
+            skins: document
+                .skins()
+                .map(|skin| load_gltf_skin(&skin, buffers))
+                .collect(),
+            animations: document
+                .animations()
+                .map(|a| load_gltf_animation(&a, buffers))
+                .collect(),
+            lights,
+            cameras,
+        })
+    }
+
+    pub fn scene_name(&self) -> Option<&str> {
+        self.scene_names.get(self.scene)?.as_deref()
+    }
+
+    /// The meshes and textures used by the selected scene.
+    pub fn jobs(&self) -> Vec<Job> {
+        let mut jobs = vec![];
+        let mut meshes = HashSet::new();
+        let mut textures = HashSet::new();
+        let mut stack = self.roots.clone();
+        while let Some(node) = stack.pop() {
+            stack.extend(self.hierarchy[node].children.iter().copied());
+            let Some(mesh_index) = self.hierarchy[node].mesh else {
+                continue;
+            };
+            if !meshes.insert(mesh_index) {
+                continue;
+            }
+            for (primitive_index, primitive) in self.meshes[mesh_index].iter().enumerate() {
+                jobs.push(Job::Mesh(PrimitiveKey {
+                    mesh_index,
+                    primitive_index,
+                }));
+                for texture in primitive.textures.iter() {
+                    if textures.insert(texture.key) {
+                        jobs.push(Job::Texture(texture.key));
+                    }
+                }
+            }
+        }
+        jobs
+    }
+
+    /// Whether all indices refer to existing nodes, meshes, textures and skins. The document is validated when it is
+    /// imported, this is for structures that come from elsewhere.
+    pub fn is_consistent(&self) -> bool {
+        let node = |index: &usize| *index < self.nodes.len();
+        self.hierarchy.len() == self.nodes.len()
+            && self.scene < self.scene_names.len()
+            && self.roots.iter().all(node)
+            && self.hierarchy.iter().all(|n| {
+                n.children.iter().all(node) && n.mesh.is_none_or(|m| m < self.meshes.len())
+            })
+            && self.nodes.iter().all(|n| {
+                n.parent.is_none_or(|p| node(&p.0)) && n.skin.is_none_or(|s| s < self.skins.len())
+            })
+            && self
+                .meshes
+                .iter()
+                .flatten()
+                .flat_map(|p| p.textures.iter())
+                .all(|t| t.key.texture_index < self.textures.len())
+            && self
+                .skins
+                .iter()
+                .flat_map(|s| s.joints.iter())
+                .all(|j| node(&j.0))
+            && self
+                .animations
+                .iter()
+                .flat_map(|a| a.channels.iter())
+                .all(|c| node(&c.node.0))
+    }
+}
+
+fn texture_structure(texture: &gltf::Texture<'_>) -> TextureStructure {
+    let sampler = texture.sampler();
+    // Without a filter specified we use trilinear filtering.
+    let min_filter = sampler
+        .min_filter()
+        .unwrap_or(gltf::texture::MinFilter::LinearMipmapLinear);
+    TextureStructure {
+        name: texture
+            .name()
+            .or(texture.source().and_then(|i| i.name()))
+            .map(|z| z.to_owned()),
+        // S(U) and T(V): https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_metallicroughnesstexture
+        address_mode_u: sampler.wrap_s().to_wgpu(),
+        address_mode_v: sampler.wrap_t().to_wgpu(),
+        mag_filter: sampler
+            .mag_filter()
+            .unwrap_or(gltf::texture::MagFilter::Linear)
+            .to_wgpu(),
+        min_filter: min_filter.to_wgpu(),
+        mipmap_filter: min_filter.mipmap_filter(),
+    }
+}
+
+/// A texture of a material, the KHR_texture_transform may override the uv map it uses.
+fn material_texture(
+    texture: gltf::Texture<'_>,
+    format: wgpu::TextureFormat,
+    texture_type: TextureType,
+    tex_coord: u32,
+    transform: Option<impl TextureTransformToUniform>,
+) -> MaterialTexture {
+    let (tex_coord, transform) = match transform {
+        Some(transform) => (
+            transform.tex_coord_override().unwrap_or(tex_coord),
+            transform.to_uniform(),
+        ),
+        None => (tex_coord, Default::default()),
+    };
+    MaterialTexture {
+        key: SampledTextureKey {
+            texture_index: texture.index(),
+            format,
+        },
+        texture_type,
+        tex_coord,
+        transform,
+    }
+}
+
+/// The textures of a material, with the format each is sampled as.
+fn material_textures(material: &gltf::Material) -> Vec<MaterialTexture> {
+    use wgpu::TextureFormat::{Rgba8Unorm, Rgba8UnormSrgb};
+    let pbr = material.pbr_metallic_roughness();
+    let mut textures = vec![];
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_emissivetexture
+    // is in srgb
+    if let Some(info) = material.emissive_texture() {
+        textures.push(material_texture(
+            info.texture(),
+            Rgba8UnormSrgb,
+            TextureType::Emissive,
+            info.tex_coord(),
+            info.texture_transform(),
+        ));
+    }
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_basecolortexture
+    // must be srgb
+    if let Some(info) = pbr.base_color_texture() {
+        textures.push(material_texture(
+            info.texture(),
+            Rgba8UnormSrgb,
+            TextureType::BaseColor,
+            info.tex_coord(),
+            info.texture_transform(),
+        ));
+    }
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_pbrmetallicroughness_metallicroughnesstexture
+    // is in linear transform function, so NOT srgb!
+    if let Some(info) = pbr.metallic_roughness_texture() {
+        textures.push(material_texture(
+            info.texture(),
+            Rgba8Unorm,
+            TextureType::MetallicRoughness,
+            info.tex_coord(),
+            info.texture_transform(),
+        ));
+    }
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_normaltexture
+    // Linear transfer function.
+    if let Some(info) = material.normal_texture() {
+        textures.push(material_texture(
+            info.texture(),
+            Rgba8Unorm,
+            TextureType::Normal,
+            info.tex_coord(),
+            parse_texture_transform(info.extension_value("KHR_texture_transform")),
+        ));
+    }
+    // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#_material_occlusiontexture
+    // Linear transfer function.
+    if let Some(info) = material.occlusion_texture() {
+        textures.push(material_texture(
+            info.texture(),
+            Rgba8Unorm,
+            TextureType::Occlusion,
+            info.tex_coord(),
+            parse_texture_transform(info.extension_value("KHR_texture_transform")),
+        ));
+    }
+    textures
+}
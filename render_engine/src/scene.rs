@@ -0,0 +1,296 @@
+use crate::fragment::mesh_object_textured::MeshObjectTextured;
+use glam::{Mat4, Quat, Vec3};
+
+/// Refers to a node in a [`Scene`], for gltf files this is the node index in the document.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeHandle(pub usize);
+
+/// An instance of an object in [`Scene::objects`], objects are shared by nodes that place the same mesh.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectInstance {
+    /// Index into [`Scene::objects`].
+    pub object: usize,
+
+    /// Index into the instances of that object.
+    pub instance: usize,
+}
+
+/// A node of the scene hierarchy, with its local transform decomposed such that parts of it can be replaced.
+#[derive(Clone, Debug)]
+pub struct Node {
+    /// Name of the node, if it has one.
+    pub name: Option<String>,
+
+    /// The parent, none for root nodes.
+    pub parent: Option<NodeHandle>,
+
+    /// The children of this node.
+    pub children: Vec<NodeHandle>,
+
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+
+    /// Morph target weights of the mesh instantiated by this node, empty if it has none.
+    pub weights: Vec<f32>,
+
+    /// Application specific data as raw json, if present.
+    pub extras: Option<String>,
+
+    /// The object instances placed by this node.
+    pub objects: Vec<ObjectInstance>,
+
+    /// Index into [`Scene::skins`] if the objects of this node are skinned.
+    pub skin: Option<usize>,
+}
+
+impl Node {
+    pub fn new() -> Self {
+        Self {
+            name: None,
+            parent: None,
+            children: vec![],
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+            weights: vec![],
+            extras: None,
+            objects: vec![],
+            skin: None,
+        }
+    }
+
+    pub fn local_transform(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    /// Set the local transform, it must be decomposable into scale, rotation and translation.
+    pub fn set_local_transform(&mut self, transform: &Mat4) {
+        let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+        self.scale = scale;
+        self.rotation = rotation;
+        self.translation = translation;
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A skin, binding the joint matrices of a mesh object to nodes.
+#[derive(Clone, Debug)]
+pub struct Skin {
+    /// The joint nodes.
+    pub joints: Vec<NodeHandle>,
+
+    /// Inverse bind matrix for each joint.
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    /// Calculate the joint matrices, these are expressed in the frame of the mesh node such that the instance
+    /// transform can still be applied on top.
+    pub fn joint_matrices(&self, world_transforms: &[Mat4], mesh_transform: &Mat4) -> Vec<Mat4> {
+        // https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#joint-hierarchy
+        let to_mesh_frame = mesh_transform.inverse();
+        self.joints
+            .iter()
+            .enumerate()
+            .map(|(i, joint)| {
+                let inverse_bind = self
+                    .inverse_bind_matrices
+                    .get(i)
+                    .copied()
+                    .unwrap_or(Mat4::IDENTITY);
+                to_mesh_frame * world_transforms[joint.0] * inverse_bind
+            })
+            .collect()
+    }
+}
+
+/// A hierarchy of nodes that place the objects.
+///
+/// To move part of the scene, change the local transform of a node and call [`Scene::update`].
+pub struct Scene {
+    /// Name of the scene, if it has one.
+    pub name: Option<String>,
+
+    /// All nodes, indexed by their handle.
+    pub nodes: Vec<Node>,
+
+    /// The nodes without a parent that make up this scene.
+    pub roots: Vec<NodeHandle>,
+
+    /// The world transform of each node, updated by [`Scene::update_world_transforms`].
+    pub world_transforms: Vec<Mat4>,
+
+    /// The renderable objects.
+    pub objects: Vec<MeshObjectTextured>,
+
+    /// Skins referenced by the nodes.
+    pub skins: Vec<Skin>,
+}
+
+impl Scene {
+    /// Create a scene from nodes, the parents of the nodes must be set, children and roots are derived from them.
+    /// Nodes whose parents form a cycle can't be reached from the roots, they keep an identity world transform.
+    pub fn new(mut nodes: Vec<Node>) -> Self {
+        for node in nodes.iter_mut() {
+            node.children.clear();
+        }
+        let mut roots = vec![];
+        for index in 0..nodes.len() {
+            match nodes[index].parent {
+                Some(parent) => nodes[parent.0].children.push(NodeHandle(index)),
+                None => roots.push(NodeHandle(index)),
+            }
+        }
+        let mut res = Self {
+            name: None,
+            world_transforms: vec![Mat4::IDENTITY; nodes.len()],
+            nodes,
+            roots,
+            objects: vec![],
+            skins: vec![],
+        };
+        res.update_world_transforms();
+        res
+    }
+
+    pub fn with_name(mut self, name: Option<&str>) -> Self {
+        self.name = name.map(|z| z.to_owned());
+        self
+    }
+
+    pub fn node(&self, handle: NodeHandle) -> &Node {
+        &self.nodes[handle.0]
+    }
+
+    pub fn node_mut(&mut self, handle: NodeHandle) -> &mut Node {
+        &mut self.nodes[handle.0]
+    }
+
+    /// Find the first node with this name.
+    pub fn find_by_name(&self, name: &str) -> Option<NodeHandle> {
+        self.nodes
+            .iter()
+            .position(|n| n.name.as_deref() == Some(name))
+            .map(NodeHandle)
+    }
+
+    pub fn world_transform(&self, handle: NodeHandle) -> Mat4 {
+        self.world_transforms[handle.0]
+    }
+
+    /// Set the local transform of a node, does NOT update the world transforms or objects.
+    pub fn set_local_transform(&mut self, handle: NodeHandle, transform: &Mat4) {
+        self.nodes[handle.0].set_local_transform(transform);
+    }
+
+    /// Recompute the world transforms from the local transforms.
+    pub fn update_world_transforms(&mut self) {
+        let mut stack: Vec<(Mat4, NodeHandle)> =
+            self.roots.iter().map(|r| (Mat4::IDENTITY, *r)).collect();
+        while let Some((parent_transform, handle)) = stack.pop() {
+            let node = &self.nodes[handle.0];
+            let world = parent_transform * node.local_transform();
+            self.world_transforms[handle.0] = world;
+            stack.extend(node.children.iter().map(|c| (world, *c)));
+        }
+    }
+
+    /// Push the world transforms, joint matrices and morph weights into the objects and update the gpu data of those
+    /// that changed.
+    ///
+    /// Objects with several instances are expected to be without skin and morph targets, as those are per object.
+    pub fn update_objects(&mut self) {
+        let mut changed = vec![false; self.objects.len()];
+        for node_index in 0..self.nodes.len() {
+            let node = &self.nodes[node_index];
+            let world_transform = self.world_transforms[node_index];
+            let joint_matrices = node
+                .skin
+                .map(|i| self.skins[i].joint_matrices(&self.world_transforms, &world_transform));
+            for object_instance in node.objects.iter() {
+                let mesh_object = &mut self.objects[object_instance.object].mesh_object;
+                let changed = &mut changed[object_instance.object];
+                if mesh_object.instances.get(object_instance.instance) != Some(&world_transform) {
+                    mesh_object.set_instance_transform(object_instance.instance, &world_transform);
+                    *changed = true;
+                }
+                if !node.weights.is_empty() {
+                    let previous = std::mem::take(&mut mesh_object.morph_weights);
+                    mesh_object.set_morph_weights(&node.weights);
+                    *changed |= mesh_object.morph_weights != previous;
+                }
+                if let Some(joint_matrices) = joint_matrices.as_ref()
+                    && mesh_object.joint_matrices != *joint_matrices
+                {
+                    mesh_object.set_joint_matrices(joint_matrices);
+                    *changed = true;
+                }
+            }
+        }
+        for (object, changed) in self.objects.iter_mut().zip(changed) {
+            if changed {
+                object.mesh_object.replace_gpu_data();
+            }
+        }
+    }
+
+    /// Propagate changed local transforms to the world transforms and the objects.
+    pub fn update(&mut self) {
+        self.update_world_transforms();
+        self.update_objects();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_scene_world_transforms() {
+        let parent = Node {
+            name: Some("parent".to_owned()),
+            translation: Vec3::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        let child = Node {
+            name: Some("child".to_owned()),
+            parent: Some(NodeHandle(0)),
+            translation: Vec3::new(0.0, 2.0, 0.0),
+            ..Default::default()
+        };
+        let mut scene = Scene::new(vec![parent, child]);
+        let child = scene.find_by_name("child").unwrap();
+        assert_eq!(scene.roots, vec![NodeHandle(0)]);
+        assert_eq!(scene.node(NodeHandle(0)).children, vec![child]);
+        assert_eq!(
+            scene.world_transform(child).w_axis.truncate(),
+            Vec3::new(1.0, 2.0, 0.0)
+        );
+
+        // Moving the parent moves the child along.
+        scene.set_local_transform(
+            NodeHandle(0),
+            &Mat4::from_translation(Vec3::new(0.0, 0.0, 3.0)),
+        );
+        scene.update_world_transforms();
+        assert_eq!(
+            scene.world_transform(child).w_axis.truncate(),
+            Vec3::new(0.0, 2.0, 3.0)
+        );
+
+        // A node that is its own parent is not walked.
+        let cyclic = Node {
+            parent: Some(NodeHandle(0)),
+            translation: Vec3::X,
+            ..Default::default()
+        };
+        let scene = Scene::new(vec![cyclic]);
+        assert!(scene.roots.is_empty());
+        assert_eq!(scene.world_transform(NodeHandle(0)), Mat4::IDENTITY);
+    }
+}
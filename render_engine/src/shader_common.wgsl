@@ -9,6 +9,8 @@ struct ViewUniform {
     view_proj: mat4x4<f32>,
     camera_world_position: vec3<f32>,
     pad: u32,
+    // Size of the render target in pixels.
+    viewport_size: vec2<f32>,
 }
 // @binding(CAMERA_UNIFORM_BINDING) @group(CAMERA_UNIFORM_SET)
 // var<storage, read> camera_uniform : CameraUniformType;
@@ -18,7 +20,7 @@ struct ViewUniform {
 // From common, vertex output;
 struct CommonVertexOutput {
     @builtin(position) clip_position  : vec4<f32>,
-    @location(0) color : vec3<f32>,
+    @location(0) color : vec4<f32>,
     @location(1) normal : vec3<f32>,
     @location(2) view_vector : vec3<f32>,
     @location(3) world_pos : vec3<f32>,
@@ -28,6 +30,9 @@ struct CommonVertexOutput {
     @location(5) tangent_w : vec3<f32>,
     @location(6) bitangent_w : vec3<f32>,
     @location(7) normal_w : vec3<f32>,
+
+    // The second uv map, textures select which one they use.
+    @location(8) uv1_pos : vec2<f32>,
 };
 
 struct VertexInput {
@@ -49,6 +54,7 @@ const LIGHT_TYPE_OFF : LightType = 0;
 const LIGHT_TYPE_DIRECTIONAL : LightType = 1;
 const LIGHT_TYPE_OMNI : LightType = 2;
 const LIGHT_TYPE_AMBIENT : LightType = 3;
+const LIGHT_TYPE_SPOT : LightType = 4;
 
 
 struct Light {
@@ -57,6 +63,10 @@ struct Light {
      @location(2) color: vec3<f32>,
      @location(3) intensity: f32 ,
      @location(4) light_type: LightType,
+     // Zero is infinite range.
+     @location(5) range: f32,
+     @location(6) inner_cone_angle: f32,
+     @location(7) outer_cone_angle: f32,
      // hardness_kd_ks: vec3f,
 };
 
@@ -68,7 +78,7 @@ fn Light_direction(me: ptr<function,Light>,  at_point: vec3<f32>) -> vec3<f32> {
             {
                 return normalize(-(*me).direction);
             }
-        case LIGHT_TYPE_OMNI:
+        case LIGHT_TYPE_OMNI, LIGHT_TYPE_SPOT:
             {
                 return normalize((*me).position - at_point);
             }
@@ -78,6 +88,15 @@ fn Light_direction(me: ptr<function,Light>,  at_point: vec3<f32>) -> vec3<f32> {
             }
     }
 }
+/// Smoothly go to zero intensity at the light range, from
+/// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_lights_punctual#range-property
+fn Light_range_attenuation(me: ptr<function,Light>, distance: f32) -> f32 {
+    if ((*me).range <= 0.0) {
+        return 1.0;
+    }
+    return clamp(1.0 - pow(distance / (*me).range, 4.0), 0.0, 1.0);
+}
+
 /// Determines the light intensity at a certain point for this light, accounting for falloff.
 fn Light_intensity(me: ptr<function,Light>,  at_point: vec3<f32>) -> f32 {
     switch((*me).light_type)
@@ -86,11 +105,25 @@ fn Light_intensity(me: ptr<function,Light>,  at_point: vec3<f32>) -> f32 {
             {
                 // This falls off...
                 let distance = length((*me).position - at_point);
-                // This is... well not ideal, it becomes very very large when the light is close, and also doesn't
-                // take into account anything like a light range, or other falloff properties, but it is good enough
-                // for the simple point lights I have right now.
+                // This is... well not ideal, it becomes very very large when the light is close, but it is good
+                // enough for the simple point lights I have right now.
+                let remaining = 1.0 / (distance * distance + 0.01);
+                return (*me).intensity * remaining * Light_range_attenuation(me, distance);
+            }
+        case LIGHT_TYPE_SPOT:
+            {
+                // Same falloff as the omni light, but also limited to the cone, see
+                // https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_lights_punctual#inner-and-outer-cone-angles
+                let distance = length((*me).position - at_point);
                 let remaining = 1.0 / (distance * distance + 0.01);
-                return (*me).intensity * remaining;
+                let cos_outer = cos((*me).outer_cone_angle);
+                let cos_inner = cos((*me).inner_cone_angle);
+                let light_angle_scale = 1.0 / max(0.001, cos_inner - cos_outer);
+                let light_angle_offset = -cos_outer * light_angle_scale;
+                let cd = dot(normalize((*me).direction), normalize(at_point - (*me).position));
+                var angular_attenuation = clamp(cd * light_angle_scale + light_angle_offset, 0.0, 1.0);
+                angular_attenuation *= angular_attenuation;
+                return (*me).intensity * remaining * Light_range_attenuation(me, distance) * angular_attenuation;
             }
         default :
             {
@@ -112,6 +145,23 @@ const TEXTURE_UNIFORM_SET : u32 = 3;
 const TEXTURE_UNIFORM_BINDING_TEXTURE: u32 = 0;
 const TEXTURE_UNIFORM_BINDING_SAMPLER: u32 = 1;
 const TEXTURE_UNIFORM_META: u32 = 2;
+const TEXTURE_UNIFORM_MATERIAL: u32 = 3;
+
+// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_texture_transform
+struct TextureTransform {
+     offset: vec2<f32>,
+     scale: vec2<f32>,
+     rotation: f32,
+};
+
+/// Apply the transform to the uv coordinates, this is translation * rotation * scale.
+fn TextureTransform_apply(me: TextureTransform, uv: vec2<f32>) -> vec2<f32> {
+    let c = cos(me.rotation);
+    let s = sin(me.rotation);
+    let scaled = uv * me.scale;
+    let rotated = vec2<f32>(c * scaled.x + s * scaled.y, -s * scaled.x + c * scaled.y);
+    return rotated + me.offset;
+}
 
 alias TextureType = u32;
 const TEXTURE_TYPE_NONE : TextureType = 0;
@@ -127,10 +177,42 @@ struct TextureUniform {
      @location(2) occlusion: u32,
      @location(3) normal: u32,
      @location(4) emissive: u32,
+     // The uv map (TEXCOORD_n) each texture uses.
+     @location(5) base_color_tex_coord: u32,
+     @location(6) metallic_roughness_tex_coord: u32,
+     @location(7) occlusion_tex_coord: u32,
+     @location(8) normal_tex_coord: u32,
+     @location(9) emissive_tex_coord: u32,
+     // The uv transform applied before sampling each texture.
+     base_color_transform: TextureTransform,
+     metallic_roughness_transform: TextureTransform,
+     occlusion_transform: TextureTransform,
+     normal_transform: TextureTransform,
+     emissive_transform: TextureTransform,
 };
 // @binding(TEXTURE_UNIFORM_META) @group(TEXTURE_UNIFORM_SET)
 // var<storage, read> texture_uniform : array<TextureUniform>;
 
+alias AlphaMode = u32;
+const ALPHA_MODE_OPAQUE : AlphaMode = 0;
+const ALPHA_MODE_MASK : AlphaMode = 1;
+const ALPHA_MODE_BLEND : AlphaMode = 2;
+
+// The global factors of the material, https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#reference-material
+struct MaterialUniform {
+     base_color_factor: vec4<f32>,
+     emissive_factor: vec3<f32>,
+     metallic_factor: f32,
+     roughness_factor: f32,
+     normal_scale: f32,
+     occlusion_strength: f32,
+     alpha_cutoff: f32,
+     alpha_mode: AlphaMode,
+     double_sided: u32,
+};
+// @binding(TEXTURE_UNIFORM_MATERIAL) @group(TEXTURE_UNIFORM_SET)
+// var<storage, read> material_uniform : array<MaterialUniform>;
+
 //-----------------------------------------------------
 // Constants
 const PI_F: f32 = 3.141592653589793;
@@ -0,0 +1,583 @@
+// Loading of KTX2 textures, https://registry.khronos.org/KTX/specs/2.0/ktxspec.v2.html
+//
+// Block compressed payloads are uploaded as is if the device supports the format, otherwise the BC1 to BC5 formats
+// are decoded to rgba8 on the cpu. Levels may be zstd or zlib supercompressed.
+//
+// Basis universal payloads (BasisLZ/ETC1S and UASTC, with vkFormat 0) need a transcoder, which is not available here,
+// so KHR_texture_basisu is NOT supported. The gltf loader uses the png or jpeg fallback image of the texture for those,
+// and for the formats that can't be decoded here; a texture without a fallback fails to load.
+
+use thiserror::Error;
+
+const IDENTIFIER: [u8; 12] = [
+    0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+const SUPERCOMPRESSION_NONE: u32 = 0;
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+const SUPERCOMPRESSION_ZSTD: u32 = 2;
+const SUPERCOMPRESSION_ZLIB: u32 = 3;
+
+/// Decodes a block of 4x4 texels to rgba8, in row major order.
+type DecodeBlock = fn(&[u8]) -> [[u8; 4]; 16];
+
+#[derive(Error, Debug)]
+pub enum Ktx2Error {
+    #[error("not a ktx2 file")]
+    Identifier,
+    #[error("the file is truncated")]
+    Truncated,
+    #[error("vkFormat {0} is not supported")]
+    Format(u32),
+    #[error("basis universal payloads need a transcoder, which is not available")]
+    Basis,
+    #[error("supercompression scheme {0} is not supported")]
+    Supercompression(u32),
+    #[error("only 2d textures with a single layer and face are supported")]
+    Dimensions,
+    #[error("decompressing level {0} failed")]
+    Decompress(usize),
+    #[error("{0:?} is not supported by the device and can't be decoded on the cpu")]
+    NoFallback(wgpu::TextureFormat),
+    #[error("failed to read: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The mip levels of a 2d texture, level zero is the largest.
+#[derive(Clone, Debug)]
+pub struct Ktx2Image {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+/// Map the vulkan formats to wgpu, only the 8 bit rgba and the block compressed formats are supported.
+pub(crate) fn vk_format_to_wgpu(vk_format: u32) -> Option<wgpu::TextureFormat> {
+    use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+    let astc = |block, srgb| TextureFormat::Astc {
+        block,
+        channel: if srgb {
+            AstcChannel::UnormSrgb
+        } else {
+            AstcChannel::Unorm
+        },
+    };
+    Some(match vk_format {
+        37 => TextureFormat::Rgba8Unorm,
+        43 => TextureFormat::Rgba8UnormSrgb,
+        131 | 133 => TextureFormat::Bc1RgbaUnorm,
+        132 | 134 => TextureFormat::Bc1RgbaUnormSrgb,
+        135 => TextureFormat::Bc2RgbaUnorm,
+        136 => TextureFormat::Bc2RgbaUnormSrgb,
+        137 => TextureFormat::Bc3RgbaUnorm,
+        138 => TextureFormat::Bc3RgbaUnormSrgb,
+        139 => TextureFormat::Bc4RUnorm,
+        140 => TextureFormat::Bc4RSnorm,
+        141 => TextureFormat::Bc5RgUnorm,
+        142 => TextureFormat::Bc5RgSnorm,
+        143 => TextureFormat::Bc6hRgbUfloat,
+        144 => TextureFormat::Bc6hRgbFloat,
+        145 => TextureFormat::Bc7RgbaUnorm,
+        146 => TextureFormat::Bc7RgbaUnormSrgb,
+        147 => TextureFormat::Etc2Rgb8Unorm,
+        148 => TextureFormat::Etc2Rgb8UnormSrgb,
+        149 => TextureFormat::Etc2Rgb8A1Unorm,
+        150 => TextureFormat::Etc2Rgb8A1UnormSrgb,
+        151 => TextureFormat::Etc2Rgba8Unorm,
+        152 => TextureFormat::Etc2Rgba8UnormSrgb,
+        153 => TextureFormat::EacR11Unorm,
+        154 => TextureFormat::EacR11Snorm,
+        155 => TextureFormat::EacRg11Unorm,
+        156 => TextureFormat::EacRg11Snorm,
+        // The astc formats come in unorm and srgb pairs, in order of block size.
+        157..=184 => {
+            let blocks = [
+                AstcBlock::B4x4,
+                AstcBlock::B5x4,
+                AstcBlock::B5x5,
+                AstcBlock::B6x5,
+                AstcBlock::B6x6,
+                AstcBlock::B8x5,
+                AstcBlock::B8x6,
+                AstcBlock::B8x8,
+                AstcBlock::B10x5,
+                AstcBlock::B10x6,
+                AstcBlock::B10x8,
+                AstcBlock::B10x10,
+                AstcBlock::B12x10,
+                AstcBlock::B12x12,
+            ];
+            let index = (vk_format - 157) as usize;
+            astc(blocks[index / 2], index % 2 == 1)
+        }
+        _ => return None,
+    })
+}
+
+/// The vulkan format of a format supported by [`vk_format_to_wgpu`].
+pub(crate) fn wgpu_to_vk_format(format: wgpu::TextureFormat) -> Option<u32> {
+    (0..=184).find(|vk_format| vk_format_to_wgpu(*vk_format) == Some(format))
+}
+
+/// The number of bytes of a level of this size, as tightly packed rows of blocks. None if that doesn't fit in memory.
+fn level_byte_size(format: wgpu::TextureFormat, width: u32, height: u32) -> Option<usize> {
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4);
+    let blocks = u64::from(width.div_ceil(block_width)) * u64::from(height.div_ceil(block_height));
+    blocks
+        .checked_mul(u64::from(block_size))
+        .and_then(|size| usize::try_from(size).ok())
+}
+
+impl Ktx2Image {
+    /// Whether the data starts with the ktx2 identifier.
+    pub fn is_ktx2(bytes: &[u8]) -> bool {
+        bytes.starts_with(&IDENTIFIER)
+    }
+
+    /// Parse a ktx2 file, decompressing the levels if they use zstd or zlib supercompression.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Ktx2Error> {
+        if !Self::is_ktx2(bytes) {
+            return Err(Ktx2Error::Identifier);
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(Ktx2Error::Truncated);
+        }
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let vk_format = u32_at(12);
+        let width = u32_at(20);
+        let height = u32_at(24);
+        let depth = u32_at(28);
+        let layer_count = u32_at(32);
+        let face_count = u32_at(36);
+        let level_count = u32_at(40).max(1) as usize;
+        let supercompression = u32_at(44);
+
+        if supercompression == SUPERCOMPRESSION_BASIS_LZ || vk_format == 0 {
+            return Err(Ktx2Error::Basis);
+        }
+        if ![
+            SUPERCOMPRESSION_NONE,
+            SUPERCOMPRESSION_ZSTD,
+            SUPERCOMPRESSION_ZLIB,
+        ]
+        .contains(&supercompression)
+        {
+            return Err(Ktx2Error::Supercompression(supercompression));
+        }
+        if width == 0 || height == 0 || depth > 1 || layer_count > 1 || face_count != 1 {
+            return Err(Ktx2Error::Dimensions);
+        }
+        // A level past the 1x1 one would be rejected by the device.
+        if level_count > super::mipmap::mip_level_count(width, height) as usize {
+            return Err(Ktx2Error::Dimensions);
+        }
+        let format = vk_format_to_wgpu(vk_format).ok_or(Ktx2Error::Format(vk_format))?;
+
+        if bytes.len() < HEADER_SIZE + level_count * LEVEL_INDEX_ENTRY_SIZE {
+            return Err(Ktx2Error::Truncated);
+        }
+        let mut levels = vec![];
+        for level in 0..level_count {
+            let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+            let offset = u64_at(entry) as usize;
+            let length = u64_at(entry + 8) as usize;
+            let data = bytes
+                .get(offset..offset.saturating_add(length))
+                .ok_or(Ktx2Error::Truncated)?;
+            let data = match supercompression {
+                SUPERCOMPRESSION_ZSTD => {
+                    zstd_decompress(data).ok_or(Ktx2Error::Decompress(level))?
+                }
+                SUPERCOMPRESSION_ZLIB => miniz_oxide::inflate::decompress_to_vec_zlib(data)
+                    .map_err(|_| Ktx2Error::Decompress(level))?,
+                _ => data.to_vec(),
+            };
+            let expected =
+                level_byte_size(format, (width >> level).max(1), (height >> level).max(1))
+                    .ok_or(Ktx2Error::Dimensions)?;
+            if data.len() < expected {
+                return Err(Ktx2Error::Truncated);
+            }
+            levels.push(data);
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+        })
+    }
+
+    /// Read and parse a ktx2 file.
+    pub fn from_path(path: &std::path::Path) -> Result<Self, Ktx2Error> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    /// Interpret the data as srgb or linear, formats without an srgb variant are left as is.
+    pub fn with_srgb(mut self, srgb: bool) -> Self {
+        self.format = if srgb {
+            self.format.add_srgb_suffix()
+        } else {
+            self.format.remove_srgb_suffix()
+        };
+        self
+    }
+
+    /// Whether [`Self::to_rgba8`] can decode the format, others can only be used if the device supports them.
+    pub fn has_rgba8_fallback(&self) -> bool {
+        use wgpu::TextureFormat;
+        matches!(
+            self.format,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+        ) || block_decoder(self.format).is_some()
+    }
+
+    /// Decode the levels to rgba8 on the cpu, this is only possible for the BC1 to BC5 formats.
+    pub fn to_rgba8(&self) -> Result<Self, Ktx2Error> {
+        use wgpu::TextureFormat;
+        if matches!(
+            self.format,
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+        ) {
+            return Ok(self.clone());
+        }
+        let (decode_block, format) =
+            block_decoder(self.format).ok_or(Ktx2Error::NoFallback(self.format))?;
+        let block_size = self.format.block_copy_size(None).unwrap_or(16) as usize;
+        let levels = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let width = (self.width >> level).max(1) as usize;
+                let height = (self.height >> level).max(1) as usize;
+                let blocks_wide = width.div_ceil(4);
+                let mut rgba = vec![0u8; width * height * 4];
+                for (block_index, block) in data
+                    .chunks_exact(block_size)
+                    .take(blocks_wide * height.div_ceil(4))
+                    .enumerate()
+                {
+                    let (block_x, block_y) =
+                        (block_index % blocks_wide * 4, block_index / blocks_wide * 4);
+                    for (pixel, texel) in decode_block(block).iter().enumerate() {
+                        let (x, y) = (block_x + pixel % 4, block_y + pixel / 4);
+                        if x < width && y < height {
+                            let start = (y * width + x) * 4;
+                            rgba[start..start + 4].copy_from_slice(texel);
+                        }
+                    }
+                }
+                rgba
+            })
+            .collect();
+        Ok(Self {
+            format,
+            width: self.width,
+            height: self.height,
+            levels,
+        })
+    }
+
+    /// Whether the device can sample this image as is, compressed textures must have a size that is a multiple of
+    /// the block size.
+    pub fn is_supported(&self, context: &crate::Context) -> bool {
+        let (block_width, block_height) = self.format.block_dimensions();
+        context.supports_texture_format(self.format)
+            && self.width.is_multiple_of(block_width)
+            && self.height.is_multiple_of(block_height)
+    }
+
+    /// Create the texture with all levels, falling back to rgba8 if the device doesn't support the format.
+    pub fn to_texture(&self, context: &crate::Context) -> Result<wgpu::Texture, Ktx2Error> {
+        if !self.is_supported(context) {
+            return self.to_rgba8()?.to_texture(context);
+        }
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = context.device.create_texture(&wgpu::TextureDescriptor {
+            size,
+            mip_level_count: self.levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            label: Some("ktx2"),
+            view_formats: &[],
+        });
+        let (block_width, block_height) = self.format.block_dimensions();
+        let block_size = self.format.block_copy_size(None).unwrap_or(4);
+        for (level, data) in self.levels.iter().enumerate() {
+            // Small levels of compressed textures still cover a whole block.
+            let level_size = size
+                .mip_level_size(level as u32, wgpu::TextureDimension::D2)
+                .physical_size(self.format);
+            context.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(level_size.width / block_width * block_size),
+                    rows_per_image: Some(level_size.height / block_height),
+                },
+                level_size,
+            );
+        }
+        context.queue.submit([]);
+        Ok(texture)
+    }
+}
+
+fn zstd_decompress(data: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Read as _;
+    let mut decoder = ruzstd::decoding::StreamingDecoder::new(data).ok()?;
+    let mut decompressed = vec![];
+    decoder.read_to_end(&mut decompressed).ok()?;
+    Some(decompressed)
+}
+
+/// Load a stand-alone ktx2 file as a texture.
+pub fn load_ktx2_texture(
+    context: &crate::Context,
+    path: &std::path::Path,
+    srgb: bool,
+) -> Result<wgpu::Texture, Ktx2Error> {
+    Ktx2Image::from_path(path)?
+        .with_srgb(srgb)
+        .to_texture(context)
+}
+
+/// The block decoder of a format, with the rgba8 format it decodes to.
+fn block_decoder(format: wgpu::TextureFormat) -> Option<(DecodeBlock, wgpu::TextureFormat)> {
+    use wgpu::TextureFormat;
+    Some(match format {
+        TextureFormat::Bc1RgbaUnorm => (decode_bc1_block, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc1RgbaUnormSrgb => (decode_bc1_block, TextureFormat::Rgba8UnormSrgb),
+        TextureFormat::Bc2RgbaUnorm => (decode_bc2_block, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc2RgbaUnormSrgb => (decode_bc2_block, TextureFormat::Rgba8UnormSrgb),
+        TextureFormat::Bc3RgbaUnorm => (decode_bc3_block, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc3RgbaUnormSrgb => (decode_bc3_block, TextureFormat::Rgba8UnormSrgb),
+        TextureFormat::Bc4RUnorm => (decode_bc4_block, TextureFormat::Rgba8Unorm),
+        TextureFormat::Bc5RgUnorm => (decode_bc5_block, TextureFormat::Rgba8Unorm),
+        _ => return None,
+    })
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 31) as u8;
+    let g = ((color >> 5) & 63) as u8;
+    let b = (color & 31) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+/// Decode the color part of a BC1, BC2 or BC3 block, the latter two always use the four color mode.
+fn decode_color_block(block: &[u8], four_colors: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u16, wb: u16| {
+        let total = wa + wb;
+        let mut c = [0u8; 4];
+        for i in 0..3 {
+            c[i] = ((a[i] as u16 * wa + b[i] as u16 * wb) / total) as u8;
+        }
+        c[3] = 255;
+        c
+    };
+    let palette = if four_colors || c0 > c1 {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0, 0, 0, 0]]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[((indices >> (2 * i)) & 3) as usize])
+}
+
+/// Decode a single channel block, as used for the BC3 alpha and the BC4 and BC5 channels.
+fn decode_channel_block(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = if a0 > a1 {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            i => (((8 - i as u32) * a0 + (i as u32 - 1) * a1) / 7) as u8,
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            6 => 0,
+            7 => 255,
+            i => (((6 - i as u32) * a0 + (i as u32 - 1) * a1) / 5) as u8,
+        })
+    };
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| palette[((indices >> (3 * i)) & 7) as usize])
+}
+
+fn decode_bc1_block(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_color_block(block, false)
+}
+
+fn decode_bc2_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = decode_color_block(&block[8..], true);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let alpha = (block[i / 2] >> (4 * (i % 2))) & 15;
+        texel[3] = alpha * 17;
+    }
+    texels
+}
+
+fn decode_bc3_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut texels = decode_color_block(&block[8..], true);
+    for (texel, alpha) in texels.iter_mut().zip(decode_channel_block(&block[..8])) {
+        texel[3] = alpha;
+    }
+    texels
+}
+
+fn decode_bc4_block(block: &[u8]) -> [[u8; 4]; 16] {
+    decode_channel_block(block).map(|r| [r, 0, 0, 255])
+}
+
+fn decode_bc5_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let r = decode_channel_block(&block[..8]);
+    let g = decode_channel_block(&block[8..]);
+    std::array::from_fn(|i| [r[i], g[i], 0, 255])
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// Build a single level ktx2 file.
+    pub(crate) fn ktx2_file(vk_format: u32, width: u32, height: u32, level: &[u8]) -> Vec<u8> {
+        supercompressed_ktx2_file(vk_format, width, height, level, SUPERCOMPRESSION_NONE)
+    }
+
+    /// Build a single level ktx2 file, the level must already be compressed with the given scheme.
+    fn supercompressed_ktx2_file(
+        vk_format: u32,
+        width: u32,
+        height: u32,
+        level: &[u8],
+        supercompression: u32,
+    ) -> Vec<u8> {
+        let mut bytes = IDENTIFIER.to_vec();
+        for value in [vk_format, 1, width, height, 0, 0, 1, 1, supercompression] {
+            bytes.extend(value.to_le_bytes());
+        }
+        // No data format descriptor, key value data or supercompression data.
+        bytes.extend([0u8; 32]);
+        let offset = (HEADER_SIZE + LEVEL_INDEX_ENTRY_SIZE) as u64;
+        for value in [offset, level.len() as u64, level.len() as u64] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(level);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_ktx2() {
+        let pixels: Vec<u8> = (0..16).collect();
+        let image = Ktx2Image::parse(&ktx2_file(43, 2, 2, &pixels)).unwrap();
+        assert_eq!(image.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(image.levels, vec![pixels.clone()]);
+        assert_eq!(
+            image.with_srgb(false).format,
+            wgpu::TextureFormat::Rgba8Unorm
+        );
+
+        assert!(matches!(
+            Ktx2Image::parse(&ktx2_file(43, 4, 4, &pixels)),
+            Err(Ktx2Error::Truncated)
+        ));
+        assert!(matches!(
+            Ktx2Image::parse(&ktx2_file(0, 2, 2, &pixels)),
+            Err(Ktx2Error::Basis)
+        ));
+    }
+
+    #[test]
+    fn test_supercompression() {
+        let pixels: Vec<u8> = (0..16).collect();
+        let zstd = ruzstd::encoding::compress_to_vec(
+            pixels.as_slice(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        let zlib = miniz_oxide::deflate::compress_to_vec_zlib(&pixels, 6);
+        for (level, scheme) in [(zstd, SUPERCOMPRESSION_ZSTD), (zlib, SUPERCOMPRESSION_ZLIB)] {
+            let file = supercompressed_ktx2_file(43, 2, 2, &level, scheme);
+            assert_eq!(
+                Ktx2Image::parse(&file).unwrap().levels,
+                vec![pixels.clone()]
+            );
+        }
+        let garbage = supercompressed_ktx2_file(43, 2, 2, &pixels, SUPERCOMPRESSION_ZSTD);
+        assert!(matches!(
+            Ktx2Image::parse(&garbage),
+            Err(Ktx2Error::Decompress(0))
+        ));
+        let basis_lz = supercompressed_ktx2_file(0, 2, 2, &pixels, SUPERCOMPRESSION_BASIS_LZ);
+        assert!(matches!(Ktx2Image::parse(&basis_lz), Err(Ktx2Error::Basis)));
+    }
+
+    #[test]
+    fn test_malformed_ktx2() {
+        let pixels: Vec<u8> = (0..16).collect();
+        // A 2x2 image has two levels at most.
+        let mut three_levels = ktx2_file(43, 2, 2, &pixels);
+        three_levels[40..44].copy_from_slice(&3u32.to_le_bytes());
+        assert!(matches!(
+            Ktx2Image::parse(&three_levels),
+            Err(Ktx2Error::Dimensions)
+        ));
+        // The size of the level doesn't fit in 32 bits, that must not overflow.
+        let huge = ktx2_file(43, u32::MAX, u32::MAX, &pixels);
+        assert!(matches!(
+            Ktx2Image::parse(&huge),
+            Err(Ktx2Error::Truncated | Ktx2Error::Dimensions)
+        ));
+    }
+
+    #[test]
+    fn test_bc1_fallback() {
+        // Pure red and blue endpoints, the first row selects each palette entry in order.
+        let block = [0x00, 0xf8, 0x1f, 0x00, 0b1110_0100, 0, 0, 0];
+        let image = Ktx2Image::parse(&ktx2_file(131, 4, 4, &block))
+            .unwrap()
+            .to_rgba8()
+            .unwrap();
+        assert_eq!(image.format, wgpu::TextureFormat::Rgba8Unorm);
+        let level = &image.levels[0];
+        assert_eq!(&level[0..4], &[255, 0, 0, 255]);
+        assert_eq!(&level[4..8], &[0, 0, 255, 255]);
+        assert_eq!(&level[8..12], &[170, 0, 85, 255]);
+        assert_eq!(&level[12..16], &[85, 0, 170, 255]);
+        // The other rows use index zero.
+        assert_eq!(&level[60..64], &[255, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::wgpu_util::StaticWgslStack;
+use std::collections::HashMap;
+
+pub const MIPMAP_WGSL: StaticWgslStack = StaticWgslStack {
+    name: "mipmap",
+    entry: "vs_main",
+    sources: &[include_str!("mipmap.wgsl")],
+};
+
+/// The number of levels of a full mip chain, down to a single texel.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Compute all mip levels of an rgba8 image on the cpu, the first level is the image itself. Each texel averages a
+/// block of 2x2 texels of the previous level, in linear space for srgb images like [`MipmapGenerator`] does.
+pub fn rgba8_mip_levels(width: u32, height: u32, image: Vec<u8>, srgb: bool) -> Vec<Vec<u8>> {
+    let decode: Vec<f32> = (0..=255u8)
+        .map(|v| {
+            let v = v as f32 / 255.0;
+            if srgb { super::srgb_to_linear(v) } else { v }
+        })
+        .collect();
+    let encode = |v: f32| {
+        let v = if srgb { super::linear_to_srgb(v) } else { v };
+        (v * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+    let (mut width, mut height) = (width as usize, height as usize);
+    let mut levels = vec![image];
+    for _ in 1..mip_level_count(width as u32, height as u32) {
+        let previous = levels.last().unwrap();
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let mut next = vec![0u8; next_width * next_height * 4];
+        for y in 0..next_height {
+            for x in 0..next_width {
+                // Dimensions of one texel keep sampling the same texel.
+                let xs = [2 * x, (2 * x + 1).min(width - 1)];
+                let ys = [2 * y, (2 * y + 1).min(height - 1)];
+                for channel in 0..4 {
+                    let mut sum = 0.0;
+                    for (sx, sy) in ys.iter().flat_map(|sy| xs.iter().map(move |sx| (*sx, *sy))) {
+                        let value = previous[(sy * width + sx) * 4 + channel];
+                        // Alpha is always linear.
+                        sum += if channel < 3 {
+                            decode[value as usize]
+                        } else {
+                            value as f32 / 255.0
+                        };
+                    }
+                    let average = sum / 4.0;
+                    next[(y * next_width + x) * 4 + channel] = if channel < 3 {
+                        encode(average)
+                    } else {
+                        (average * 255.0).round() as u8
+                    };
+                }
+            }
+        }
+        levels.push(next);
+        (width, height) = (next_width, next_height);
+    }
+    levels
+}
+
+/// Fills the mip levels of a texture from its first level.
+///
+/// Each level is rendered from the previous one through views in the texture's own format. Views of srgb formats
+/// decode when sampling and encode when writing, so the averaging happens in linear space for all formats.
+#[derive(Debug)]
+pub struct MipmapGenerator {
+    shader: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// The pipelines by target format, created on first use.
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mipmap"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        Self {
+            shader: MIPMAP_WGSL.create(device),
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("mipmap"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            bind_group_layout,
+            pipelines: Default::default(),
+        }
+    }
+
+    /// Whether mip levels can be generated for this texture, it must be a 2d texture of a filterable format that can
+    /// be rendered to, and have the texture binding and render attachment usages.
+    pub fn supports(context: &crate::Context, texture: &wgpu::Texture) -> bool {
+        let features = texture
+            .format()
+            .guaranteed_format_features(context.device.features());
+        texture.dimension() == wgpu::TextureDimension::D2
+            && texture.usage().contains(
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+            && features
+                .allowed_usages
+                .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+            && features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+    }
+
+    fn pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let Self {
+            shader,
+            bind_group_layout,
+            pipelines,
+            ..
+        } = self;
+        pipelines
+            .entry(format)
+            .or_insert_with(|| {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("mipmap"),
+                    bind_group_layouts: &[bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("mipmap"),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: shader,
+                        entry_point: Some("vs_main"),
+                        buffers: &[],
+                        compilation_options: Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: shader,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(format.into())],
+                        compilation_options: Default::default(),
+                    }),
+                    primitive: Default::default(),
+                    depth_stencil: None,
+                    multisample: Default::default(),
+                    multiview: None,
+                    cache: None,
+                })
+            })
+            .clone()
+    }
+
+    /// Render every level after the first from the previous one, returns false if the texture is not supported.
+    pub fn generate(&mut self, context: &crate::Context, texture: &wgpu::Texture) -> bool {
+        if texture.mip_level_count() < 2 {
+            return true;
+        }
+        if !Self::supports(context, texture) {
+            return false;
+        }
+        let device = &context.device;
+        let pipeline = self.pipeline(device, texture.format());
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mipmap"),
+        });
+        for layer in 0..texture.depth_or_array_layers() {
+            let level_view = |level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("mipmap"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            };
+            for level in 1..texture.mip_level_count() {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("mipmap"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&level_view(level - 1)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+                let target = level_view(level);
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("mipmap"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target,
+                        depth_slice: None,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+        context.queue.submit([encoder.finish()]);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mip_level_count() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(2, 1), 2);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(300, 17), 9);
+    }
+
+    #[test]
+    fn test_mipmap_shader() {
+        let module = naga::front::wgsl::parse_str(include_str!("mipmap.wgsl")).unwrap();
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .unwrap();
+    }
+
+    #[test]
+    fn test_rgba8_mip_levels() {
+        // A 3x2 image, the odd column does not contribute to the smaller level.
+        let image = [[0, 0, 0, 255], [255, 255, 255, 0], [10, 10, 10, 10]];
+        let image: Vec<u8> = image
+            .iter()
+            .chain(image.iter())
+            .flatten()
+            .copied()
+            .collect();
+        let levels = rgba8_mip_levels(3, 2, image.clone(), false);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(levels[0], image);
+        assert_eq!(levels[1], vec![128, 128, 128, 128]);
+
+        // Black and white average to middle grey in linear space, which is brighter in srgb.
+        let levels = rgba8_mip_levels(3, 2, image, true);
+        assert_eq!(levels[1], vec![188, 188, 188, 128]);
+    }
+}
@@ -0,0 +1,26 @@
+// Downsamples a mip level into the next one, the linear filter averages the 2x2 source texels of each target texel.
+
+struct MipmapVertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// A single triangle that covers the entire target.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> MipmapVertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: MipmapVertexOutput;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@fragment
+fn fs_main(in: MipmapVertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source_texture, source_sampler, in.uv, 0.0);
+}
@@ -1,6 +1,28 @@
+pub mod ktx2;
+pub mod mipmap;
+
+use glam::{Vec2, Vec3, Vec4, vec2, vec3, vec4};
 use wgpu::{Device, util::DeviceExt as _};
 use zerocopy::{Immutable, IntoBytes};
 
+/// Convert an srgb encoded color channel to linear.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear color channel to its srgb encoding.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
 // For all textures... index 0 is unused and denotes not present, this also prevents the situation where the array holds
 // nothing.
 
@@ -13,33 +35,134 @@ pub struct TextureUniform {
     pub occlusion: u32,
     pub normal: u32,
     pub emissive: u32,
+    // The uv map (TEXCOORD_n) used by each texture.
+    pub base_color_tex_coord: u32,
+    pub metallic_roughness_tex_coord: u32,
+    pub occlusion_tex_coord: u32,
+    pub normal_tex_coord: u32,
+    pub emissive_tex_coord: u32,
+    // The uv transform applied before sampling each texture.
+    pub base_color_transform: TextureTransform,
+    pub metallic_roughness_transform: TextureTransform,
+    pub occlusion_transform: TextureTransform,
+    pub normal_transform: TextureTransform,
+    pub emissive_transform: TextureTransform,
+}
+
+/// Transform applied to the uv coordinates before sampling, from the KHR_texture_transform extension;
+/// https://github.com/KhronosGroup/glTF/tree/main/extensions/2.0/Khronos/KHR_texture_transform
+#[derive(Debug, Copy, Clone, PartialEq, IntoBytes, Immutable)]
+#[repr(C)]
+pub struct TextureTransform {
+    pub offset: Vec2,
+    pub scale: Vec2,
+    /// Rotation in radians, counter-clockwise in uv space.
+    pub rotation: f32,
+    pub _pad: u32,
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        TextureTransform {
+            offset: vec2(0.0, 0.0),
+            scale: vec2(1.0, 1.0),
+            rotation: 0.0,
+            _pad: 0,
+        }
+    }
 }
 
 impl TextureUniform {
     pub fn create_from_iter<'a, I: Iterator<Item = (usize, &'a SampledTexture)>>(it: I) -> Self {
-        let mut base_color = 0;
-        let mut metallic_roughness = 0;
-        let mut occlusion = 0;
-        let mut normal = 0;
-        let mut emissive = 0;
+        let mut res = TextureUniform::default();
 
         for (index, texture) in it {
+            let index = index as u32;
+            let tex_coord = texture.tex_coord;
             match texture.texture_type {
-                TextureType::BaseColor => base_color = index as u32,
-                TextureType::MetallicRoughness => metallic_roughness = index as u32,
-                TextureType::Occlusion => occlusion = index as u32,
-                TextureType::Normal => normal = index as u32,
-                TextureType::Emissive => emissive = index as u32,
+                TextureType::BaseColor => {
+                    res.base_color = index;
+                    res.base_color_tex_coord = tex_coord;
+                    res.base_color_transform = texture.transform;
+                }
+                TextureType::MetallicRoughness => {
+                    res.metallic_roughness = index;
+                    res.metallic_roughness_tex_coord = tex_coord;
+                    res.metallic_roughness_transform = texture.transform;
+                }
+                TextureType::Occlusion => {
+                    res.occlusion = index;
+                    res.occlusion_tex_coord = tex_coord;
+                    res.occlusion_transform = texture.transform;
+                }
+                TextureType::Normal => {
+                    res.normal = index;
+                    res.normal_tex_coord = tex_coord;
+                    res.normal_transform = texture.transform;
+                }
+                TextureType::Emissive => {
+                    res.emissive = index;
+                    res.emissive_tex_coord = tex_coord;
+                    res.emissive_transform = texture.transform;
+                }
                 _ => {}
             }
         }
 
-        TextureUniform {
-            base_color,
-            metallic_roughness,
-            occlusion,
-            normal,
-            emissive,
+        res
+    }
+}
+
+/// The global factors of a material, these get multiplied with the sampled textures.
+///
+/// Defaults are from https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#reference-material
+#[derive(Debug, Copy, Clone, PartialEq, IntoBytes, Immutable)]
+#[repr(C)]
+pub struct MaterialUniform {
+    pub base_color_factor: Vec4,
+    pub emissive_factor: Vec3,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Scale applied to the x and y of the sampled normal map.
+    pub normal_scale: f32,
+    /// How much of the occlusion texture is applied.
+    pub occlusion_strength: f32,
+    /// Alpha below this value is discarded if the alpha mode is [`AlphaMode::Mask`].
+    pub alpha_cutoff: f32,
+    pub alpha_mode: AlphaMode,
+    /// If non-zero, back faces are not culled and their normals are flipped.
+    pub double_sided: u32,
+    pub _pad: [u32; 2],
+}
+
+/// How the alpha of the base color is interpreted, https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html#alpha-coverage
+#[derive(
+    Debug, Copy, Clone, PartialEq, Hash, Ord, PartialOrd, Eq, IntoBytes, Immutable, Default,
+)]
+#[repr(u32)]
+pub enum AlphaMode {
+    /// Alpha is ignored, everything is fully opaque.
+    #[default]
+    Opaque = 0,
+    /// Fragments with an alpha below the cutoff are discarded, the remainder is opaque.
+    Mask = 1,
+    /// Alpha blended, these must be drawn with the blending pipeline.
+    Blend = 2,
+}
+
+impl Default for MaterialUniform {
+    fn default() -> Self {
+        MaterialUniform {
+            base_color_factor: vec4(1.0, 1.0, 1.0, 1.0),
+            emissive_factor: vec3(0.0, 0.0, 0.0),
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_cutoff: 0.5,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: 0,
+            _pad: Default::default(),
         }
     }
 }
@@ -63,6 +186,10 @@ pub struct SampledTexture {
     pub sampler: wgpu::Sampler,
     pub texture: wgpu::Texture,
     pub texture_type: TextureType,
+    /// The uv map this texture is sampled with, TEXCOORD_n in gltf.
+    pub tex_coord: u32,
+    /// Transform applied to the uv coordinates before sampling.
+    pub transform: TextureTransform,
 }
 
 // What a misnomer :/
@@ -71,6 +198,7 @@ pub struct CpuTextureInfo {
     pub device: wgpu::Device,
     pub name: String,
     pub textures: Vec<SampledTexture>,
+    pub material: MaterialUniform,
 }
 
 const fn create_non_zero() -> Option<std::num::NonZero<u32>> {
@@ -104,15 +232,23 @@ impl CpuTextureInfo {
                 view_formats: &[],
             }),
             texture_type: TextureType::None,
+            tex_coord: 0,
+            transform: Default::default(),
         });
 
         Self {
             device: device.clone(),
             textures: dummy.iter().chain(textures.iter()).cloned().collect(),
             name: name.to_string(),
+            material: Default::default(),
         }
     }
 
+    pub fn with_material(mut self, material: MaterialUniform) -> Self {
+        self.material = material;
+        self
+    }
+
     pub fn to_gpu(&self) -> GpuTextureInfo {
         let sampler_pointers: Vec<&wgpu::Sampler> =
             self.textures.iter().map(|z| &z.sampler).collect();
@@ -137,6 +273,13 @@ impl CpuTextureInfo {
                     contents: texture_uniform.as_bytes(),
                     usage: wgpu::BufferUsages::STORAGE,
                 });
+        let material_uniform_buffer =
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{}_material_uniform", self.name)),
+                    contents: self.material.as_bytes(),
+                    usage: wgpu::BufferUsages::STORAGE,
+                });
         let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &layout,
             entries: &[
@@ -152,6 +295,10 @@ impl CpuTextureInfo {
                     binding: GpuTextureInfo::TEXTURE_BINDING_UNIFORM_META,
                     resource: texture_uniform_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: GpuTextureInfo::TEXTURE_BINDING_UNIFORM_MATERIAL,
+                    resource: material_uniform_buffer.as_entire_binding(),
+                },
             ],
             label: Some(&self.name),
         });
@@ -169,6 +316,7 @@ impl GpuTextureInfo {
     pub const TEXTURE_BINDING_TEXTURE: u32 = 0;
     pub const TEXTURE_BINDING_SAMPLER: u32 = 1;
     pub const TEXTURE_BINDING_UNIFORM_META: u32 = 2;
+    pub const TEXTURE_BINDING_UNIFORM_MATERIAL: u32 = 3;
 
     pub const fn bind_group_layout() -> wgpu::BindGroupLayoutDescriptor<'static> {
         // Do this clunky bi-directional approach such that the compiler doesn't complain :/
@@ -200,8 +348,18 @@ impl GpuTextureInfo {
             },
             count: None,
         };
+        const FOURTH: wgpu::BindGroupLayoutEntry = wgpu::BindGroupLayoutEntry {
+            binding: GpuTextureInfo::TEXTURE_BINDING_UNIFORM_MATERIAL,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
         wgpu::BindGroupLayoutDescriptor {
-            entries: &[FIRST, SECOND, THIRD],
+            entries: &[FIRST, SECOND, THIRD, FOURTH],
             label: Some("mesh_object_textured_layout"),
         }
     }
@@ -223,7 +381,39 @@ mod test {
             metallic_roughness,
             occlusion,
             normal,
-            emissive
+            emissive,
+            base_color_tex_coord,
+            metallic_roughness_tex_coord,
+            occlusion_tex_coord,
+            normal_tex_coord,
+            emissive_tex_coord,
+            base_color_transform,
+            metallic_roughness_transform,
+            occlusion_transform,
+            normal_transform,
+            emissive_transform
+        );
+    }
+    #[test]
+    fn test_texture_transform_struct_align() {
+        let module = naga::front::wgsl::parse_str(include_str!("../shader_common.wgsl")).unwrap();
+        crate::verify_wgsl_struct_sized!(TextureTransform, module, offset, scale, rotation);
+    }
+    #[test]
+    fn test_material_struct_align() {
+        let module = naga::front::wgsl::parse_str(include_str!("../shader_common.wgsl")).unwrap();
+        crate::verify_wgsl_struct_sized!(
+            MaterialUniform,
+            module,
+            base_color_factor,
+            emissive_factor,
+            metallic_factor,
+            roughness_factor,
+            normal_scale,
+            occlusion_strength,
+            alpha_cutoff,
+            alpha_mode,
+            double_sided
         );
     }
 }
@@ -1,6 +1,23 @@
-use glam::{Vec2, Vec3, Vec3A, Vec4, vec3, vec4};
+use glam::{UVec4, Vec2, Vec3, Vec3A, Vec4, vec3, vec4};
 use wgpu::util::DeviceExt as _;
-use zerocopy::IntoBytes as _;
+use zerocopy::{Immutable, IntoBytes};
+
+/// The displacements of a single morph target, these are relative to the base mesh.
+#[derive(Clone, Debug, Default)]
+pub struct MorphTarget {
+    pub position: Vec<Vec3>,
+    pub normal: Option<Vec<Vec3>>,
+    pub tangent: Option<Vec<Vec3>>,
+}
+
+/// The displacements of a morph target for a single vertex, as stored on the gpu.
+#[derive(Debug, Copy, Clone, PartialEq, IntoBytes, Immutable, Default)]
+#[repr(C)]
+pub struct MorphTargetDelta {
+    pub position: Vec3A,
+    pub normal: Vec3A,
+    pub tangent: Vec3A,
+}
 
 /// A representation of a mesh on the CPU side.
 #[derive(Clone)]
@@ -11,6 +28,9 @@ pub struct CpuMesh {
     /// The vertex indices
     pub index: Vec<u32>,
 
+    /// How the indices form primitives, only point, line and triangle lists are supported.
+    pub topology: wgpu::PrimitiveTopology,
+
     //--- Optionals below.
     /// Name to use for the vertex buffer
     pub name: Option<String>,
@@ -23,9 +43,21 @@ pub struct CpuMesh {
 
     /// The UV mapping
     pub uv: Option<Vec<Vec2>>,
-    // apparently some PBR materials have two UV maps :/
+
+    /// The second UV mapping, some PBR materials use this for the occlusion map.
+    pub uv1: Option<Vec<Vec2>>,
+
     /// Tangents, in mikktspace.
     pub tangents: Option<Vec<Vec4>>,
+
+    /// The joint indices that influence each vertex, these index into the joint matrices of the mesh object.
+    pub joints: Option<Vec<UVec4>>,
+
+    /// The weight for each of the joints.
+    pub weights: Option<Vec<Vec4>>,
+
+    /// Morph targets, blended with the weights of the mesh object.
+    pub morph_targets: Vec<MorphTarget>,
 }
 
 impl CpuMesh {
@@ -34,11 +66,16 @@ impl CpuMesh {
         Self {
             position,
             index,
+            topology: wgpu::PrimitiveTopology::TriangleList,
             color: None,
             normal: None,
             uv: None,
+            uv1: None,
             name: None,
             tangents: None,
+            joints: None,
+            weights: None,
+            morph_targets: vec![],
         }
     }
 
@@ -86,12 +123,17 @@ impl CpuMesh {
 
         let mut axis_mesh = Self {
             index: (0..position.len() as u32).collect(),
+            topology: wgpu::PrimitiveTopology::TriangleList,
             position,
             color: Some(colors),
             normal: None,
             uv: None,
+            uv1: None,
             name: Some("coordinate_frame".to_owned()),
             tangents: None,
+            joints: None,
+            weights: None,
+            morph_targets: vec![],
         };
         axis_mesh.calculate_normals();
         axis_mesh
@@ -103,17 +145,73 @@ impl CpuMesh {
         self
     }
 
+    /// Set the topology, the indices must form primitives of this topology.
+    pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// Calculate tangents in mikktspace, this requires uvs and normals and is only possible for triangles.
+    ///
+    /// Vertices on tangent seams are split, so this may add vertices.
     pub fn calculate_tangents(&mut self) -> bool {
         // This needs to access the uv and normals, but it doesn't provide the API to propage it missing, so shield
         // against the invalid unwrap here.
-        if self.uv.is_none() || self.normal.is_none() {
+        if self.uv.is_none()
+            || self.normal.is_none()
+            || self.topology != wgpu::PrimitiveTopology::TriangleList
+        {
             return false;
         }
-        self.tangents = Some(vec![Default::default(); self.position.len()]);
-        bevy_mikktspace::generate_tangents(self)
+        super::mikktspace::generate_tangents(self)
     }
 
+    /// Append a copy of a vertex with all its attributes, returns the index of the copy.
+    pub fn duplicate_vertex(&mut self, vertex: usize) -> u32 {
+        fn duplicate<T: Copy>(values: &mut Vec<T>, vertex: usize) {
+            if let Some(value) = values.get(vertex).copied() {
+                values.push(value);
+            }
+        }
+        duplicate(&mut self.position, vertex);
+        if let Some(v) = self.color.as_mut() {
+            duplicate(v, vertex);
+        }
+        if let Some(v) = self.normal.as_mut() {
+            duplicate(v, vertex);
+        }
+        if let Some(v) = self.uv.as_mut() {
+            duplicate(v, vertex);
+        }
+        if let Some(v) = self.uv1.as_mut() {
+            duplicate(v, vertex);
+        }
+        if let Some(v) = self.tangents.as_mut() {
+            duplicate(v, vertex);
+        }
+        if let Some(v) = self.joints.as_mut() {
+            duplicate(v, vertex);
+        }
+        if let Some(v) = self.weights.as_mut() {
+            duplicate(v, vertex);
+        }
+        for target in self.morph_targets.iter_mut() {
+            duplicate(&mut target.position, vertex);
+            if let Some(v) = target.normal.as_mut() {
+                duplicate(v, vertex);
+            }
+            if let Some(v) = target.tangent.as_mut() {
+                duplicate(v, vertex);
+            }
+        }
+        (self.position.len() - 1) as u32
+    }
+
+    /// Calculate flat normals, this does nothing for points and lines as they have no surface.
     pub fn calculate_normals(&mut self) {
+        if self.topology != wgpu::PrimitiveTopology::TriangleList {
+            return;
+        }
         let mut normals: Vec<Vec3A> = vec![Default::default(); self.position.len()];
         for poly_indices in self.index.chunks(3) {
             let a = self.position[poly_indices[0] as usize];
@@ -128,6 +226,26 @@ impl CpuMesh {
         self.normal = Some(normals);
     }
 
+    /// Calculate normals shared by the triangles around a vertex, weighted by the triangle areas. This does nothing
+    /// for points and lines as they have no surface.
+    pub fn calculate_smooth_normals(&mut self) {
+        if self.topology != wgpu::PrimitiveTopology::TriangleList {
+            return;
+        }
+        let mut normals: Vec<Vec3A> = vec![Default::default(); self.position.len()];
+        for poly_indices in self.index.chunks_exact(3) {
+            let a = self.position[poly_indices[0] as usize];
+            let b = self.position[poly_indices[1] as usize];
+            let c = self.position[poly_indices[2] as usize];
+            // The length of the cross product is twice the area.
+            let this_normal: Vec3A = (b - a).cross(c - a).into();
+            for v in poly_indices {
+                normals[*v as usize] += this_normal;
+            }
+        }
+        self.normal = Some(normals.into_iter().map(|n| n.normalize_or_zero()).collect());
+    }
+
     pub fn get_name_prefix(&self) -> String {
         let name_prefix = if let Some(name) = self.name.as_ref() {
             format!("{}", name)
@@ -141,8 +259,61 @@ impl CpuMesh {
         name_prefix
     }
 
+    /// Expand every point into a quad of four vertices that hold the attributes of the point, wgpu only draws points
+    /// of a single pixel. The vertex shader offsets the corners in screen space, based on the vertex index.
+    pub fn to_point_sprites(&self) -> CpuMesh {
+        let vertices: Vec<u32> = self.index.iter().flat_map(|v| [*v; 4]).collect();
+        let mut sprites = self.gather_vertices(&vertices);
+        sprites.index = (0..self.index.len() as u32)
+            .flat_map(|point| {
+                let c = point * 4;
+                [c, c + 1, c + 2, c + 2, c + 1, c + 3]
+            })
+            .collect();
+        sprites
+    }
+
+    /// A mesh with copies of these vertices in this order, without indices.
+    fn gather_vertices(&self, vertices: &[u32]) -> CpuMesh {
+        fn gather<T: Copy>(values: &[T], vertices: &[u32]) -> Vec<T> {
+            vertices.iter().map(|v| values[*v as usize]).collect()
+        }
+        CpuMesh {
+            position: gather(&self.position, vertices),
+            index: vec![],
+            topology: self.topology,
+            name: self.name.clone(),
+            color: self.color.as_deref().map(|v| gather(v, vertices)),
+            normal: self.normal.as_deref().map(|v| gather(v, vertices)),
+            uv: self.uv.as_deref().map(|v| gather(v, vertices)),
+            uv1: self.uv1.as_deref().map(|v| gather(v, vertices)),
+            tangents: self.tangents.as_deref().map(|v| gather(v, vertices)),
+            joints: self.joints.as_deref().map(|v| gather(v, vertices)),
+            weights: self.weights.as_deref().map(|v| gather(v, vertices)),
+            morph_targets: self
+                .morph_targets
+                .iter()
+                .map(|target| MorphTarget {
+                    position: gather(&target.position, vertices),
+                    normal: target.normal.as_deref().map(|v| gather(v, vertices)),
+                    tangent: target.tangent.as_deref().map(|v| gather(v, vertices)),
+                })
+                .collect(),
+        }
+    }
+
     /// Create the gpu mesh, which is independent of the CPU Mesh.
+    ///
+    /// Points are uploaded as quads, see [`CpuMesh::to_point_sprites`].
     pub fn to_gpu(&self, context: &crate::Context) -> GpuMesh {
+        if self.topology == wgpu::PrimitiveTopology::PointList {
+            self.to_point_sprites().upload(context)
+        } else {
+            self.upload(context)
+        }
+    }
+
+    fn upload(&self, context: &crate::Context) -> GpuMesh {
         // https://www.w3.org/TR/webgpu/#minimum-buffer-binding-size
 
         let name_prefix = self.get_name_prefix();
@@ -152,19 +323,22 @@ impl CpuMesh {
             .device
             .create_bind_group_layout(&crate::vertex::mesh::GpuMesh::MESH_LAYOUT);
 
+        // All buffers can be copied from, such that the mesh can be read back by the exporter.
         let vertex_buffer = context
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{}_vertex", name_prefix)),
                 contents: self.position.as_bytes(),
-                usage: wgpu::BufferUsages::VERTEX,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_SRC,
             });
         let index_buffer = context
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{}_index", name_prefix)),
                 contents: self.index.as_bytes(),
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
+                usage: wgpu::BufferUsages::INDEX
+                    | wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC,
             });
         let index_length = self.index.len() as u32;
 
@@ -181,7 +355,7 @@ impl CpuMesh {
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{}_normal", name_prefix)),
                 contents: normal_data,
-                usage: wgpu::BufferUsages::STORAGE,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
             });
 
         let color_data = self
@@ -194,7 +368,7 @@ impl CpuMesh {
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{}_color", name_prefix)),
                 contents: color_data,
-                usage: wgpu::BufferUsages::STORAGE,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
             });
 
         let uv_data = self
@@ -207,7 +381,20 @@ impl CpuMesh {
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{}_uv", name_prefix)),
                 contents: uv_data,
-                usage: wgpu::BufferUsages::STORAGE,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            });
+
+        let uv1_data = self
+            .uv1
+            .as_ref()
+            .map(|z| z.as_bytes())
+            .unwrap_or([Vec2::ZERO].as_bytes());
+        let uv1_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{}_uv1", name_prefix)),
+                contents: uv1_data,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
             });
 
         let tangent_data = self
@@ -220,9 +407,63 @@ impl CpuMesh {
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some(&format!("{}_tangent", name_prefix)),
                 contents: tangent_data,
-                usage: wgpu::BufferUsages::STORAGE,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
             });
 
+        let joints_data = self
+            .joints
+            .as_ref()
+            .map(|z| z.as_bytes())
+            .unwrap_or([UVec4::ZERO].as_bytes());
+        let joints_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{}_joints", name_prefix)),
+                contents: joints_data,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            });
+
+        let weights_data = self
+            .weights
+            .as_ref()
+            .map(|z| z.as_bytes())
+            .unwrap_or([Vec4::ZERO].as_bytes());
+        let weights_buffer = context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{}_weights", name_prefix)),
+                contents: weights_data,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            });
+
+        // All targets are stored back to back, each holding a delta for every vertex.
+        let vertex_count = self.position.len();
+        let mut morph_target_data: Vec<MorphTargetDelta> =
+            Vec::with_capacity(self.morph_targets.len() * vertex_count);
+        for target in self.morph_targets.iter() {
+            morph_target_data.extend((0..vertex_count).map(|i| {
+                let get = |v: Option<&Vec<Vec3>>| {
+                    v.and_then(|v| v.get(i)).copied().unwrap_or_default().into()
+                };
+                MorphTargetDelta {
+                    position: get(Some(&target.position)),
+                    normal: get(target.normal.as_ref()),
+                    tangent: get(target.tangent.as_ref()),
+                }
+            }));
+        }
+        if morph_target_data.is_empty() {
+            morph_target_data.push(Default::default());
+        }
+        let morph_target_buffer =
+            context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{}_morph_targets", name_prefix)),
+                    contents: morph_target_data.as_bytes(),
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                });
+
         let bind_group = context
             .device
             .create_bind_group(&wgpu::BindGroupDescriptor {
@@ -249,14 +490,23 @@ impl CpuMesh {
             vertex_buffer,
             index_buffer,
             index_length,
+            topology: self.topology,
             normal_buffer,
             normal_present: self.normal.is_some(),
             color_buffer,
             color_present: self.color.is_some(),
             uv_buffer,
             uv_present: self.uv.is_some(),
+            uv1_buffer,
+            uv1_present: self.uv1.is_some(),
             tangent_buffer,
             tangent_present: self.tangents.is_some(),
+            joints_buffer,
+            weights_buffer,
+            skin_present: self.joints.is_some() && self.weights.is_some(),
+            morph_target_buffer,
+            morph_target_count: self.morph_targets.len() as u32,
+            vertex_count: vertex_count as u32,
             bind_group,
         }
     }
@@ -275,6 +525,8 @@ pub struct GpuMesh {
     pub index_buffer: wgpu::Buffer,
     /// Total number of indices.
     pub index_length: u32,
+    /// How the indices form primitives.
+    pub topology: wgpu::PrimitiveTopology,
 
     //--- Optionals below, if they are unused, they are zero length, but still bound.
     pub normal_buffer: wgpu::Buffer,
@@ -285,8 +537,21 @@ pub struct GpuMesh {
     pub uv_buffer: wgpu::Buffer,
     pub uv_present: bool,
 
+    pub uv1_buffer: wgpu::Buffer,
+    pub uv1_present: bool,
+
     pub tangent_buffer: wgpu::Buffer,
     pub tangent_present: bool,
+
+    /// Joint indices and weights, only used if both are present.
+    pub joints_buffer: wgpu::Buffer,
+    pub weights_buffer: wgpu::Buffer,
+    pub skin_present: bool,
+
+    /// The morph target deltas, `morph_target_count` blocks of `vertex_count` entries.
+    pub morph_target_buffer: wgpu::Buffer,
+    pub morph_target_count: u32,
+    pub vertex_count: u32,
 }
 
 impl GpuMesh {
@@ -345,6 +610,92 @@ impl GpuMesh {
             attributes: &Self::VERTEX_ATTRIBUTES,
         }
     }
+
+    /// Read the mesh back from the gpu, this waits for the copies to finish.
+    ///
+    /// Point sprites are collapsed into their points again, but vertices shared by points are not merged.
+    pub fn to_cpu(&self, context: &crate::Context) -> CpuMesh {
+        let floats = |buffer: &wgpu::Buffer| -> Vec<f32> {
+            crate::wgpu_util::read_buffer(context, buffer)
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect()
+        };
+        let vertex_count = self.vertex_count as usize;
+        let read = |buffer: &wgpu::Buffer, present: bool, stride: usize| {
+            present.then(|| {
+                let mut values = floats(buffer);
+                values.truncate(vertex_count * stride);
+                values
+            })
+        };
+        let position: Vec<Vec3> = read(&self.vertex_buffer, true, 3)
+            .unwrap()
+            .chunks_exact(3)
+            .map(Vec3::from_slice)
+            .collect();
+        let index: Vec<u32> = crate::wgpu_util::read_buffer(context, &self.index_buffer)
+            .chunks_exact(4)
+            .take(self.index_length as usize)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        let mut mesh = CpuMesh::new(position, index).with_topology(self.topology);
+        mesh.name = Some(self.name.clone());
+        mesh.normal = read(&self.normal_buffer, self.normal_present, 4).map(|v| {
+            v.chunks_exact(4)
+                .map(|z| Vec3A::from_slice(&z[..3]))
+                .collect()
+        });
+        mesh.color = read(&self.color_buffer, self.color_present, 4)
+            .map(|v| v.chunks_exact(4).map(Vec4::from_slice).collect());
+        mesh.uv = read(&self.uv_buffer, self.uv_present, 2)
+            .map(|v| v.chunks_exact(2).map(Vec2::from_slice).collect());
+        mesh.uv1 = read(&self.uv1_buffer, self.uv1_present, 2)
+            .map(|v| v.chunks_exact(2).map(Vec2::from_slice).collect());
+        mesh.tangents = read(&self.tangent_buffer, self.tangent_present, 4)
+            .map(|v| v.chunks_exact(4).map(Vec4::from_slice).collect());
+        mesh.weights = read(&self.weights_buffer, self.skin_present, 4)
+            .map(|v| v.chunks_exact(4).map(Vec4::from_slice).collect());
+        // The joints are integers, read as floats with the same bits.
+        mesh.joints = read(&self.joints_buffer, self.skin_present, 4).map(|v| {
+            v.chunks_exact(4)
+                .map(|z| {
+                    UVec4::new(
+                        z[0].to_bits(),
+                        z[1].to_bits(),
+                        z[2].to_bits(),
+                        z[3].to_bits(),
+                    )
+                })
+                .collect()
+        });
+
+        // The deltas hold the position, normal and tangent, each padded to four floats.
+        let delta_size = 12;
+        let deltas = floats(&self.morph_target_buffer);
+        for target in 0..self.morph_target_count as usize {
+            let delta = |vertex: usize, offset: usize| {
+                let start = (target * vertex_count + vertex) * delta_size + offset;
+                Vec3::from_slice(&deltas[start..start + 3])
+            };
+            mesh.morph_targets.push(MorphTarget {
+                position: (0..vertex_count).map(|v| delta(v, 0)).collect(),
+                normal: Some((0..vertex_count).map(|v| delta(v, 4)).collect()),
+                tangent: Some((0..vertex_count).map(|v| delta(v, 8)).collect()),
+            });
+        }
+
+        if self.topology == wgpu::PrimitiveTopology::PointList {
+            // Every point was expanded into four consecutive vertices.
+            let points: Vec<u32> = (0..(vertex_count / 4) as u32).map(|p| p * 4).collect();
+            mesh = CpuMesh {
+                index: (0..points.len() as u32).collect(),
+                ..mesh.gather_vertices(&points)
+            };
+        }
+        mesh
+    }
 }
 
 crate::static_assert_size!(Vec3, 12);
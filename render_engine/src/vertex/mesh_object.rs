@@ -5,7 +5,7 @@ use log::warn;
 use wgpu::util::DeviceExt as _;
 use zerocopy::{Immutable, IntoBytes};
 
-use crate::wgpu_util::StaticWgslStack;
+use crate::wgpu_util::{ShaderError, StaticWgslStack, WgslFileStack};
 pub const MESH_OBJECT_WGSL: StaticWgslStack = StaticWgslStack {
     name: "mesh_object",
     entry: "main",
@@ -30,9 +30,24 @@ pub struct MeshObject {
     /// The buffer for our uniform.
     pub mesh_object_uniform: wgpu::Buffer,
 
+    /// Cpu representation of the joint matrices used for skinning, in the frame of the instance transform.
+    pub joint_matrices: Vec<Mat4>,
+
+    /// Gpu representation of the joint matrices.
+    pub joint_matrices_buffer: wgpu::Buffer,
+
+    /// Cpu representation of the morph target weights, one for each target of the mesh.
+    pub morph_weights: Vec<f32>,
+
+    /// Gpu representation of the morph target weights.
+    pub morph_weights_buffer: wgpu::Buffer,
+
     /// The GPU mesh to operate on.
     pub gpu_mesh: GpuMesh,
 
+    /// Diameter in pixels of the points, only used for point meshes.
+    pub point_size: f32,
+
     /// The bindgroup that contains all the buffers.
     pub bind_group: wgpu::BindGroup,
 }
@@ -44,6 +59,12 @@ pub struct MeshObjectMetaUniform {
     pub normal_present: u32,
     pub uv_present: u32,
     pub tangent_present: u32,
+    pub uv1_present: u32,
+    pub skin_present: u32,
+    pub vertex_count: u32,
+    pub morph_target_count: u32,
+    /// Diameter in pixels for point meshes, zero for other topologies.
+    pub point_size: f32,
 }
 
 impl MeshObject {
@@ -56,20 +77,20 @@ impl MeshObject {
             mapped_at_creation: false,
         });
         let instances = vec![];
-        let mesh_object_uniform = MeshObjectMetaUniform {
-            color_present: gpu_mesh.color_present as u32,
-            normal_present: gpu_mesh.normal_present as u32,
-            uv_present: gpu_mesh.uv_present as u32,
-            tangent_present: gpu_mesh.tangent_present as u32,
-        };
-        let mesh_object_uniform =
+        let joint_matrices = vec![Mat4::IDENTITY];
+        let joint_matrices_buffer =
             context
                 .device
                 .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some(&format!("{}_mesh_object_uniform", gpu_mesh.name)),
-                    contents: mesh_object_uniform.as_bytes(),
+                    label: Some(&format!("{}_joint_matrices", gpu_mesh.name)),
+                    contents: joint_matrices.as_bytes(),
                     usage: wgpu::BufferUsages::STORAGE,
                 });
+        let morph_weights = vec![0.0; gpu_mesh.morph_target_count as usize];
+        let morph_weights_buffer =
+            Self::create_morph_weights_buffer(&context, &gpu_mesh, &morph_weights);
+        let point_size = Self::DEFAULT_POINT_SIZE;
+        let mesh_object_uniform = Self::create_mesh_object_uniform(&context, &gpu_mesh, point_size);
 
         let layout = context.device.create_bind_group_layout(&Self::MESH_LAYOUT);
         let bind_group = context
@@ -101,6 +122,30 @@ impl MeshObject {
                         binding: Self::MESH_BINDING_TANGENT,
                         resource: gpu_mesh.tangent_buffer.as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_UV1,
+                        resource: gpu_mesh.uv1_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_JOINTS,
+                        resource: gpu_mesh.joints_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_WEIGHTS,
+                        resource: gpu_mesh.weights_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_OBJECT_JOINT_MATRICES_BINDING,
+                        resource: joint_matrices_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_MORPH_TARGETS,
+                        resource: gpu_mesh.morph_target_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_OBJECT_MORPH_WEIGHTS_BINDING,
+                        resource: morph_weights_buffer.as_entire_binding(),
+                    },
                 ],
                 label: Some(&format!("{}_bind_group", gpu_mesh.name)),
             });
@@ -110,11 +155,54 @@ impl MeshObject {
             instances,
             instances_buffer,
             mesh_object_uniform,
+            joint_matrices,
+            joint_matrices_buffer,
+            morph_weights,
+            morph_weights_buffer,
             gpu_mesh,
+            point_size,
             bind_group,
         }
     }
 
+    fn create_mesh_object_uniform(
+        context: &Context,
+        gpu_mesh: &GpuMesh,
+        point_size: f32,
+    ) -> wgpu::Buffer {
+        let mesh_object_uniform = MeshObjectMetaUniform {
+            color_present: gpu_mesh.color_present as u32,
+            normal_present: gpu_mesh.normal_present as u32,
+            uv_present: gpu_mesh.uv_present as u32,
+            tangent_present: gpu_mesh.tangent_present as u32,
+            uv1_present: gpu_mesh.uv1_present as u32,
+            skin_present: gpu_mesh.skin_present as u32,
+            vertex_count: gpu_mesh.vertex_count,
+            morph_target_count: gpu_mesh.morph_target_count,
+            point_size: if gpu_mesh.topology == wgpu::PrimitiveTopology::PointList {
+                point_size
+            } else {
+                0.0
+            },
+        };
+        context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{}_mesh_object_uniform", gpu_mesh.name)),
+                contents: mesh_object_uniform.as_bytes(),
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+    }
+
+    /// Set the diameter of points in pixels, does NOT update the gpu data.
+    pub fn set_point_size(&mut self, point_size: f32) {
+        self.point_size = point_size;
+    }
+    pub fn with_point_size(mut self, point_size: f32) -> Self {
+        self.point_size = point_size;
+        self
+    }
+
     /// Set the object to have a single transform, does NOT update the gpu data.
     pub fn set_single_transform(&mut self, transform: &Mat4) {
         self.instances.resize(1, Default::default());
@@ -131,6 +219,54 @@ impl MeshObject {
         self.instances = transform.iter().copied().collect();
     }
 
+    /// Set the transform of one instance, growing the instances with identities if needed, does NOT update the gpu data.
+    pub fn set_instance_transform(&mut self, instance: usize, transform: &Mat4) {
+        if self.instances.len() <= instance {
+            self.instances.resize(instance + 1, Mat4::IDENTITY);
+        }
+        self.instances[instance] = *transform;
+    }
+
+    /// Set the joint matrices for a skinned mesh, does NOT update the gpu data.
+    pub fn set_joint_matrices(&mut self, joint_matrices: &[Mat4]) {
+        self.joint_matrices = joint_matrices.to_vec();
+    }
+
+    /// Set the morph target weights, missing weights are zero, does NOT update the gpu data.
+    pub fn set_morph_weights(&mut self, weights: &[f32]) {
+        self.morph_weights = weights.to_vec();
+        self.morph_weights
+            .resize(self.gpu_mesh.morph_target_count as usize, 0.0);
+    }
+
+    fn create_morph_weights_buffer(
+        context: &Context,
+        gpu_mesh: &GpuMesh,
+        morph_weights: &[f32],
+    ) -> wgpu::Buffer {
+        // The storage buffer can't be empty, so meshes without targets get a single zero weight.
+        let contents = if morph_weights.is_empty() {
+            [0.0f32].as_bytes()
+        } else {
+            morph_weights.as_bytes()
+        };
+        context
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{}_morph_weights", gpu_mesh.name)),
+                contents,
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+    }
+
+    /// The distance from the point to the closest instance origin, used to sort objects for blending.
+    pub fn distance_to(&self, point: Vec3) -> f32 {
+        self.instances
+            .iter()
+            .map(|instance| instance.w_axis.truncate().distance(point))
+            .fold(f32::INFINITY, f32::min)
+    }
+
     /// This replaces the current gpu data with a fresh buffer that holds the updated instance values.
     pub fn replace_gpu_data(&mut self) {
         let instances_buffer =
@@ -143,6 +279,24 @@ impl MeshObject {
                 });
         self.instances_buffer = instances_buffer;
 
+        // The storage buffer can't be empty, keep a single identity matrix for unskinned meshes.
+        if self.joint_matrices.is_empty() {
+            self.joint_matrices.push(Mat4::IDENTITY);
+        }
+        let joint_matrices_buffer =
+            self.context
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{}_joint_matrices", self.gpu_mesh.name)),
+                    contents: self.joint_matrices.as_bytes(),
+                    usage: wgpu::BufferUsages::STORAGE,
+                });
+        self.joint_matrices_buffer = joint_matrices_buffer;
+        self.morph_weights_buffer =
+            Self::create_morph_weights_buffer(&self.context, &self.gpu_mesh, &self.morph_weights);
+        self.mesh_object_uniform =
+            Self::create_mesh_object_uniform(&self.context, &self.gpu_mesh, self.point_size);
+
         let layout = self
             .context
             .device
@@ -177,6 +331,30 @@ impl MeshObject {
                         binding: Self::MESH_BINDING_TANGENT,
                         resource: self.gpu_mesh.tangent_buffer.as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_UV1,
+                        resource: self.gpu_mesh.uv1_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_JOINTS,
+                        resource: self.gpu_mesh.joints_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_WEIGHTS,
+                        resource: self.gpu_mesh.weights_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_OBJECT_JOINT_MATRICES_BINDING,
+                        resource: self.joint_matrices_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_BINDING_MORPH_TARGETS,
+                        resource: self.gpu_mesh.morph_target_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: Self::MESH_OBJECT_MORPH_WEIGHTS_BINDING,
+                        resource: self.morph_weights_buffer.as_entire_binding(),
+                    },
                 ],
                 label: Some(&format!("{}_bind_group", self.gpu_mesh.name)),
             });
@@ -207,6 +385,32 @@ impl MeshObject {
         super::VertexCreaterShader::new(MESH_OBJECT_WGSL.create(device), MESH_OBJECT_WGSL.entry)
     }
 
+    /// The sources of the embedded shader in the source tree, to edit the shader while the application runs.
+    pub fn wgsl_files() -> WgslFileStack {
+        WgslFileStack::new(
+            MESH_OBJECT_WGSL.name,
+            MESH_OBJECT_WGSL.entry,
+            &[
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader_common.wgsl"),
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/vertex/mesh_object.wgsl"),
+            ],
+        )
+    }
+
+    /// Create the vertex stage from the shader on disk, see [`crate::fragment::PBRMaterial::from_wgsl_files`].
+    pub fn shader_from_wgsl_files(
+        device: &wgpu::Device,
+        files: &WgslFileStack,
+    ) -> Result<super::VertexCreaterShader, ShaderError> {
+        Ok(super::VertexCreaterShader::new(
+            files.create(device)?,
+            &files.entry,
+        ))
+    }
+
+    /// Points are drawn with this diameter in pixels unless specified otherwise.
+    pub const DEFAULT_POINT_SIZE: f32 = 2.0;
+
     pub const MESH_OBJECT_SET: u32 = 2;
     pub const MESH_OBJECT_UNIFORM_BINDING: u32 = 0;
     pub const MESH_OBJECT_INSTANCES_BINDING: u32 = 1;
@@ -214,6 +418,12 @@ impl MeshObject {
     pub const MESH_BINDING_COLOR: u32 = 3;
     pub const MESH_BINDING_UV: u32 = 4;
     pub const MESH_BINDING_TANGENT: u32 = 5;
+    pub const MESH_BINDING_UV1: u32 = 6;
+    pub const MESH_BINDING_JOINTS: u32 = 7;
+    pub const MESH_BINDING_WEIGHTS: u32 = 8;
+    pub const MESH_OBJECT_JOINT_MATRICES_BINDING: u32 = 9;
+    pub const MESH_BINDING_MORPH_TARGETS: u32 = 10;
+    pub const MESH_OBJECT_MORPH_WEIGHTS_BINDING: u32 = 11;
     pub const MESH_LAYOUT: wgpu::BindGroupLayoutDescriptor<'static> =
         wgpu::BindGroupLayoutDescriptor {
             label: Some("mesh_object_layout"),
@@ -280,6 +490,68 @@ impl MeshObject {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::MESH_BINDING_UV1,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Skinning data
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::MESH_BINDING_JOINTS,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::MESH_BINDING_WEIGHTS,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::MESH_OBJECT_JOINT_MATRICES_BINDING,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Morph targets
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::MESH_BINDING_MORPH_TARGETS,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: Self::MESH_OBJECT_MORPH_WEIGHTS_BINDING,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         };
 
@@ -301,6 +573,29 @@ crate::static_assert_size!(Mat4, 4 * 4 * 4);
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::vertex::mesh::MorphTargetDelta;
+
+    #[test]
+    fn test_wgsl_files() {
+        // The files on disk are the embedded ones.
+        let module = MeshObject::wgsl_files().to_module().unwrap();
+        assert!(module.entry_points.iter().any(|e| e.name == "main"));
+        crate::fragment::PBRMaterial::wgsl_files()
+            .to_module()
+            .unwrap();
+
+        let path = std::env::temp_dir().join("test_wgsl_files.wgsl");
+        std::fs::write(&path, "fn broken( {}").unwrap();
+        let broken = WgslFileStack::new("broken", "main", &[path.to_str().unwrap()]);
+        assert!(matches!(broken.to_module(), Err(ShaderError::Parse { .. })));
+        std::fs::write(&path, "fn f() -> f32 { return 1u; }").unwrap();
+        assert!(matches!(
+            broken.to_module(),
+            Err(ShaderError::Validation { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(broken.to_module(), Err(ShaderError::Io { .. })));
+    }
     #[test]
     fn test_mesh_object_struct_align() {
         let module = MESH_OBJECT_WGSL.to_module();
@@ -310,7 +605,13 @@ mod test {
             color_present,
             normal_present,
             uv_present,
-            tangent_present
+            tangent_present,
+            uv1_present,
+            skin_present,
+            vertex_count,
+            morph_target_count,
+            point_size
         );
+        crate::verify_wgsl_struct_sized!(MorphTargetDelta, module, position, normal, tangent);
     }
 }
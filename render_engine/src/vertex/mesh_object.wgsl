@@ -11,6 +11,12 @@ const MESH_OBJECT_BINDING_NORMAL: u32 = 2;
 const MESH_OBJECT_BINDING_COLOR: u32 = 3;
 const MESH_OBJECT_BINDING_UV: u32 = 4;
 const MESH_OBJECT_BINDING_TANGENT: u32 = 5;
+const MESH_OBJECT_BINDING_UV1: u32 = 6;
+const MESH_OBJECT_BINDING_JOINTS: u32 = 7;
+const MESH_OBJECT_BINDING_WEIGHTS: u32 = 8;
+const MESH_OBJECT_JOINT_MATRICES_BINDING: u32 = 9;
+const MESH_OBJECT_BINDING_MORPH_TARGETS: u32 = 10;
+const MESH_OBJECT_MORPH_WEIGHTS_BINDING: u32 = 11;
 
 
 struct MeshObjectMetaUniform {
@@ -18,6 +24,18 @@ struct MeshObjectMetaUniform {
     normal_present: u32,
     uv_present: u32,
     tangent_present: u32,
+    uv1_present: u32,
+    skin_present: u32,
+    vertex_count: u32,
+    morph_target_count: u32,
+    // Diameter in pixels for point meshes, zero for other topologies.
+    point_size: f32,
+};
+
+struct MorphTargetDelta {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    tangent: vec3<f32>,
 };
 
 @binding(MESH_OBJECT_UNIFORM_BINDING) @group(MESH_OBJECT_SET)
@@ -39,6 +57,25 @@ vertex_uv : array<vec2<f32>>;
 @binding(MESH_OBJECT_BINDING_TANGENT) @group(MESH_OBJECT_SET) var<storage, read>
 vertex_tangent : array<vec4<f32>>;
 
+@binding(MESH_OBJECT_BINDING_UV1) @group(MESH_OBJECT_SET) var<storage, read>
+vertex_uv1 : array<vec2<f32>>;
+
+@binding(MESH_OBJECT_BINDING_JOINTS) @group(MESH_OBJECT_SET) var<storage, read>
+vertex_joints : array<vec4<u32>>;
+
+@binding(MESH_OBJECT_BINDING_WEIGHTS) @group(MESH_OBJECT_SET) var<storage, read>
+vertex_weights : array<vec4<f32>>;
+
+@binding(MESH_OBJECT_JOINT_MATRICES_BINDING) @group(MESH_OBJECT_SET) var<storage, read>
+mesh_object_joint_matrices : array<mat4x4<f32>>;
+
+// Indexed by target * vertex_count + vertex.
+@binding(MESH_OBJECT_BINDING_MORPH_TARGETS) @group(MESH_OBJECT_SET) var<storage, read>
+vertex_morph_targets : array<MorphTargetDelta>;
+
+@binding(MESH_OBJECT_MORPH_WEIGHTS_BINDING) @group(MESH_OBJECT_SET) var<storage, read>
+mesh_object_morph_weights : array<f32>;
+
 
 @vertex
 fn main(in : VertexInput) ->  CommonVertexOutput {
@@ -51,11 +88,40 @@ fn main(in : VertexInput) ->  CommonVertexOutput {
     let view_proj = camera_uniform.view_proj;
     let camera_world_position = camera_uniform.camera_world_position;
 
-    // Obtain the model location in the world.
-    let model_matrix = mesh_object_instances[in.instanceID];
+    // Blend up to four joint matrices for skinned meshes, these are expressed in the frame of the instance.
+    var skin_matrix = mat4x4<f32>(
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    );
+    if (mesh_object_uniform.skin_present > 0) {
+        let joints = vertex_joints[in.vertexID];
+        let weights = vertex_weights[in.vertexID];
+        skin_matrix = weights.x * mesh_object_joint_matrices[joints.x]
+                    + weights.y * mesh_object_joint_matrices[joints.y]
+                    + weights.z * mesh_object_joint_matrices[joints.z]
+                    + weights.w * mesh_object_joint_matrices[joints.w];
+    }
+
+    // Obtain the model location in the world, the skin matrix is applied first such that the normal matrix below
+    // also takes the skinning into account.
+    let model_matrix = mesh_object_instances[in.instanceID] * skin_matrix;
+
+    // Blend the morph targets into the base attributes.
+    var position = in.position;
+    var normal_delta = vec3<f32>(0.0);
+    var tangent_delta = vec3<f32>(0.0);
+    for (var i = 0u; i < mesh_object_uniform.morph_target_count; i++) {
+        let weight = mesh_object_morph_weights[i];
+        let delta = vertex_morph_targets[i * mesh_object_uniform.vertex_count + in.vertexID];
+        position += weight * delta.position;
+        normal_delta += weight * delta.normal;
+        tangent_delta += weight * delta.tangent;
+    }
 
     // Transform the vertex from local frame to world frame.
-    let world_position =  (model_matrix * vec4<f32>(in.position, 1.0));
+    let world_position =  (model_matrix * vec4<f32>(position, 1.0));
 
     // This is gross, I thought we could avoid the matrix inverse on the model matrix, given that it is a homogenous matrix
     // that at worst has a scaled rotation matrix, but the scaling may be uniform, which makes it a lot harder to do an easy
@@ -65,25 +131,30 @@ fn main(in : VertexInput) ->  CommonVertexOutput {
     let normal_matrix = transpose(_naga_inverse_4x4_f32(model_matrix));
 
     // Set the color to default ot white.
-    out.color = vec3<f32>(1.0, 1.0, 1.0);
+    out.color = vec4<f32>(1.0, 1.0, 1.0, 1.0);
     if (mesh_object_uniform.color_present > 0) {
-        out.color = vertex_color[in.vertexID].rgb;
+        out.color = vertex_color[in.vertexID];
     }
 
-    // Retrieve the normal, and rotate it from local frame to world frame.
+    // Retrieve the normal, and rotate it from local frame to world frame; as a direction it is not translated.
     if (mesh_object_uniform.normal_present > 0) {
-        let normal = vertex_normal[in.vertexID];
-        out.normal =  (model_matrix * vec4<f32>(normal, 1.0)).xyz;
+        let normal = vertex_normal[in.vertexID] + normal_delta;
+        out.normal =  (normal_matrix * vec4<f32>(normal, 0.0)).xyz;
     }
     // Retrieve the uv map.
     if (mesh_object_uniform.uv_present > 0) {
         out.uv_pos = vertex_uv[in.vertexID];
     }
+    // And the optional second uv map.
+    if (mesh_object_uniform.uv1_present > 0) {
+        out.uv1_pos = vertex_uv1[in.vertexID];
+    }
 
 
     if (mesh_object_uniform.tangent_present > 0) {
-        let tangent = normalize(vertex_tangent[in.vertexID]);
-        let normal = normalize(vertex_normal[in.vertexID]);
+        let raw_tangent = vertex_tangent[in.vertexID];
+        let tangent = normalize(vec4f(raw_tangent.xyz + tangent_delta, raw_tangent.w));
+        let normal = normalize(vertex_normal[in.vertexID] + normal_delta);
 
         // This follows https://github.com/KhronosGroup/glTF-Sample-Renderer/blob/e6b052db89fb2adbaf31da4565a08265c96c2b9f/source/Renderer/shaders/primitive.vert#L135-L148
         out.tangent_w = (model_matrix * vec4f(tangent.xyz, 0.0)).xyz;
@@ -94,6 +165,18 @@ fn main(in : VertexInput) ->  CommonVertexOutput {
 
     // Assign the clip position and other remainders to the output.
     out.clip_position = (view_proj * world_position);
+
+    // Points are uploaded as quads of four vertices at the same position, offset the corners in screen space.
+    if (mesh_object_uniform.point_size > 0.0) {
+        let corner = vec2<f32>(f32(in.vertexID & 1u), f32((in.vertexID >> 1u) & 1u)) * 2.0 - 1.0;
+        let viewport_size = max(camera_uniform.viewport_size, vec2<f32>(1.0));
+        let offset = corner * mesh_object_uniform.point_size / viewport_size * out.clip_position.w;
+        out.clip_position += vec4<f32>(offset, 0.0, 0.0);
+    }
+    // Without normals, like for points and lines, the vertex faces the camera; a zero normal can't be normalized.
+    if (mesh_object_uniform.normal_present == 0) {
+        out.normal = camera_world_position - world_position.xyz;
+    }
     out.view_vector = camera_world_position - world_position.xyz;
     out.world_pos = world_position.xyz;
     return out;
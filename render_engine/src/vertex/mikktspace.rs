@@ -8,16 +8,23 @@
 //
 // This file provides an implementation of that trait for my Mesh struct, such that we can have tangents.
 
-use glam::vec4;
+use super::mesh::CpuMesh;
+use glam::{Vec4, vec4};
 
 /// Map from the split face/vert situation to the index, this is the index into the indices.
 fn face_vert_to_index(face: usize, vert: usize) -> usize {
     face * 3 + vert
 }
 
-impl bevy_mikktspace::Geometry for super::mesh::CpuMesh {
+/// Collects the tangent of every face corner, vertices are shared between faces and may need different tangents.
+struct CornerTangents<'a> {
+    mesh: &'a CpuMesh,
+    tangents: Vec<Vec4>,
+}
+
+impl bevy_mikktspace::Geometry for CornerTangents<'_> {
     fn num_faces(&self) -> usize {
-        self.index.len() / 3
+        self.mesh.index.len() / 3
     }
 
     fn num_vertices_of_face(&self, face: usize) -> usize {
@@ -27,31 +34,108 @@ impl bevy_mikktspace::Geometry for super::mesh::CpuMesh {
 
     fn position(&self, face: usize, vert: usize) -> [f32; 3] {
         let index = face_vert_to_index(face, vert);
-        let vertex_index = self.index[index] as usize;
-        self.position[vertex_index].into()
+        let vertex_index = self.mesh.index[index] as usize;
+        self.mesh.position[vertex_index].into()
     }
 
     fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
         let index = face_vert_to_index(face, vert);
-        let vertex_index = self.index[index] as usize;
-        self.normal.as_ref().unwrap()[vertex_index].into()
+        let vertex_index = self.mesh.index[index] as usize;
+        self.mesh.normal.as_ref().unwrap()[vertex_index].into()
     }
 
     fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
         let index = face_vert_to_index(face, vert);
-        let vertex_index = self.index[index] as usize;
-        self.uv.as_ref().unwrap()[vertex_index].into()
+        let vertex_index = self.mesh.index[index] as usize;
+        self.mesh.uv.as_ref().unwrap()[vertex_index].into()
     }
 
     fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
         let index = face_vert_to_index(face, vert);
-        let vertex_index = self.index[index] as usize;
 
         // https://github.com/bevyengine/bevy/blob/98a5aa50c3f0ba3af72920b994387790230f43d9/crates/bevy_mesh/src/mikktspace.rs#L126-L129
         // Bevy does; tangent[3] = -tangent[3];
         // to ensure its in a RH coordinate system, so lets do the same.
-        let tangent = vec4(tangent[0], tangent[1], tangent[2], -tangent[3]);
+        self.tangents[index] = vec4(tangent[0], tangent[1], tangent[2], -tangent[3]);
+    }
+}
+
+/// Generate the tangents, requires normals and uvs. Vertices whose corners get different tangents, like those on the
+/// seam of a mirrored uv island, are split such that every corner keeps its own tangent.
+pub fn generate_tangents(mesh: &mut CpuMesh) -> bool {
+    let mut corners = CornerTangents {
+        mesh,
+        tangents: vec![Vec4::ZERO; mesh.index.len()],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut corners) {
+        return false;
+    }
+    let corner_tangents = corners.tangents;
+
+    mesh.tangents = None;
+    let mut tangents: Vec<Option<Vec4>> = vec![None; mesh.position.len()];
+    // The copies made so far, keyed by the original vertex and the bits of the tangent.
+    let mut splits: std::collections::HashMap<(u32, [u32; 4]), u32> = Default::default();
+    for (corner, tangent) in corner_tangents.into_iter().enumerate() {
+        let vertex = mesh.index[corner];
+        match tangents[vertex as usize] {
+            None => tangents[vertex as usize] = Some(tangent),
+            Some(existing) if existing == tangent => {}
+            Some(_) => {
+                let key = (vertex, tangent.to_array().map(f32::to_bits));
+                let split = *splits.entry(key).or_insert_with(|| {
+                    tangents.push(Some(tangent));
+                    mesh.duplicate_vertex(vertex as usize)
+                });
+                mesh.index[corner] = split;
+            }
+        }
+    }
+    mesh.tangents = Some(
+        tangents
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect(),
+    );
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use glam::{Vec2, Vec3A, vec2, vec3};
+
+    #[test]
+    fn test_tangent_seam_split() {
+        // Two triangles sharing the edge between vertex 0 and 2, the uvs of the second are mirrored.
+        let mut mesh = CpuMesh::new(
+            vec![
+                vec3(0.0, 0.0, 0.0),
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(-1.0, 0.0, 0.0),
+            ],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        mesh.normal = Some(vec![Vec3A::Z; 4]);
+        let uv: Vec<Vec2> = vec![
+            vec2(0.0, 0.0),
+            vec2(1.0, 0.0),
+            vec2(0.0, 1.0),
+            vec2(1.0, 0.0),
+        ];
+        mesh.uv = Some(uv);
+        assert!(mesh.calculate_tangents());
 
-        self.tangents.as_mut().unwrap()[vertex_index] = tangent;
+        // The shared vertices are split, and every corner has the tangent of its own triangle.
+        assert_eq!(mesh.position.len(), 6);
+        let tangents = mesh.tangents.as_ref().unwrap();
+        assert_eq!(tangents.len(), 6);
+        for corner in 0..3 {
+            assert!(tangents[mesh.index[corner] as usize].x > 0.5);
+        }
+        for corner in 3..6 {
+            assert!(tangents[mesh.index[corner] as usize].x < -0.5);
+        }
     }
 }
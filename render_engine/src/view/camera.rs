@@ -1,4 +1,17 @@
+use crate::axes::{AxisConversion, CoordinateSystem};
 use glam::{Mat4, Vec3};
+
+/// How the camera projects the view onto the screen.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Projection {
+    /// Perspective projection using `fovy`.
+    #[default]
+    Perspective,
+    /// Orthographic projection, `ymag` is half the vertical extent of the view, the horizontal extent follows from
+    /// the aspect ratio.
+    Orthographic { ymag: f32 },
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Camera {
     /// Location of the camera position.
@@ -19,8 +32,11 @@ pub struct Camera {
     /// Depth near for the perspective transform.
     pub znear: f32,
 
-    /// depth far for the perspective transform.
+    /// depth far for the perspective transform, may be infinite for perspective projections.
     pub zfar: f32,
+
+    /// The projection to use.
+    pub projection: Projection,
 }
 impl Camera {
     pub fn new(width: u32, height: u32) -> Self {
@@ -40,14 +56,31 @@ impl Camera {
             fovy: 45.0,
             znear: 0.001,
             zfar: 1000.0,
+            projection: Projection::Perspective,
         }
     }
+    /// Place the default camera in a world with this convention, [`Camera::new`] assumes the glTF convention.
+    pub fn with_coordinate_system(self, world: CoordinateSystem) -> Self {
+        AxisConversion::new(CoordinateSystem::GLTF, world).convert_camera(&self)
+    }
+
     pub fn to_view_projection_matrix(&self) -> Mat4 {
         // https://github.com/bitshifter/glam-rs/issues/569
         // Okay, so this doesn't actually do what we need :<
         //let view = Mat4::look_at_rh(self.eye, self.target, self.up);
         // info!("self: {:?}", self);
-        let proj = Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.znear, self.zfar);
+        let proj = match self.projection {
+            Projection::Perspective if self.zfar.is_infinite() => {
+                Mat4::perspective_infinite_rh(self.fovy.to_radians(), self.aspect, self.znear)
+            }
+            Projection::Perspective => {
+                Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic { ymag } => {
+                let xmag = ymag * self.aspect;
+                Mat4::orthographic_rh(-xmag, xmag, -ymag, ymag, self.znear, self.zfar)
+            }
+        };
         let view = Mat4::look_at_rh(self.eye, self.target, self.up);
         // view.col_mut(0)[1] *= -1.0;
         return proj * view;
@@ -65,6 +98,8 @@ impl super::CameraView for Camera {
                 view_proj,
                 camera_world_position,
                 _pad: Default::default(),
+                viewport_size: Default::default(),
+                _pad1: Default::default(),
             }
         }
     }
@@ -1,6 +1,6 @@
 pub mod camera;
 pub mod orbit;
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
 use wgpu::util::DeviceExt as _;
 use zerocopy::{Immutable, IntoBytes};
 
@@ -10,10 +10,19 @@ pub struct ViewUniform {
     pub view_proj: Mat4,
     pub camera_world_position: Vec3,
     pub _pad: u32,
+    /// Size of the render target in pixels, for sizes that are specified in screen space like the point size.
+    pub viewport_size: Vec2,
+    pub _pad1: [u32; 2],
 }
 
 impl ViewUniform {
     pub const VIEW_UNIFORM_SET: u32 = 0;
+
+    pub fn with_viewport_size(mut self, width: u32, height: u32) -> Self {
+        self.viewport_size = Vec2::new(width as f32, height as f32);
+        self
+    }
+
     // pub const VIEW_UNIFORM_BINDING: u32 = 0;
     pub const fn bind_group_layout() -> wgpu::BindGroupLayoutDescriptor<'static> {
         wgpu::BindGroupLayoutDescriptor {
@@ -60,6 +69,12 @@ mod test {
     #[test]
     fn test_view_uniform_struct_align() {
         let module = naga::front::wgsl::parse_str(include_str!("../shader_common.wgsl")).unwrap();
-        crate::verify_wgsl_struct_sized!(ViewUniform, module, view_proj, camera_world_position);
+        crate::verify_wgsl_struct_sized!(
+            ViewUniform,
+            module,
+            view_proj,
+            camera_world_position,
+            viewport_size
+        );
     }
 }
@@ -1,6 +1,7 @@
 use glam::{Mat4, vec3};
 
 use super::camera::Camera;
+use crate::axes::CoordinateSystem;
 
 #[derive(Copy, Clone, Debug)]
 pub struct OrbitCamera {
@@ -25,6 +26,11 @@ impl OrbitCamera {
             amount_down: 0.0,
         }
     }
+    /// Orbit around the up direction of this convention, see [`Camera::with_coordinate_system`].
+    pub fn with_coordinate_system(mut self, world: CoordinateSystem) -> Self {
+        self.camera = self.camera.with_coordinate_system(world);
+        self
+    }
     pub fn update(&mut self) {
         const CARTESIAN: bool = false;
         if CARTESIAN {
@@ -44,13 +50,9 @@ impl OrbitCamera {
         // something something, polar coordinates.
         let target_to_camera = self.camera.eye - self.camera.target;
 
-        // Ideally, we'd use the 'up' vector here to determine the rotation.
-
-        // Express the delta into the up frame.
-        let camera_rotate_about_axis = vec3(0.0, 0.0, 1.0);
-        let rotation = glam::Quat::from_rotation_arc(camera_rotate_about_axis, self.camera.up);
-
-        // Go from left hand to right hand...
+        // Express the offset in a frame where the up direction of the camera is +z, such that we orbit around it.
+        let rotation =
+            glam::Quat::from_rotation_arc(self.camera.up.normalize(), vec3(0.0, 0.0, 1.0));
         let camera_local_frame = rotation.mul_vec3(target_to_camera);
 
         let x = camera_local_frame[0];
@@ -63,9 +65,9 @@ impl OrbitCamera {
         let mut phi = y.atan2(x);
         let mut rho = magnitude;
 
-        // Perform the changes.
-        theta += delta_vertical;
-        phi += delta_horizontal;
+        // Perform the changes, theta is measured from up, so moving up decreases it.
+        theta -= delta_vertical;
+        phi -= delta_horizontal;
         rho += delta_distance * target_to_camera.length().max(0.01);
 
         // Back to cartesian
@@ -0,0 +1,93 @@
+// Noticing changes to files on disk, for reloading assets and shaders while the application runs.
+//
+// This polls the modification times, which is cheap enough to do every frame and needs no platform specific
+// notification mechanism.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Watches a set of files for changes to their modification time.
+#[derive(Debug, Clone)]
+pub struct FileWatcher {
+    /// The files with the modification time they had when they were last checked, none if they didn't exist.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    /// The minimum time between checks.
+    interval: Duration,
+    last_check: Instant,
+}
+
+impl FileWatcher {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Start watching the files, changes made before this are not reported.
+    pub fn new<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            files: paths
+                .into_iter()
+                .map(|p| (p.as_ref().to_owned(), modified(p.as_ref())))
+                .collect(),
+            interval: Self::DEFAULT_INTERVAL,
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.files.iter().map(|(p, _)| p.as_path())
+    }
+
+    /// Whether any of the files was modified, created or removed since the previous check; files are checked at most
+    /// once per interval so this can be called every frame.
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < self.interval {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let mut changed = false;
+        for (path, last_modified) in self.files.iter_mut() {
+            let current = modified(path);
+            if current != *last_modified {
+                *last_modified = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_watcher() {
+        let path = std::env::temp_dir().join("test_file_watcher.txt");
+        std::fs::write(&path, "a").unwrap();
+        let mut watcher = FileWatcher::new([&path]).with_interval(Duration::ZERO);
+        assert!(!watcher.changed());
+
+        // Set the time explicitly, the resolution of modification times varies.
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+
+        // Checks are skipped within the interval.
+        std::fs::write(&path, "b").unwrap();
+        let mut watcher = watcher.with_interval(Duration::from_secs(3600));
+        assert!(!watcher.changed());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -22,6 +22,105 @@ impl StaticWgslStack {
     }
 }
 
+/// Errors while creating a shader from sources on disk.
+#[derive(thiserror::Error, Debug)]
+pub enum ShaderError {
+    #[error("failed to read {path:?}: {source}")]
+    Io {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse {name}: {message}")]
+    Parse { name: String, message: String },
+    #[error("failed to validate {name}: {message}")]
+    Validation { name: String, message: String },
+    #[error("{name} was rejected by the device: {source}")]
+    Device { name: String, source: wgpu::Error },
+}
+
+/// The counterpart of [`StaticWgslStack`] that reads the sources from disk, to pick up edits without rebuilding.
+#[derive(Debug, Clone)]
+pub struct WgslFileStack {
+    pub name: String,
+    pub entry: String,
+    pub paths: Vec<std::path::PathBuf>,
+}
+impl WgslFileStack {
+    pub fn new(name: &str, entry: &str, paths: &[&str]) -> Self {
+        Self {
+            name: name.to_owned(),
+            entry: entry.to_owned(),
+            paths: paths.iter().map(std::path::PathBuf::from).collect(),
+        }
+    }
+
+    /// The concatenated sources.
+    pub fn read(&self) -> Result<String, ShaderError> {
+        let mut combined_string = String::new();
+        for path in self.paths.iter() {
+            let source = std::fs::read_to_string(path).map_err(|source| ShaderError::Io {
+                path: path.clone(),
+                source,
+            })?;
+            combined_string.push_str(&source);
+        }
+        Ok(combined_string)
+    }
+
+    /// Parse and validate the sources, the errors quote the offending lines of the concatenated sources.
+    pub fn to_module(&self) -> Result<wgpu::naga::Module, ShaderError> {
+        self.validate(&self.read()?)
+    }
+
+    fn validate(&self, combined_string: &str) -> Result<wgpu::naga::Module, ShaderError> {
+        let module =
+            naga::front::wgsl::parse_str(combined_string).map_err(|e| ShaderError::Parse {
+                name: self.name.clone(),
+                message: e.emit_to_string(combined_string),
+            })?;
+        // The device checks the capabilities, this only verifies the shader is consistent.
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|e| ShaderError::Validation {
+            name: self.name.clone(),
+            message: e.emit_to_string(combined_string),
+        })?;
+        Ok(module)
+    }
+
+    /// Create the shader module, errors are returned instead of being raised on the device.
+    pub fn create(&self, device: &wgpu::Device) -> Result<wgpu::ShaderModule, ShaderError> {
+        let combined_string = self.read()?;
+        self.validate(&combined_string)?;
+        capture_validation_errors(device, || {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&self.name),
+                source: wgpu::ShaderSource::Wgsl(combined_string.as_str().into()),
+            })
+        })
+        .map_err(|source| ShaderError::Device {
+            name: self.name.clone(),
+            source,
+        })
+    }
+}
+
+/// Run `f` and return the validation error it raised on the device, which would be fatal otherwise.
+pub fn capture_validation_errors<T>(
+    device: &wgpu::Device,
+    f: impl FnOnce() -> T,
+) -> Result<T, wgpu::Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = f();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error),
+        None => Ok(value),
+    }
+}
+
 #[macro_export]
 macro_rules! verify_field {
     ($Container:ty, $field:expr, $members:expr) => {
@@ -91,6 +190,83 @@ impl<'a> std::convert::From<&'a BindGroupLayoutDescriptorOwned>
     }
 }
 
+/// Copy a buffer into a mappable one and wait for its contents, the buffer must have the copy source usage.
+pub fn read_buffer(context: &crate::Context, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let staging = context.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("read_buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = context
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    context.queue.submit(Some(encoder.finish()));
+    map_read(context, &staging)
+}
+
+/// Read the first mip level of a texture with 4 bytes per texel, like rgba8, the texture must have the copy source
+/// usage. The rows are returned without the padding of the copy.
+pub fn read_texture(context: &crate::Context, texture: &wgpu::Texture) -> Vec<u8> {
+    let width = texture.width();
+    let height = texture.height();
+    let row_size = 4 * width;
+    let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let staging = context.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("read_texture"),
+        size: (padded_row_size * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = context
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &staging,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_size),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    context.queue.submit(Some(encoder.finish()));
+    map_read(context, &staging)
+        .chunks(padded_row_size as usize)
+        .flat_map(|row| &row[..row_size as usize])
+        .copied()
+        .collect()
+}
+
+fn map_read(context: &crate::Context, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let slice = buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        tx.send(result).unwrap();
+    });
+    context
+        .device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .unwrap();
+    rx.recv().unwrap().unwrap();
+    let data = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    data
+}
+
 /*
 #[derive(Debug, Clone)]
 pub struct PipelineLayoutDescriptorOwned {